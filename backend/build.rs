@@ -0,0 +1,51 @@
+//! Embeds what `/system/info` and `/system/ready` report about the build: the git commit and
+//! the migrations the schema expects.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rustc-env=FLASHQC_GIT_SHA={}", git_sha());
+
+    let mut migrations: Vec<String> = fs::read_dir("migrations")
+        .expect("cannot read the migrations folder")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("up.sql").is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    migrations.sort();
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let listed: Vec<String> = migrations.iter().map(|name| format!("{name:?}")).collect();
+    fs::write(
+        Path::new(&out_dir).join("migrations.rs"),
+        format!("&[{}]", listed.join(", ")),
+    )
+    .expect("cannot write the list of migrations");
+}
+
+/// `GIT_SHA` when set, e.g. by a Docker build without the repository, or the checked out commit
+fn git_sha() -> String {
+    if let Ok(sha) = env::var("GIT_SHA") {
+        return sha;
+    }
+    // The checked out commit moves with HEAD, or with the branch HEAD points to
+    if let Ok(head) = fs::read_to_string("../.git/HEAD") {
+        println!("cargo:rerun-if-changed=../.git/HEAD");
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=../.git/{reference}");
+        }
+    }
+    Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
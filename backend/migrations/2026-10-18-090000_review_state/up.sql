@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS review_state (
+    user_id INTEGER NOT NULL REFERENCES flashqc_user (id) ON DELETE CASCADE,
+    card_id INTEGER NOT NULL REFERENCES card (id) ON DELETE CASCADE,
+    ease_factor DOUBLE PRECISION NOT NULL DEFAULT 2.5,
+    interval_days INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    lapses INTEGER NOT NULL DEFAULT 0,
+    due_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_reviewed_at TIMESTAMPTZ,
+    PRIMARY KEY (user_id, card_id)
+);
+
+CREATE INDEX IF NOT EXISTS review_state_user_due_idx ON review_state (
+    user_id, due_at
+);
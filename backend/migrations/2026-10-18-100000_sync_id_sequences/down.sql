@@ -0,0 +1,2 @@
+-- Sequences cannot be moved back safely once new rows have been inserted
+SELECT 1;
@@ -0,0 +1,10 @@
+-- Mock data was inserted with explicit ids, which does not advance the SERIAL
+-- sequences: move them past the existing rows so new inserts get fresh ids
+SELECT SETVAL(PG_GET_SERIAL_SEQUENCE('deck', 'id'), COALESCE(MAX(id), 0) + 1, FALSE)
+FROM deck;
+SELECT SETVAL(PG_GET_SERIAL_SEQUENCE('plugin', 'id'), COALESCE(MAX(id), 0) + 1, FALSE)
+FROM plugin;
+SELECT SETVAL(PG_GET_SERIAL_SEQUENCE('card', 'id'), COALESCE(MAX(id), 0) + 1, FALSE)
+FROM card;
+SELECT SETVAL(PG_GET_SERIAL_SEQUENCE('flashqc_user', 'id'), COALESCE(MAX(id), 0) + 1, FALSE)
+FROM flashqc_user;
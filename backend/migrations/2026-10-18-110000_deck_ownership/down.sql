@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS deck_owner_idx;
+ALTER TABLE deck DROP COLUMN is_public;
+ALTER TABLE deck DROP COLUMN owner_id;
@@ -0,0 +1,17 @@
+ALTER TABLE deck ADD COLUMN owner_id INTEGER REFERENCES flashqc_user (id);
+ALTER TABLE deck ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Decks created before ownership existed go to the first user, and stay visible to everyone.
+-- Without any user yet they go to a system account, which cannot log in since its password
+-- hash is not a valid hash.
+INSERT INTO flashqc_user (username, hashed_password, email)
+SELECT 'system', '!', 'system@localhost'
+WHERE EXISTS (SELECT 1 FROM deck) AND NOT EXISTS (SELECT 1 FROM flashqc_user);
+
+UPDATE deck SET
+    owner_id = (SELECT MIN(id) FROM flashqc_user),
+    is_public = TRUE;
+
+ALTER TABLE deck ALTER COLUMN owner_id SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS deck_owner_idx ON deck (owner_id);
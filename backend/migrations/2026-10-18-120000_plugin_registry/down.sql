@@ -0,0 +1,2 @@
+ALTER TABLE plugin DROP CONSTRAINT plugin_name_key;
+ALTER TABLE plugin DROP COLUMN version;
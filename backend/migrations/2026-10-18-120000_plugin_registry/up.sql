@@ -0,0 +1,3 @@
+-- Plugins are now upserted by name at startup from their plugin.json manifest
+ALTER TABLE plugin ADD COLUMN version TEXT NOT NULL DEFAULT '0.0.0';
+ALTER TABLE plugin ADD CONSTRAINT plugin_name_key UNIQUE (name);
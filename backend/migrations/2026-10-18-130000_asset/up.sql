@@ -0,0 +1,9 @@
+-- Media files (images, sounds...) referenced by the cards of a deck
+CREATE TABLE IF NOT EXISTS asset (
+    id SERIAL PRIMARY KEY,
+    deck_id INTEGER NOT NULL REFERENCES deck (id) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    data BYTEA NOT NULL,
+    UNIQUE (deck_id, filename)
+);
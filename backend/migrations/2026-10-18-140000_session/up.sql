@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS session (
+    id SERIAL PRIMARY KEY,
+    -- SHA-256 of the bearer token, the token itself is only known by the client
+    token_hash TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL REFERENCES flashqc_user (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS session_user_idx ON session (user_id);
+CREATE INDEX IF NOT EXISTS session_expires_idx ON session (expires_at);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS card_deck_plugin_idx;
+DROP INDEX IF EXISTS deck_name_idx;
+
+ALTER TABLE deck DROP COLUMN IF EXISTS created_at;
@@ -0,0 +1,4 @@
+ALTER TABLE deck ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS deck_name_idx ON deck (LOWER(name));
+CREATE INDEX IF NOT EXISTS card_deck_plugin_idx ON card (deck_id, plugin_name);
@@ -0,0 +1,7 @@
+DROP TRIGGER IF EXISTS card_search_vector_trigger ON card;
+DROP FUNCTION IF EXISTS card_update_search_vector;
+DROP FUNCTION IF EXISTS card_search_vector;
+DROP INDEX IF EXISTS card_search_idx;
+
+ALTER TABLE card DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE plugin DROP COLUMN IF EXISTS searchable_fields;
@@ -0,0 +1,29 @@
+-- Top-level string fields of plugin_data that are indexed, from the plugin manifest
+ALTER TABLE plugin ADD COLUMN IF NOT EXISTS searchable_fields TEXT [] NOT NULL DEFAULT '{}';
+
+ALTER TABLE card ADD COLUMN IF NOT EXISTS search_vector TSVECTOR NOT NULL DEFAULT ''::TSVECTOR;
+
+CREATE INDEX IF NOT EXISTS card_search_idx ON card USING GIN (search_vector);
+
+-- The 'simple' configuration does not stem, decks are written in many languages
+CREATE OR REPLACE FUNCTION card_search_vector(
+    plugin_data JSONB, fields TEXT []
+) RETURNS TSVECTOR AS $$
+    SELECT TO_TSVECTOR('simple', COALESCE(STRING_AGG(value, ' '), ''))
+    FROM JSONB_EACH_TEXT(plugin_data)
+    WHERE key = ANY(fields)
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION card_update_search_vector() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.search_vector := card_search_vector(
+        NEW.plugin_data,
+        (SELECT searchable_fields FROM plugin WHERE id = NEW.plugin_id)
+    );
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER card_search_vector_trigger
+BEFORE INSERT OR UPDATE OF plugin_data, plugin_id ON card
+FOR EACH ROW EXECUTE FUNCTION card_update_search_vector();
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS card_tag;
+DROP TABLE IF EXISTS tag;
+
+DROP INDEX IF EXISTS deck_parent_idx;
+
+ALTER TABLE deck DROP COLUMN IF EXISTS parent_id;
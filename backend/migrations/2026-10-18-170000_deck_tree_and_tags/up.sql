@@ -0,0 +1,16 @@
+ALTER TABLE deck ADD COLUMN IF NOT EXISTS parent_id INTEGER REFERENCES deck (id);
+
+CREATE INDEX IF NOT EXISTS deck_parent_idx ON deck (parent_id);
+
+CREATE TABLE IF NOT EXISTS tag (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS card_tag (
+    card_id INTEGER NOT NULL REFERENCES card (id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    PRIMARY KEY (card_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS card_tag_tag_idx ON card_tag (tag_id);
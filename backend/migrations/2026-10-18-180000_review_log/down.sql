@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS review_log;
+DROP FUNCTION IF EXISTS review_log_append_only;
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS review_log (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES flashqc_user (id) ON DELETE CASCADE,
+    card_id INTEGER NOT NULL REFERENCES card (id) ON DELETE CASCADE,
+    grade TEXT NOT NULL,
+    reason TEXT,
+    duration_ms INTEGER,
+    score INTEGER,
+    reviewed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS review_log_user_reviewed_idx ON review_log (
+    user_id, reviewed_at
+);
+CREATE INDEX IF NOT EXISTS review_log_card_idx ON review_log (card_id);
+
+-- The log is append-only: rows only go away along with their user or card
+CREATE OR REPLACE FUNCTION review_log_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'review_log is append-only';
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER review_log_append_only_trigger
+BEFORE UPDATE ON review_log
+FOR EACH ROW EXECUTE FUNCTION review_log_append_only();
@@ -1,17 +1,19 @@
+use crate::plugin::PluginHosting;
 use crate::schema::flashqc_user;
-use crate::utils::db;
-use crate::utils::gen_error::GenericError;
-use crate::utils::token_store::TokenStore;
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
+use crate::utils::token_store::{IssuedToken, TokenStore};
 use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHasher};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use base64::Engine;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
-use rocket::serde::json::{Value, json};
+use rocket::serde::json::Json;
 use rocket::{Route, State, post, routes as rocket_routes};
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Deserialize, Queryable, Insertable, Serialize)]
 #[diesel(table_name = flashqc_user)]
@@ -25,7 +27,34 @@ pub struct User {
 
 pub struct Auth {
     pub user: User,
-    pub token: String,
+    pub token: IssuedToken,
+}
+
+/// Fields of a new row in `flashqc_user`, the id comes from the sequence
+#[derive(Insertable)]
+#[diesel(table_name = flashqc_user)]
+struct InsertableUser {
+    username: String,
+    hashed_password: String,
+    email: String,
+}
+
+/// What the API tells about a user, never the password hash
+#[derive(Debug, Serialize)]
+pub struct UserProfile {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        UserProfile {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+        }
+    }
 }
 
 fn get_user_with_pass(
@@ -33,30 +62,47 @@ fn get_user_with_pass(
     username: &str,
     password: &str,
 ) -> Result<User, diesel::result::Error> {
-    let hashed_password = hash_password(password);
-    println!(
-        "Attempting to authenticate user: {}, with hashed password: {}",
-        username, hashed_password
-    );
+    let user: User = flashqc_user::table
+        .select(flashqc_user::all_columns)
+        .filter(flashqc_user::username.eq(username))
+        .first(&mut *db_connection)?;
+    if verify_password(password, &user.hashed_password) {
+        Ok(user)
+    } else {
+        Err(diesel::result::Error::NotFound)
+    }
+}
+
+pub fn get_user_by_username(
+    db_connection: &mut diesel::pg::PgConnection,
+    username: &str,
+) -> Result<Option<User>, diesel::result::Error> {
     flashqc_user::table
         .select(flashqc_user::all_columns)
         .filter(flashqc_user::username.eq(username))
-        .filter(flashqc_user::hashed_password.eq(hashed_password))
         .first(&mut *db_connection)
+        .optional()
+}
+
+/// Plugin pages run code the user did not write, they never get to act on behalf of the user
+fn refuse_plugin_request<T>(req: &Request<'_>) -> Option<Outcome<T, ApiError>> {
+    let hosting = req.rocket().state::<PluginHosting>()?;
+    hosting.is_plugin_request(req).then(|| {
+        ApiError::Forbidden("Plugins cannot use the credentials of the user".to_string()).fail(req)
+    })
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Auth {
-    type Error = GenericError;
+    type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let unauthorized_response = Outcome::Error((
-            Status::Unauthorized,
-            GenericError::Custom("Unauthorized".into()),
-        ));
-        // why do we have an await here?
-        let store = req.guard::<&State<Arc<Mutex<TokenStore>>>>().await.unwrap();
-        let mut store = store.lock().unwrap();
+        let unauthorized_response =
+            || ApiError::Unauthorized("Missing or invalid credentials".to_string()).fail(req);
+        if let Some(refused) = refuse_plugin_request(req) {
+            return refused;
+        }
+        let store = req.guard::<&State<TokenStore>>().await.unwrap();
 
         match req.headers().get_one("Authorization") {
             Some(header) if header.starts_with("Basic ") => {
@@ -66,31 +112,48 @@ impl<'r> FromRequest<'r> for Auth {
                 // TODO: Solve all of these unwraps
                 let value_decoded = engine.decode(username_password);
                 if value_decoded.is_err() {
-                    return unauthorized_response;
+                    return unauthorized_response();
                 }
                 let value_decoded = value_decoded.unwrap();
                 let credentials = String::from_utf8(value_decoded);
                 if credentials.is_err() {
-                    return unauthorized_response;
+                    return unauthorized_response();
                 }
                 let credentials = credentials.unwrap();
                 let parts: Vec<&str> = credentials.splitn(2, ':').collect();
-                println!("Username and password parts: {:?}", parts);
                 if parts.len() != 2 {
-                    return unauthorized_response;
+                    return unauthorized_response();
                 }
-                let (username, password) = (parts[0], parts[1]);
-                let mut db_connection = db::get_connection().lock().unwrap();
-                let user_obj = get_user_with_pass(&mut db_connection, username, password);
-                if user_obj.is_err() {
-                    return unauthorized_response;
+                let (username, password) = (parts[0].to_string(), parts[1].to_string());
+                let db_connection = match req.guard::<DbConn>().await {
+                    Outcome::Success(db_connection) => db_connection,
+                    Outcome::Error(e) => return Outcome::Error(e),
+                    Outcome::Forward(status) => return Outcome::Forward(status),
+                };
+                let store = store.inner().clone();
+                let user_agent = req.headers().get_one("User-Agent").map(String::from);
+                let login = db_connection
+                    .run(move |db_connection| -> QueryResult<Option<Auth>> {
+                        let Ok(user) = get_user_with_pass(db_connection, &username, &password)
+                        else {
+                            return Ok(None);
+                        };
+                        let token =
+                            store.generate_token(db_connection, &user, user_agent.as_deref())?;
+                        Ok(Some(Auth { user, token }))
+                    })
+                    .await;
+                match login {
+                    Ok(Some(auth)) => Outcome::Success(auth),
+                    Ok(None) => unauthorized_response(),
+                    Err(e) => {
+                        eprintln!("Database error creating session: {:?}", e);
+                        ApiError::Internal("Failed to create session".to_string()).fail(req)
+                    }
                 }
-                let user = user_obj.unwrap();
-                let token = store.generate_token(user.clone());
-                Outcome::Success(Auth { user, token })
             }
-            Some(_) => unauthorized_response, // empty
-            None => unauthorized_response,
+            Some(_) => unauthorized_response(), // empty
+            None => unauthorized_response(),
         }
     }
 }
@@ -98,55 +161,265 @@ impl<'r> FromRequest<'r> for Auth {
 /// After basic auth, we use bearer auth for subsequent requests
 pub struct BearerAuth {
     pub user: User,
+    pub token: String,
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for BearerAuth {
-    type Error = GenericError;
+    type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let unauthorized_response = Outcome::Error((
-            Status::Unauthorized,
-            GenericError::Custom("Unauthorized".into()),
-        ));
+        let unauthorized_response =
+            || ApiError::Unauthorized("Missing, invalid or expired token".to_string()).fail(req);
+        if let Some(refused) = refuse_plugin_request(req) {
+            return refused;
+        }
 
-        let store = req.guard::<&State<Arc<Mutex<TokenStore>>>>().await.unwrap();
-        let store = store.lock().unwrap();
+        let store = req.guard::<&State<TokenStore>>().await.unwrap();
 
         match req.headers().get_one("Authorization") {
             Some(header) if header.starts_with("Bearer ") => {
-                let token = &header[7..];
-                if !store.is_valid(token) {
-                    return unauthorized_response;
-                } else {
-                    let user = store.get_user(token).unwrap().clone();
-                    return Outcome::Success(BearerAuth { user });
+                let token = header[7..].to_string();
+                let db_connection = match req.guard::<DbConn>().await {
+                    Outcome::Success(db_connection) => db_connection,
+                    Outcome::Error(e) => return Outcome::Error(e),
+                    Outcome::Forward(status) => return Outcome::Forward(status),
+                };
+                let store = store.inner().clone();
+                let lookup = token.clone();
+                let user = db_connection
+                    .run(move |db_connection| store.get_user(db_connection, &lookup))
+                    .await;
+                match user {
+                    Ok(Some(user)) => Outcome::Success(BearerAuth { user, token }),
+                    Ok(None) => unauthorized_response(),
+                    Err(e) => {
+                        eprintln!("Database error validating session: {:?}", e);
+                        ApiError::Internal("Failed to validate session".to_string()).fail(req)
+                    }
                 }
             }
-            Some(_) => unauthorized_response,
-            None => unauthorized_response,
+            Some(_) => unauthorized_response(),
+            None => unauthorized_response(),
         }
     }
 }
 
-/// Hashes using argon2, the salt is in the envvar SALT
+/// Hashes using argon2 with a random salt, into a PHC string that embeds the salt and parameters
 pub fn hash_password(password: &str) -> String {
-    let salt = std::env::var("SALT").unwrap_or_else(|_| "default_salt".into());
-    let salt = SaltString::encode_b64(salt.as_bytes()).unwrap();
+    let salt = SaltString::generate(&mut OsRng);
 
     let argon2 = Argon2::default();
     let password_hash = argon2.hash_password(password.as_bytes(), &salt).unwrap();
     password_hash.to_string()
 }
 
+/// Checks a password against the PHC string stored for a user
+pub fn verify_password(password: &str, hashed_password: &str) -> bool {
+    match PasswordHash::new(hashed_password) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(e) => {
+            eprintln!("Invalid password hash in database: {:?}", e);
+            false
+        }
+    }
+}
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_USERNAME_LENGTH: usize = 50;
+
+fn unprocessable(message: impl Into<String>) -> ApiError {
+    ApiError::Validation(message.into())
+}
+
+fn validate_password(password: &str) -> Result<(), ApiError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(unprocessable(format!(
+            "Password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    username: String,
+    email: String,
+    password: String,
+}
+
+#[post("/register", format = "json", data = "<registration>")]
+async fn register(
+    registration: Json<Registration>,
+    db_connection: DbConn,
+) -> Result<(Status, Json<UserProfile>), ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let registration = registration.into_inner();
+            let username = registration.username.trim();
+            let email = registration.email.trim();
+            if username.is_empty()
+                || username.chars().count() > MAX_USERNAME_LENGTH
+                || !username
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+            {
+                return Err(unprocessable(format!(
+                    "Username must have 1 to {MAX_USERNAME_LENGTH} letters, digits, '_', '-' or '.'"
+                )));
+            }
+            if !email.contains('@') || email.contains(char::is_whitespace) {
+                return Err(unprocessable("Email address is not valid"));
+            }
+            validate_password(&registration.password)?;
+
+            let user: User = diesel::insert_into(flashqc_user::table)
+                .values(InsertableUser {
+                    username: username.to_string(),
+                    hashed_password: hash_password(&registration.password),
+                    email: email.to_string(),
+                })
+                .returning(flashqc_user::all_columns)
+                .get_result(db_connection)
+                .map_err(|e| match e {
+                    DatabaseError(DatabaseErrorKind::UniqueViolation, ref info)
+                        if info.constraint_name() == Some("flashqc_user_username_key") =>
+                    {
+                        ApiError::Conflict(format!("Username {} is already taken", username))
+                    }
+                    DatabaseError(DatabaseErrorKind::UniqueViolation, ref info)
+                        if info.constraint_name() == Some("flashqc_user_email_key") =>
+                    {
+                        ApiError::Conflict(format!("Email {} is already registered", email))
+                    }
+                    e => {
+                        eprintln!("Database error registering user: {:?}", e);
+                        ApiError::Internal("Failed to register user".to_string())
+                    }
+                })?;
+
+            rocket::info!("Registered user {}", user.id);
+            Ok((Status::Created, Json(user.into())))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+/// Changes the password of the user and ends all their sessions. The response holds a new token,
+/// so the client that changed the password stays logged in.
+#[post("/password", format = "json", data = "<change>")]
+async fn change_password(
+    change: Json<PasswordChange>,
+    user_data: BearerAuth,
+    store: &State<TokenStore>,
+    user_agent: UserAgent,
+    db_connection: DbConn,
+) -> Result<Json<IssuedToken>, ApiError> {
+    let store = store.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            if !verify_password(&change.current_password, &user_data.user.hashed_password) {
+                return Err(ApiError::Forbidden(
+                    "Current password is incorrect".to_string(),
+                ));
+            }
+            validate_password(&change.new_password)?;
+
+            let token = db_connection
+                .transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::update(flashqc_user::table.find(user_data.user.id))
+                        .set(flashqc_user::hashed_password.eq(hash_password(&change.new_password)))
+                        .execute(conn)?;
+                    store.remove_user_tokens(conn, user_data.user.id)?;
+                    store.generate_token(conn, &user_data.user, user_agent.0.as_deref())
+                })
+                .map_err(|e| {
+                    eprintln!("Database error changing password: {:?}", e);
+                    ApiError::Internal("Failed to change password".to_string())
+                })?;
+
+            rocket::info!("Changed password of user {}", user_data.user.id);
+            Ok(Json(token))
+        })
+        .await
+}
+
 #[post("/login")]
 /// The _req is on purpose to trigger the FromRequest implementation
-fn basic_auth(_req: Auth) -> Result<Value, GenericError> {
+fn basic_auth(_req: Auth) -> Result<Json<IssuedToken>, ApiError> {
     // get token from Auth request guard
-    let token = _req.token;
-    Ok(json!({ "token": token }))
+    Ok(Json(_req.token))
+}
+
+/// Ends the session of the token used for the request
+#[post("/logout")]
+async fn logout(
+    user_data: BearerAuth,
+    store: &State<TokenStore>,
+    db_connection: DbConn,
+) -> Result<Status, ApiError> {
+    let store = store.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            store
+                .remove_token(db_connection, &user_data.token)
+                .map_err(|e| {
+                    eprintln!("Database error ending session: {:?}", e);
+                    ApiError::Internal("Failed to end session".to_string())
+                })?;
+            Ok(Status::NoContent)
+        })
+        .await
+}
+
+/// Swaps the token used for the request for a new one, with a new expiry
+#[post("/refresh")]
+async fn refresh(
+    user_data: BearerAuth,
+    store: &State<TokenStore>,
+    user_agent: UserAgent,
+    db_connection: DbConn,
+) -> Result<Json<IssuedToken>, ApiError> {
+    let store = store.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            store
+                .refresh(
+                    db_connection,
+                    &user_data.user,
+                    &user_data.token,
+                    user_agent.0.as_deref(),
+                )
+                .map(Json)
+                .map_err(|e| {
+                    eprintln!("Database error refreshing session: {:?}", e);
+                    ApiError::Internal("Failed to refresh session".to_string())
+                })
+        })
+        .await
+}
+
+/// `User-Agent` header of the request, if any
+struct UserAgent(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgent {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(UserAgent(
+            req.headers().get_one("User-Agent").map(String::from),
+        ))
+    }
 }
 
 pub fn routes() -> Vec<Route> {
-    rocket_routes![basic_auth]
+    rocket_routes![basic_auth, change_password, logout, refresh, register]
 }
@@ -1,38 +1,447 @@
-use diesel::prelude::QueryDsl;
-use diesel::{ExpressionMethods, RunQueryDsl};
+use chrono::Utc;
+use diesel::prelude::{PgConnection, QueryDsl, QueryResult};
+use diesel::{Connection, ExpressionMethods, OptionalExtension, RunQueryDsl, SelectableHelper};
 use rocket::response::status;
 use rocket::serde::json::{Json, Value, json};
-use rocket::{Route, get, routes as rocket_routes};
+use rocket::{Route, State, delete, get, patch, post, put, routes as rocket_routes};
 
-use crate::auth::BearerAuth;
-use crate::models::Card;
-use crate::schema::card;
-use crate::utils::db;
-use crate::utils::gen_error::GenericError;
+use crate::auth::{BearerAuth, User};
+use crate::deck::{DeckAccess, authorize_deck};
+use crate::models::{
+    Card, CardChanges, CardChangeset, InsertableCard, NewCard, NewReviewLog, ReviewState, Tag,
+};
+use crate::plugin::{PluginRegistry, RegisteredPlugin};
+use crate::scheduler::ReviewRequest;
+use crate::schema::{card, card_tag, review_log, review_state, tag};
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
 
-#[get("/<id>")]
-fn get_card(id: i32) -> Result<Json<Card>, status::NotFound<String>> {
-    let mut db_connection = db::get_connection().lock().unwrap();
+const MAX_TAG_LENGTH: usize = 50;
+
+fn unprocessable(message: String) -> ApiError {
+    ApiError::Validation(message)
+}
+
+/// Tags are case-insensitive single words, stored in lowercase
+fn normalize_tag(name: &str) -> Result<String, ApiError> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(unprocessable("Tag cannot be empty".to_string()));
+    }
+    if name.chars().count() > MAX_TAG_LENGTH {
+        return Err(unprocessable(format!(
+            "Tag cannot be longer than {MAX_TAG_LENGTH} characters"
+        )));
+    }
+    if name.chars().any(char::is_whitespace) {
+        return Err(unprocessable("Tag cannot contain whitespace".to_string()));
+    }
+    Ok(name)
+}
+
+fn tags_of_card(db_connection: &mut PgConnection, card_id: i32) -> QueryResult<Vec<Tag>> {
+    card_tag::table
+        .inner_join(tag::table)
+        .filter(card_tag::card_id.eq(card_id))
+        .select(Tag::as_select())
+        .order_by(tag::name.asc())
+        .load(db_connection)
+}
+
+/// Finds an installed plugin, and checks the card data against its JSON Schema
+fn check_plugin_data<'r>(
+    registry: &'r PluginRegistry,
+    plugin_name: &str,
+    plugin_data: &serde_json::Value,
+) -> Result<&'r RegisteredPlugin, ApiError> {
+    let plugin = registry
+        .get(plugin_name)
+        .ok_or_else(|| unprocessable(format!("Plugin '{plugin_name}' does not exist")))?;
 
-    let card: Card = if let Ok(card) = card::table
-        .select(card::all_columns)
+    plugin.schema.validate(plugin_data).map_err(|violations| {
+        unprocessable(format!(
+            "Invalid plugin_data for plugin '{}': {}",
+            plugin_name,
+            violations
+                .iter()
+                .map(|violation| violation.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        ))
+    })?;
+
+    Ok(plugin)
+}
+
+/// Checks that the user can add cards to the deck referenced in a request body
+fn ensure_deck_writable(
+    db_connection: &mut diesel::pg::PgConnection,
+    deck_id: i32,
+    user: &User,
+) -> Result<(), ApiError> {
+    authorize_deck(db_connection, deck_id, Some(user), DeckAccess::Write)
+        .map(|_| ())
+        .map_err(|e| {
+            if matches!(e, ApiError::NotFound(_)) {
+                unprocessable(format!("Deck with id {deck_id} does not exist"))
+            } else {
+                e
+            }
+        })
+}
+
+/// Loads a card, checking that the user can access the deck it belongs to
+pub(crate) fn authorize_card(
+    db_connection: &mut diesel::pg::PgConnection,
+    id: i32,
+    user: Option<&User>,
+    access: DeckAccess,
+) -> Result<Card, ApiError> {
+    let not_found = || ApiError::NotFound(format!("Card with id {id} not found"));
+
+    let card: Card = card::table
+        .select(Card::as_select())
         .filter(card::id.eq(id))
-        .first(&mut *db_connection)
-    {
-        card
-    } else {
-        return Err(status::NotFound(format!("Card with id {id} not found")));
-    };
+        .first(db_connection)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found(),
+            _ => {
+                eprintln!("Database error loading card {}: {:?}", id, e);
+                ApiError::Internal("Failed to load card".to_string())
+            }
+        })?;
+
+    authorize_deck(db_connection, card.deck_id, user, access).map_err(|e| {
+        if matches!(e, ApiError::NotFound(_)) {
+            not_found()
+        } else {
+            e
+        }
+    })?;
+
+    Ok(card)
+}
+
+#[get("/<id>")]
+async fn get_card(
+    id: i32,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<Card>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let card = authorize_card(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            Ok(Json(card))
+        })
+        .await
+}
+
+#[post("/", format = "json", data = "<new_card>")]
+async fn create_card(
+    new_card: Json<NewCard>,
+    user_data: BearerAuth,
+    registry: &State<PluginRegistry>,
+    db_connection: DbConn,
+) -> Result<status::Created<Json<Card>>, ApiError> {
+    let registry = registry.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            let new_card = new_card.into_inner();
+
+            ensure_deck_writable(db_connection, new_card.deck_id, &user_data.user)?;
+            let plugin_id =
+                check_plugin_data(&registry, &new_card.plugin_name, &new_card.plugin_data)?.id;
+
+            let card: Card = diesel::insert_into(card::table)
+                .values(InsertableCard {
+                    deck_id: new_card.deck_id,
+                    plugin_id,
+                    plugin_name: new_card.plugin_name,
+                    plugin_data: new_card.plugin_data,
+                })
+                .returning(Card::as_select())
+                .get_result(db_connection)
+                .map_err(|e| {
+                    eprintln!("Database error creating card: {:?}", e);
+                    ApiError::Internal("Failed to create card".to_string())
+                })?;
+
+            rocket::info!("Created card {} in deck {}", card.id, card.deck_id);
+            Ok(status::Created::new(format!("/cards/{}", card.id)).body(Json(card)))
+        })
+        .await
+}
+
+#[patch("/<id>", format = "json", data = "<changes>")]
+async fn update_card(
+    id: i32,
+    changes: Json<CardChanges>,
+    user_data: BearerAuth,
+    registry: &State<PluginRegistry>,
+    db_connection: DbConn,
+) -> Result<Json<Card>, ApiError> {
+    let registry = registry.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            let changes = changes.into_inner();
+
+            let existing =
+                authorize_card(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            // Moving a card requires owning the destination deck as well
+            if let Some(deck_id) = changes.deck_id {
+                ensure_deck_writable(db_connection, deck_id, &user_data.user)?;
+            }
+
+            // Changing the plugin or the data means the resulting card has to be validated again
+            let mut plugin_id = None;
+            if changes.plugin_name.is_some() || changes.plugin_data.is_some() {
+                let plugin = check_plugin_data(
+                    &registry,
+                    changes
+                        .plugin_name
+                        .as_ref()
+                        .unwrap_or(&existing.plugin_name),
+                    changes
+                        .plugin_data
+                        .as_ref()
+                        .unwrap_or(&existing.plugin_data),
+                )?;
+                if changes.plugin_name.is_some() {
+                    plugin_id = Some(plugin.id);
+                }
+            }
+
+            let changeset = CardChangeset {
+                deck_id: changes.deck_id,
+                plugin_id,
+                plugin_name: changes.plugin_name,
+                plugin_data: changes.plugin_data,
+            };
+            // An empty changeset is not a valid UPDATE statement: just return the card as it is
+            if changeset.deck_id.is_none()
+                && changeset.plugin_id.is_none()
+                && changeset.plugin_data.is_none()
+            {
+                return Ok(Json(existing));
+            }
+
+            diesel::update(card::table.filter(card::id.eq(id)))
+                .set(&changeset)
+                .returning(Card::as_select())
+                .get_result(db_connection)
+                .map(Json)
+                .map_err(|e| {
+                    eprintln!("Database error updating card {}: {:?}", id, e);
+                    ApiError::Internal("Failed to update card".to_string())
+                })
+        })
+        .await
+}
+
+#[delete("/<id>")]
+async fn delete_card(
+    id: i32,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<status::NoContent, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            authorize_card(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            let num_cards = diesel::delete(card::table.filter(card::id.eq(id)))
+                .execute(db_connection)
+                .map_err(|e| {
+                    eprintln!("Database error deleting card {}: {:?}", id, e);
+                    ApiError::Internal("Failed to delete card".to_string())
+                })?;
+
+            if num_cards == 0 {
+                return Err(ApiError::NotFound(format!("Card with id {id} not found")));
+            }
+
+            rocket::info!("Deleted card {id}");
+            Ok(status::NoContent)
+        })
+        .await
+}
+
+#[post("/<id>/review", format = "json", data = "<review>")]
+async fn review_card(
+    id: i32,
+    review: Json<ReviewRequest>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<ReviewState>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let user_id = user_data.user.id;
+            let now = Utc::now();
+
+            if review.duration_ms.is_some_and(|duration| duration < 0) {
+                return Err(unprocessable("duration_ms cannot be negative".to_string()));
+            }
+
+            authorize_card(db_connection, id, Some(&user_data.user), DeckAccess::Read)?;
+
+            let grade = review.grade();
+            let state = db_connection
+                .transaction::<_, diesel::result::Error, _>(|conn| {
+                    let mut state = review_state::table
+                        .filter(review_state::user_id.eq(user_id))
+                        .filter(review_state::card_id.eq(id))
+                        .first::<ReviewState>(conn)
+                        .optional()?
+                        .unwrap_or_else(|| ReviewState::new(user_id, id, now));
+
+                    state.apply_grade(grade, now);
+
+                    diesel::insert_into(review_state::table)
+                        .values(&state)
+                        .on_conflict((review_state::user_id, review_state::card_id))
+                        .do_update()
+                        .set(&state)
+                        .execute(conn)?;
+
+                    diesel::insert_into(review_log::table)
+                        .values(NewReviewLog {
+                            user_id,
+                            card_id: id,
+                            grade: grade.as_str().to_string(),
+                            reason: review.reason().map(|reason| reason.as_str().to_string()),
+                            duration_ms: review.duration_ms,
+                            score: review.score,
+                            reviewed_at: now,
+                        })
+                        .execute(conn)?;
+
+                    Ok(state)
+                })
+                .map_err(|e| {
+                    eprintln!("Database error reviewing card {}: {:?}", id, e);
+                    ApiError::Internal("Failed to review card".to_string())
+                })?;
+
+            Ok(Json(state))
+        })
+        .await
+}
+
+#[get("/<id>/tags")]
+async fn get_card_tags(
+    id: i32,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<Vec<Tag>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            authorize_card(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            tags_of_card(db_connection, id).map(Json).map_err(|e| {
+                eprintln!("Database error loading tags of card {}: {:?}", id, e);
+                ApiError::Internal("Failed to load tags".to_string())
+            })
+        })
+        .await
+}
+
+/// Tags the card, creating the tag if it is new. Tagging twice is harmless.
+#[put("/<id>/tags/<name>")]
+async fn tag_card(
+    id: i32,
+    name: String,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<Tag>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let name = normalize_tag(&name)?;
+            authorize_card(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            db_connection
+                .transaction::<_, diesel::result::Error, _>(|conn| {
+                    // Updating the conflicting row to itself makes RETURNING give the existing id
+                    let tag_id: i32 = diesel::insert_into(tag::table)
+                        .values(tag::name.eq(&name))
+                        .on_conflict(tag::name)
+                        .do_update()
+                        .set(tag::name.eq(&name))
+                        .returning(tag::id)
+                        .get_result(conn)?;
+                    diesel::insert_into(card_tag::table)
+                        .values((card_tag::card_id.eq(id), card_tag::tag_id.eq(tag_id)))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                    tags_of_card(conn, id)
+                })
+                .map(Json)
+                .map_err(|e| {
+                    eprintln!("Database error tagging card {}: {:?}", id, e);
+                    ApiError::Internal("Failed to tag card".to_string())
+                })
+        })
+        .await
+}
+
+#[delete("/<id>/tags/<name>")]
+async fn untag_card(
+    id: i32,
+    name: String,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<status::NoContent, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let name = normalize_tag(&name)?;
+            authorize_card(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            let num_tags = diesel::delete(card_tag::table.filter(card_tag::card_id.eq(id)).filter(
+                card_tag::tag_id.eq_any(tag::table.filter(tag::name.eq(&name)).select(tag::id)),
+            ))
+            .execute(db_connection)
+            .map_err(|e| {
+                eprintln!("Database error untagging card {}: {:?}", id, e);
+                ApiError::Internal("Failed to untag card".to_string())
+            })?;
+
+            if num_tags == 0 {
+                return Err(ApiError::NotFound(format!(
+                    "Card with id {id} is not tagged {name}"
+                )));
+            }
 
-    Ok(Json(card))
+            Ok(status::NoContent)
+        })
+        .await
 }
 
 // This endpoint is only a demonstration of how an endpoint would be protected under Bearer Auth
 #[get("/under_auth")]
-fn under_auth(user_data: BearerAuth) -> Result<Value, GenericError> {
+fn under_auth(user_data: BearerAuth) -> Result<Value, ApiError> {
     Ok(json!({"status": "success", "message": format!("Hello, {}!", user_data.user.username)}))
 }
 
 pub fn routes() -> Vec<Route> {
-    rocket_routes![get_card, under_auth]
+    rocket_routes![
+        create_card,
+        delete_card,
+        get_card,
+        get_card_tags,
+        review_card,
+        tag_card,
+        under_auth,
+        untag_card,
+        update_card
+    ]
 }
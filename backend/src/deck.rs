@@ -1,109 +1,610 @@
-use crate::schema::{card, deck};
-use crate::utils::db;
+use crate::auth::{BearerAuth, User};
+use crate::pagination::{Page, Pagination, SortOrder, like_pattern};
+use crate::schema::{asset, card, card_tag, deck, review_state, tag};
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
+use chrono::Utc;
 use diesel::Connection;
-use diesel::prelude::QueryDsl;
-use diesel::{ExpressionMethods, RunQueryDsl};
-use rocket::http::Status;
-use rocket::response::status;
+use diesel::dsl::sql;
+use diesel::pg::Pg;
+use diesel::prelude::{PgConnection, QueryDsl, QueryResult, QueryableByName};
+use diesel::sql_types::{Integer, Nullable, Timestamptz};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, RunQueryDsl,
+};
+use diesel::{PgSortExpressionMethods, PgTextExpressionMethods, SelectableHelper};
+use rocket::http::{ContentType, Header};
+use rocket::response::{self, Responder, status};
 use rocket::serde::json::Json;
-use rocket::{Route, delete, get, routes as rocket_routes};
+use rocket::{FromFormField, Route, delete, get, patch, post, routes as rocket_routes};
 
-use crate::models::{Deck, DeckWithCards};
+use crate::import::anki::is_media_type;
+use crate::models::{Card, Deck, DeckChanges, DeckWithCards, InsertableDeck, NewDeck};
 
-#[get("/")]
-fn get_decks() -> String {
-    let mut db_connection = db::get_connection().lock().unwrap();
+const MAX_DECK_NAME_LENGTH: usize = 200;
 
-    let decks: Vec<Deck> = deck::table
-        .select((deck::id, deck::name))
-        .load(&mut *db_connection)
-        .unwrap()
-        .into_iter()
-        .map(|(id, name)| Deck { id, name })
-        .collect();
+pub(crate) fn validate_deck_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::Validation(
+            "Deck name cannot be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_DECK_NAME_LENGTH {
+        return Err(ApiError::Validation(format!(
+            "Deck name cannot be longer than {MAX_DECK_NAME_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
 
-    serde_json::to_string(&decks).unwrap()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DeckAccess {
+    /// Owners can read their decks, anyone can read public decks
+    Read,
+    /// Only owners can modify their decks, or the cards in them
+    Write,
 }
 
-#[get("/<id>")]
-fn get_deck(id: i32) -> Result<Json<DeckWithCards>, status::NotFound<String>> {
-    let mut db_connection = db::get_connection().lock().unwrap();
+/// Loads a deck, checking that `user` is allowed to access it.
+/// Decks the user cannot even read are reported as not found, so their existence is not leaked.
+pub(crate) fn authorize_deck(
+    db_connection: &mut diesel::pg::PgConnection,
+    id: i32,
+    user: Option<&User>,
+    access: DeckAccess,
+) -> Result<Deck, ApiError> {
+    let not_found = || ApiError::NotFound(format!("Deck with id {} not found", id));
 
-    let deck: Deck = if let Ok(deck) = deck::table
+    let deck: Deck = deck::table
         .select(deck::all_columns)
         .filter(deck::id.eq(id))
-        .first(&mut *db_connection)
-    {
-        deck
-    } else {
-        return Err(status::NotFound(format!("Deck with id {} not found", id)));
-    };
+        .first(db_connection)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found(),
+            _ => {
+                eprintln!("Database error loading deck {}: {:?}", id, e);
+                ApiError::Internal("Failed to load deck".to_string())
+            }
+        })?;
 
-    let card_ids: Vec<i32> = if let Ok(cards) = card::table
-        .filter(card::deck_id.eq(id))
-        .order_by(card::id.asc())
-        .select(card::id)
-        .load(&mut *db_connection)
-    {
-        cards
-    } else {
-        return Err(status::NotFound(format!(
-            "Cards for deck with id {} could not be loaded",
+    let is_owner = user.is_some_and(|user| user.id == deck.owner_id);
+    if !is_owner && !deck.is_public {
+        return Err(not_found());
+    }
+    if !is_owner && access == DeckAccess::Write {
+        return Err(ApiError::Forbidden(format!(
+            "Deck with id {} belongs to another user",
             id
         )));
+    }
+
+    Ok(deck)
+}
+
+/// The deck and all the decks below it, at any depth. A sub-deck the user cannot read hides its
+/// whole subtree. `UNION` rather than `UNION ALL` keeps the recursion finite even on a cycle.
+const VISIBLE_SUBTREE: &str = "WITH RECURSIVE subtree AS ( \
+    SELECT id FROM deck WHERE id = $1 \
+    UNION \
+    SELECT deck.id FROM deck JOIN subtree ON deck.parent_id = subtree.id \
+    WHERE deck.is_public OR deck.owner_id = $2) \
+    SELECT id FROM subtree";
+
+#[derive(QueryableByName)]
+struct SubtreeDeck {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+/// Ids of the deck and of its descendants `user_id` can read, the deck itself first
+pub(crate) fn visible_subtree(
+    db_connection: &mut PgConnection,
+    id: i32,
+    user_id: Option<i32>,
+) -> QueryResult<Vec<i32>> {
+    let decks: Vec<SubtreeDeck> = diesel::sql_query(VISIBLE_SUBTREE)
+        .bind::<Integer, _>(id)
+        .bind::<Nullable<Integer>, _>(user_id)
+        .load(db_connection)?;
+    let mut ids: Vec<i32> = decks.into_iter().map(|deck| deck.id).collect();
+    ids.sort_by_key(|deck_id| *deck_id != id);
+    Ok(ids)
+}
+
+/// Checks that the user can put a deck below `parent_id`: they must own the parent, and the
+/// parent cannot be the deck itself or one of its descendants
+fn check_parent(
+    db_connection: &mut PgConnection,
+    deck_id: Option<i32>,
+    parent_id: i32,
+    user: &User,
+) -> Result<(), ApiError> {
+    authorize_deck(db_connection, parent_id, Some(user), DeckAccess::Write).map_err(|e| {
+        if matches!(e, ApiError::NotFound(_)) {
+            ApiError::Validation(format!("Parent deck with id {parent_id} does not exist"))
+        } else {
+            e
+        }
+    })?;
+
+    if let Some(deck_id) = deck_id {
+        let descendants = visible_subtree(db_connection, deck_id, Some(user.id)).map_err(|e| {
+            eprintln!("Database error loading sub-decks: {:?}", e);
+            ApiError::Internal("Failed to load sub-decks".to_string())
+        })?;
+        if descendants.contains(&parent_id) {
+            return Err(ApiError::Validation(
+                "A deck cannot be moved below itself or one of its sub-decks".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+enum DeckSort {
+    Name,
+    Created,
+    /// Decks with the most overdue cards first, for the authenticated user
+    Due,
+}
+
+/// Public decks, plus the private decks of the user, whose name contains `search`
+fn visible_decks(user_id: Option<i32>, search: Option<&str>) -> deck::BoxedQuery<'static, Pg> {
+    let mut query = deck::table.into_boxed();
+    query = match user_id {
+        Some(user_id) => query.filter(deck::is_public.eq(true).or(deck::owner_id.eq(user_id))),
+        None => query.filter(deck::is_public.eq(true)),
     };
+    if let Some(search) = search.filter(|search| !search.is_empty()) {
+        query = query.filter(deck::name.ilike(like_pattern(search)));
+    }
+    query
+}
 
-    Ok(Json(DeckWithCards { deck, card_ids }))
+/// Public decks, plus the private decks of the authenticated user
+#[get("/?<page>&<per_page>&<sort>&<order>&<q>")]
+#[allow(clippy::too_many_arguments)]
+async fn get_decks(
+    page: Option<i64>,
+    per_page: Option<i64>,
+    sort: Option<DeckSort>,
+    order: Option<SortOrder>,
+    q: Option<String>,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<Page<Deck>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let pagination = Pagination::new(page, per_page)?;
+            let q = q.as_deref();
+            let user_id = user_data.as_ref().map(|auth| auth.user.id);
+            let sort = sort.unwrap_or(DeckSort::Name);
+            // Newest decks first by default, alphabetical and most due first otherwise
+            let descending = order.unwrap_or(if sort == DeckSort::Created {
+                SortOrder::Desc
+            } else {
+                SortOrder::Asc
+            }) == SortOrder::Desc;
+
+            let mut query = visible_decks(user_id, q).select(deck::all_columns);
+            query = match (sort, descending) {
+                (DeckSort::Name, false) => query.order_by(deck::name.asc()),
+                (DeckSort::Name, true) => query.order_by(deck::name.desc()),
+                (DeckSort::Created, false) => query.order_by(deck::created_at.asc()),
+                (DeckSort::Created, true) => query.order_by(deck::created_at.desc()),
+                (DeckSort::Due, _) => {
+                    let Some(user_id) = user_id else {
+                        return Err(ApiError::Unauthorized(
+                            "Sorting by due needs an authenticated user".to_string(),
+                        ));
+                    };
+                    // Earliest due date among the cards of the deck, never reviewed cards are due now
+                    let next_due = sql::<Nullable<Timestamptz>>(
+                        "(SELECT MIN(COALESCE(review_state.due_at, NOW())) FROM card \
+                         LEFT JOIN review_state ON review_state.card_id = card.id \
+                         AND review_state.user_id = ",
+                    )
+                    .bind::<Integer, _>(user_id)
+                    .sql(" WHERE card.deck_id = deck.id)");
+                    if descending {
+                        query.order_by(next_due.desc().nulls_last())
+                    } else {
+                        query.order_by(next_due.asc().nulls_last())
+                    }
+                }
+            };
+
+            let load = |conn: &mut PgConnection| -> QueryResult<Page<Deck>> {
+                let total = visible_decks(user_id, q).count().get_result(conn)?;
+                let decks = query
+                    .then_order_by(deck::id.asc())
+                    .offset(pagination.offset())
+                    .limit(pagination.limit())
+                    .load(conn)?;
+                Ok(Page::new(decks, pagination, total))
+            };
+            load(db_connection).map(Json).map_err(|e| {
+                eprintln!("Database error loading decks: {:?}", e);
+                ApiError::Internal("Failed to load decks".to_string())
+            })
+        })
+        .await
 }
 
-#[delete("/<id>")]
-fn delete_deck(id: i32) -> Result<status::NoContent, status::Custom<String>> {
-    let mut db_connection = db::get_connection().lock().unwrap();
+#[get("/<id>")]
+async fn get_deck(
+    id: i32,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<DeckWithCards>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let deck = authorize_deck(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            let card_ids: Vec<i32> = card::table
+                .filter(card::deck_id.eq(id))
+                .order_by(card::id.asc())
+                .select(card::id)
+                .load(db_connection)
+                .map_err(|e| {
+                    eprintln!("Database error loading cards of deck {}: {:?}", id, e);
+                    ApiError::Internal("Failed to load cards".to_string())
+                })?;
+
+            let user_id = user_data.as_ref().map(|auth| auth.user.id);
+            let load_tree = |conn: &mut PgConnection| -> QueryResult<(Vec<Deck>, Vec<i32>, i64)> {
+                let sub_decks = visible_decks(user_id, None)
+                    .filter(deck::parent_id.eq(id))
+                    .select(deck::all_columns)
+                    .order_by((deck::name.asc(), deck::id.asc()))
+                    .load(conn)?;
+                let subtree = visible_subtree(conn, id, user_id)?;
+                let total_card_count = card::table
+                    .filter(card::deck_id.eq_any(&subtree))
+                    .count()
+                    .get_result(conn)?;
+                Ok((sub_decks, subtree, total_card_count))
+            };
+            let (sub_decks, subtree, total_card_count) = load_tree(db_connection).map_err(|e| {
+                eprintln!("Database error loading sub-decks of deck {}: {:?}", id, e);
+                ApiError::Internal("Failed to load sub-decks".to_string())
+            })?;
 
-    rocket::info!("Deleting deck {id}");
+            Ok(Json(DeckWithCards {
+                deck,
+                card_ids,
+                sub_decks,
+                descendant_count: subtree.len() as i64 - 1,
+                total_card_count,
+            }))
+        })
+        .await
+}
 
-    // Use a transaction to ensure atomicity
+/// Cards of the deck, one page at a time, optionally only those of one plugin
+#[get("/<id>/cards?<page>&<per_page>&<plugin_name>")]
+async fn get_deck_cards(
+    id: i32,
+    page: Option<i64>,
+    per_page: Option<i64>,
+    plugin_name: Option<String>,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<Page<Card>>, ApiError> {
     db_connection
-        .transaction::<_, diesel::result::Error, _>(|conn| {
-            // First, delete all cards associated with this deck
-            let num_cards =
-                diesel::delete(card::table.filter(card::deck_id.eq(id))).execute(conn)?;
+        .run(move |db_connection| {
+            let pagination = Pagination::new(page, per_page)?;
+            authorize_deck(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            let cards_of_deck = || {
+                let mut query = card::table.filter(card::deck_id.eq(id)).into_boxed();
+                if let Some(plugin_name) = &plugin_name {
+                    query = query.filter(card::plugin_name.eq(plugin_name));
+                }
+                query
+            };
+            let load = |conn: &mut PgConnection| -> QueryResult<Page<Card>> {
+                let total = cards_of_deck().count().get_result(conn)?;
+                let cards = cards_of_deck()
+                    .select(Card::as_select())
+                    .order_by(card::id.asc())
+                    .offset(pagination.offset())
+                    .limit(pagination.limit())
+                    .load(conn)?;
+                Ok(Page::new(cards, pagination, total))
+            };
+            load(db_connection).map(Json).map_err(|e| {
+                eprintln!("Database error loading cards: {:?}", e);
+                ApiError::Internal(format!("Cards for deck with id {} could not be loaded", id))
+            })
+        })
+        .await
+}
 
-            rocket::info!("Deleted {} cards from deck {}", num_cards, id);
+/// Cards of the deck the user should review now: never reviewed cards first, then the
+/// overdue ones, oldest due date first. With `subtree`, the cards of the sub-decks are included,
+/// with `tag`, only the cards with that tag are.
+#[get("/<id>/due?<tag>&<subtree>")]
+async fn get_due_cards(
+    id: i32,
+    tag: Option<String>,
+    subtree: Option<bool>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<Card>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            authorize_deck(db_connection, id, Some(&user_data.user), DeckAccess::Read)?;
 
-            // Then delete the deck itself
-            let num_decks = diesel::delete(deck::table.filter(deck::id.eq(id))).execute(conn)?;
+            let deck_ids = if subtree.unwrap_or(false) {
+                visible_subtree(db_connection, id, Some(user_data.user.id)).map_err(|e| {
+                    eprintln!("Database error loading sub-decks of deck {}: {:?}", id, e);
+                    ApiError::Internal("Failed to load sub-decks".to_string())
+                })?
+            } else {
+                vec![id]
+            };
 
-            if num_decks == 0 {
-                return Err(diesel::result::Error::NotFound);
+            let mut query = card::table
+                .left_join(
+                    review_state::table.on(review_state::card_id
+                        .eq(card::id)
+                        .and(review_state::user_id.eq(user_data.user.id))),
+                )
+                .filter(card::deck_id.eq_any(deck_ids))
+                .into_boxed();
+            if let Some(tag) = &tag {
+                query = query.filter(
+                    card::id.eq_any(
+                        card_tag::table
+                            .inner_join(tag::table)
+                            .filter(tag::name.eq(tag.trim().to_lowercase()))
+                            .select(card_tag::card_id),
+                    ),
+                );
             }
 
-            Ok(())
+            let cards: Vec<Card> = query
+                .filter(
+                    review_state::due_at
+                        .nullable()
+                        .is_null()
+                        .or(review_state::due_at.nullable().le(Utc::now())),
+                )
+                .order_by((
+                    review_state::due_at.nullable().asc().nulls_first(),
+                    card::id.asc(),
+                ))
+                .select(Card::as_select())
+                .load(db_connection)
+                .map_err(|e| {
+                    eprintln!("Database error loading due cards of deck {}: {:?}", id, e);
+                    ApiError::Internal("Failed to load due cards".to_string())
+                })?;
+
+            Ok(Json(cards))
         })
-        .map(|_| {
-            rocket::info!(
-                "Deleted deck {id}. Number of decks left: {}",
-                deck::table
-                    .count()
-                    .get_result::<i64>(&mut *db_connection)
-                    .unwrap()
-            );
-            status::NoContent
+        .await
+}
+
+/// An asset as served to browsers. Assets come from uploads and share the origin of the API, so
+/// anything but images, audio and video is downloaded rather than displayed, and never runs.
+struct AssetFile {
+    content_type: String,
+    data: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for AssetFile {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> response::Result<'static> {
+        let content_type =
+            ContentType::parse_flexible(&self.content_type).unwrap_or(ContentType::Binary);
+        let mut response = (content_type, self.data).respond_to(request)?;
+        response.set_header(Header::new("X-Content-Type-Options", "nosniff"));
+        if !is_media_type(&self.content_type) {
+            response.set_header(Header::new(
+                "Content-Security-Policy",
+                "sandbox; default-src 'none'",
+            ));
+            response.set_header(Header::new("Content-Disposition", "attachment"));
+        }
+        Ok(response)
+    }
+}
+
+/// Media file of a deck, e.g. an image imported along with an Anki package
+#[get("/<id>/assets/<filename>")]
+async fn get_asset(
+    id: i32,
+    filename: String,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<AssetFile, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            authorize_deck(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            let (content_type, data): (String, Vec<u8>) = asset::table
+                .filter(asset::deck_id.eq(id))
+                .filter(asset::filename.eq(&filename))
+                .select((asset::content_type, asset::data))
+                .first(db_connection)
+                .optional()
+                .map_err(|e| {
+                    eprintln!("Database error loading asset: {:?}", e);
+                    ApiError::Internal("Failed to load asset".to_string())
+                })?
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!(
+                        "Asset {} not found in deck with id {}",
+                        filename, id
+                    ))
+                })?;
+
+            Ok(AssetFile { content_type, data })
         })
-        .map_err(|e| match e {
-            diesel::result::Error::NotFound => {
-                status::Custom(Status::NotFound, format!("Deck with id {} not found", id))
+        .await
+}
+
+#[post("/", format = "json", data = "<new_deck>")]
+async fn create_deck(
+    new_deck: Json<NewDeck>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<status::Created<Json<Deck>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let new_deck = new_deck.into_inner();
+            validate_deck_name(&new_deck.name)?;
+            if let Some(parent_id) = new_deck.parent_id {
+                check_parent(db_connection, None, parent_id, &user_data.user)?;
             }
-            _ => {
-                eprintln!("Database error deleting deck {}: {:?}", id, e);
-                status::Custom(
-                    Status::InternalServerError,
-                    "Failed to delete deck".to_string(),
-                )
+
+            let deck: Deck = diesel::insert_into(deck::table)
+                .values(InsertableDeck {
+                    name: new_deck.name,
+                    owner_id: user_data.user.id,
+                    is_public: new_deck.is_public,
+                    parent_id: new_deck.parent_id,
+                })
+                .returning(deck::all_columns)
+                .get_result(db_connection)
+                .map_err(|e| {
+                    eprintln!("Database error creating deck: {:?}", e);
+                    ApiError::Internal("Failed to create deck".to_string())
+                })?;
+
+            rocket::info!("Created deck {}", deck.id);
+            Ok(status::Created::new(format!("/decks/{}", deck.id)).body(Json(deck)))
+        })
+        .await
+}
+
+#[patch("/<id>", format = "json", data = "<changes>")]
+async fn update_deck(
+    id: i32,
+    changes: Json<DeckChanges>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Deck>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            if let Some(name) = &changes.name {
+                validate_deck_name(name)?;
+            }
+
+            let deck = authorize_deck(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            if let Some(Some(parent_id)) = changes.parent_id {
+                check_parent(db_connection, Some(id), parent_id, &user_data.user)?;
+            }
+
+            // An empty changeset is not a valid UPDATE statement: just return the deck as it is
+            if changes.name.is_none() && changes.is_public.is_none() && changes.parent_id.is_none()
+            {
+                return Ok(Json(deck));
+            }
+
+            diesel::update(deck::table.filter(deck::id.eq(id)))
+                .set(&*changes)
+                .returning(deck::all_columns)
+                .get_result(db_connection)
+                .map(Json)
+                .map_err(|e| {
+                    eprintln!("Database error updating deck {}: {:?}", id, e);
+                    ApiError::Internal("Failed to update deck".to_string())
+                })
+        })
+        .await
+}
+
+#[delete("/<id>")]
+async fn delete_deck(
+    id: i32,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<status::NoContent, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            authorize_deck(db_connection, id, Some(&user_data.user), DeckAccess::Write)?;
+
+            let has_sub_decks = diesel::select(diesel::dsl::exists(
+                deck::table.filter(deck::parent_id.eq(id)),
+            ))
+            .get_result::<bool>(db_connection)
+            .map_err(|e| {
+                eprintln!("Database error loading sub-decks of deck {}: {:?}", id, e);
+                ApiError::Internal("Failed to delete deck".to_string())
+            })?;
+            if has_sub_decks {
+                return Err(ApiError::Conflict(format!(
+                    "Deck with id {id} has sub-decks, delete or move them first"
+                )));
             }
+
+            rocket::info!("Deleting deck {id}");
+
+            // Use a transaction to ensure atomicity
+            db_connection
+                .transaction::<_, diesel::result::Error, _>(|conn| {
+                    // First, delete all cards associated with this deck
+                    let num_cards =
+                        diesel::delete(card::table.filter(card::deck_id.eq(id))).execute(conn)?;
+
+                    rocket::info!("Deleted {} cards from deck {}", num_cards, id);
+
+                    // Then delete the deck itself
+                    let num_decks =
+                        diesel::delete(deck::table.filter(deck::id.eq(id))).execute(conn)?;
+
+                    if num_decks == 0 {
+                        return Err(diesel::result::Error::NotFound);
+                    }
+
+                    Ok(())
+                })
+                .map(|_| {
+                    rocket::info!("Deleted deck {id}");
+                    status::NoContent
+                })
+                .map_err(|e| match e {
+                    diesel::result::Error::NotFound => {
+                        ApiError::NotFound(format!("Deck with id {} not found", id))
+                    }
+                    _ => {
+                        eprintln!("Database error deleting deck {}: {:?}", id, e);
+                        ApiError::Internal("Failed to delete deck".to_string())
+                    }
+                })
         })
+        .await
 }
 
 pub fn routes() -> Vec<Route> {
-    rocket_routes![delete_deck, get_deck, get_decks]
+    rocket_routes![
+        create_deck,
+        delete_deck,
+        get_asset,
+        get_deck,
+        get_deck_cards,
+        get_decks,
+        get_due_cards,
+        update_deck
+    ]
 }
@@ -0,0 +1,411 @@
+use diesel::prelude::*;
+use rocket::http::ContentType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use thiserror::Error;
+use zip::ZipArchive;
+
+use super::archive::UnpackBudget;
+use crate::models::{Deck, InsertableCard, InsertableDeck, NewAsset};
+use crate::plugin::PluginRegistry;
+use crate::schema::{asset, card, deck};
+
+/// Plugin used to render basic front/back notes
+const FLIP_WORD_PLUGIN: &str = "flip-word";
+/// Anki separates the fields of a note with the "unit separator" control character
+const FIELD_SEPARATOR: char = '\u{1f}';
+/// Value of `type` in an Anki note type for cloze deletions
+const CLOZE_NOTE_TYPE: i64 = 1;
+const DEFAULT_DECK_NAME: &str = "Anki import";
+
+#[derive(Error, Debug)]
+pub enum AnkiImportError {
+    #[error("Not a valid .apkg file: {0}")]
+    InvalidPackage(String),
+    #[error("Unsupported .apkg file: {0}")]
+    Unsupported(String),
+    #[error("Plugin '{0}' is not installed")]
+    MissingPlugin(&'static str),
+    #[error("Database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+impl From<zip::result::ZipError> for AnkiImportError {
+    fn from(err: zip::result::ZipError) -> Self {
+        AnkiImportError::InvalidPackage(err.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for AnkiImportError {
+    fn from(err: rusqlite::Error) -> Self {
+        AnkiImportError::InvalidPackage(format!("cannot read the collection: {err}"))
+    }
+}
+
+impl From<std::io::Error> for AnkiImportError {
+    fn from(err: std::io::Error) -> Self {
+        AnkiImportError::InvalidPackage(err.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnkiField {
+    name: String,
+}
+
+/// Subset of an Anki note type (called "model" in the collection) used by the import
+#[derive(Debug, Deserialize)]
+struct AnkiNoteType {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: i64,
+    flds: Vec<AnkiField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnkiDeck {
+    name: String,
+}
+
+#[derive(Debug)]
+struct AnkiNote {
+    id: i64,
+    note_type_id: String,
+    fields: Vec<String>,
+}
+
+/// Contents of an `.apkg` file, read into memory
+pub struct AnkiPackage {
+    note_types: HashMap<String, AnkiNoteType>,
+    notes: Vec<AnkiNote>,
+    /// Name of the Anki deck holding most of the cards, if any
+    main_deck_name: Option<String>,
+    media: Vec<(String, Vec<u8>)>,
+    /// Media files that are not images, audio or video, left out of the import
+    skipped_media: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedNote {
+    pub note_id: i64,
+    pub note_type: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub deck: Deck,
+    pub imported_cards: usize,
+    pub imported_assets: usize,
+    pub skipped: Vec<SkippedNote>,
+    /// Media files that are not images, audio or video, which are not imported
+    pub skipped_media: Vec<String>,
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &mut UnpackBudget,
+    name: &str,
+) -> Result<Option<Vec<u8>>, AnkiImportError> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(budget.read(&mut file)?))
+}
+
+impl AnkiPackage {
+    /// Unpacks an `.apkg` file: a zip with the SQLite collection and the numbered media files
+    pub fn read(bytes: &[u8]) -> Result<AnkiPackage, AnkiImportError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut budget = UnpackBudget::new();
+
+        let collection = match read_entry(&mut archive, &mut budget, "collection.anki21")? {
+            Some(collection) => collection,
+            None => match read_entry(&mut archive, &mut budget, "collection.anki2")? {
+                Some(collection) => collection,
+                None if archive.index_for_name("collection.anki21b").is_some() => {
+                    return Err(AnkiImportError::Unsupported(
+                        "collections from Anki 2.1.50+ are compressed, export the deck with \
+                         'Support older Anki versions' enabled"
+                            .to_string(),
+                    ));
+                }
+                None => {
+                    return Err(AnkiImportError::InvalidPackage(
+                        "no collection found".to_string(),
+                    ));
+                }
+            },
+        };
+
+        // SQLite can only open collections from disk
+        let mut collection_file = tempfile::NamedTempFile::new()?;
+        collection_file.write_all(&collection)?;
+        let connection = rusqlite::Connection::open_with_flags(
+            collection_file.path(),
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+
+        let (models, decks): (String, String) =
+            connection.query_row("SELECT models, decks FROM col", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        let note_types: HashMap<String, AnkiNoteType> = serde_json::from_str(&models)
+            .map_err(|e| AnkiImportError::InvalidPackage(format!("invalid note types: {e}")))?;
+        let decks: HashMap<String, AnkiDeck> = serde_json::from_str(&decks)
+            .map_err(|e| AnkiImportError::InvalidPackage(format!("invalid decks: {e}")))?;
+
+        let mut statement = connection.prepare("SELECT id, mid, flds FROM notes ORDER BY id")?;
+        let notes = statement
+            .query_map([], |row| {
+                let fields: String = row.get(2)?;
+                Ok(AnkiNote {
+                    id: row.get(0)?,
+                    note_type_id: row.get::<_, i64>(1)?.to_string(),
+                    fields: fields.split(FIELD_SEPARATOR).map(String::from).collect(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let main_deck_name = connection
+            .query_row(
+                "SELECT did FROM cards GROUP BY did ORDER BY COUNT(*) DESC LIMIT 1",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .ok()
+            .and_then(|deck_id| decks.get(&deck_id.to_string()))
+            .map(|deck| deck.name.clone());
+
+        // The "media" entry maps the numbered files of the zip to their original names
+        let media_names: HashMap<String, String> =
+            match read_entry(&mut archive, &mut budget, "media")? {
+                Some(media) => serde_json::from_slice(&media).map_err(|e| {
+                    AnkiImportError::InvalidPackage(format!("invalid media map: {e}"))
+                })?,
+                None => HashMap::new(),
+            };
+        let mut media = Vec::new();
+        let mut skipped_media = Vec::new();
+        for (index, filename) in media_names {
+            // Anything else, e.g. HTML or SVG, would run as a page of the API origin
+            if !is_media_type(&content_type_of(&filename)) {
+                skipped_media.push(filename);
+                continue;
+            }
+            if let Some(contents) = read_entry(&mut archive, &mut budget, &index)? {
+                media.push((filename, contents));
+            }
+        }
+        media.sort_by(|a, b| a.0.cmp(&b.0));
+        skipped_media.sort();
+
+        Ok(AnkiPackage {
+            note_types,
+            notes,
+            main_deck_name,
+            media,
+            skipped_media,
+        })
+    }
+}
+
+/// Turns the HTML of an Anki field into the plain text plugins display
+fn html_to_text(html: &str) -> String {
+    let html = html
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n");
+
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    // Sound references are media, not text
+    while let Some(start) = text.find("[sound:") {
+        match text[start..].find(']') {
+            Some(end) => text.replace_range(start..start + end + 1, ""),
+            None => break,
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Maps an Anki note to the `plugin_data` of a flip-word card
+fn to_flip_word(note: &AnkiNote, note_type: &AnkiNoteType) -> Result<serde_json::Value, String> {
+    if note_type.kind == CLOZE_NOTE_TYPE {
+        return Err("cloze notes are not supported by any plugin".to_string());
+    }
+    if note_type.flds.len() < 2 || note.fields.len() < 2 {
+        return Err(format!(
+            "note type has {} field(s), a front and a back are needed",
+            note_type.flds.len()
+        ));
+    }
+
+    let front = html_to_text(&note.fields[0]);
+    let back = html_to_text(&note.fields[1]);
+    if front.is_empty() || back.is_empty() {
+        return Err(format!(
+            "'{}' or '{}' has no text once media and HTML are removed",
+            note_type.flds[0].name, note_type.flds[1].name
+        ));
+    }
+
+    Ok(serde_json::json!({ "frontContent": front, "backContent": back }))
+}
+
+/// Creates a private deck owned by `owner_id` with a card for every note that maps to a plugin,
+/// and an asset for every media file. Everything is imported in a single transaction.
+pub fn import_package(
+    db_connection: &mut PgConnection,
+    registry: &PluginRegistry,
+    owner_id: i32,
+    package: AnkiPackage,
+    deck_name: Option<&str>,
+) -> Result<ImportReport, AnkiImportError> {
+    let flip_word = registry
+        .get(FLIP_WORD_PLUGIN)
+        .ok_or(AnkiImportError::MissingPlugin(FLIP_WORD_PLUGIN))?;
+
+    let mut skipped = Vec::new();
+    let mut cards = Vec::new();
+    for note in &package.notes {
+        let Some(note_type) = package.note_types.get(&note.note_type_id) else {
+            skipped.push(SkippedNote {
+                note_id: note.id,
+                note_type: note.note_type_id.clone(),
+                reason: "unknown note type".to_string(),
+            });
+            continue;
+        };
+        let plugin_data = to_flip_word(note, note_type).and_then(|plugin_data| {
+            flip_word
+                .schema
+                .validate(&plugin_data)
+                .map(|_| plugin_data)
+                .map_err(|violations| {
+                    violations
+                        .iter()
+                        .map(|violation| violation.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                })
+        });
+        match plugin_data {
+            Ok(plugin_data) => cards.push(plugin_data),
+            Err(reason) => skipped.push(SkippedNote {
+                note_id: note.id,
+                note_type: note_type.name.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let deck_name = deck_name
+        .map(String::from)
+        .or(package.main_deck_name)
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_DECK_NAME.to_string());
+
+    let imported_cards = cards.len();
+    let imported_assets = package.media.len();
+
+    let deck = db_connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let deck: Deck = diesel::insert_into(deck::table)
+            .values(InsertableDeck {
+                name: deck_name,
+                owner_id,
+                is_public: false,
+                parent_id: None,
+            })
+            .returning(deck::all_columns)
+            .get_result(conn)?;
+
+        let cards: Vec<InsertableCard> = cards
+            .into_iter()
+            .map(|plugin_data| InsertableCard {
+                deck_id: deck.id,
+                plugin_id: flip_word.id,
+                plugin_name: FLIP_WORD_PLUGIN.to_string(),
+                plugin_data,
+            })
+            .collect();
+        diesel::insert_into(card::table)
+            .values(&cards)
+            .execute(conn)?;
+
+        let assets: Vec<NewAsset> = package
+            .media
+            .into_iter()
+            .map(|(filename, data)| NewAsset {
+                deck_id: deck.id,
+                content_type: content_type_of(&filename),
+                filename,
+                data,
+            })
+            .collect();
+        diesel::insert_into(asset::table)
+            .values(&assets)
+            .execute(conn)?;
+
+        Ok(deck)
+    })?;
+
+    rocket::info!(
+        "Imported Anki package into deck {}: {} cards, {} assets, {} notes and {} media files \
+         skipped",
+        deck.id,
+        imported_cards,
+        imported_assets,
+        skipped.len(),
+        package.skipped_media.len()
+    );
+
+    Ok(ImportReport {
+        deck,
+        imported_cards,
+        imported_assets,
+        skipped,
+        skipped_media: package.skipped_media,
+    })
+}
+
+/// Whether a media type is safe to serve from the API origin: images, audio and video, but not
+/// SVG, which can run scripts
+pub(crate) fn is_media_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let top_level = essence.split('/').next().unwrap_or_default();
+    matches!(top_level, "image" | "audio" | "video") && essence != "image/svg+xml"
+}
+
+pub(crate) fn content_type_of(filename: &str) -> String {
+    filename
+        .rsplit_once('.')
+        .and_then(|(_, extension)| ContentType::from_extension(extension))
+        .unwrap_or(ContentType::Binary)
+        .to_string()
+}
@@ -0,0 +1,40 @@
+use std::io::{self, Read};
+use zip::read::ZipFile;
+
+/// Most bytes unpacked from a single upload. The upload limit only caps the compressed size,
+/// and a small zip can inflate to gigabytes.
+const MAX_UNPACKED_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Bytes left to unpack from an upload
+pub struct UnpackBudget {
+    remaining: u64,
+}
+
+impl UnpackBudget {
+    pub fn new() -> Self {
+        UnpackBudget {
+            remaining: MAX_UNPACKED_BYTES,
+        }
+    }
+
+    /// Reads a whole file of the zip, failing once the upload unpacks to more than the budget
+    pub fn read(&mut self, file: &mut ZipFile<'_>) -> io::Result<Vec<u8>> {
+        let too_large = || {
+            io::Error::other(format!(
+                "unpacks to more than {} MiB",
+                MAX_UNPACKED_BYTES / (1024 * 1024)
+            ))
+        };
+        if file.size() > self.remaining {
+            return Err(too_large());
+        }
+        // The size in the zip can lie, so the read stops past the budget too
+        let mut contents = Vec::new();
+        file.take(self.remaining + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > self.remaining {
+            return Err(too_large());
+        }
+        self.remaining -= contents.len() as u64;
+        Ok(contents)
+    }
+}
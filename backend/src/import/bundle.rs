@@ -0,0 +1,397 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Cursor, Write};
+use thiserror::Error;
+use zip::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use super::anki::{content_type_of, is_media_type};
+use super::archive::UnpackBudget;
+use crate::deck::validate_deck_name;
+use crate::models::{Deck, InsertableCard, InsertableDeck, NewAsset};
+use crate::plugin::PluginRegistry;
+use crate::schema::{asset, card, deck};
+
+/// Version of the bundle layout, bumped on every incompatible change
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+/// Entry of the zip holding the `BundleManifest`, media files live under `MEDIA_DIR`
+const MANIFEST_ENTRY: &str = "bundle.json";
+const MEDIA_DIR: &str = "media/";
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("Not a valid bundle: {0}")]
+    InvalidBundle(String),
+    #[error("Unsupported bundle format version {0}, expected {BUNDLE_FORMAT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Missing plugins: {}", .0.join(", "))]
+    MissingPlugins(Vec<String>),
+    #[error("Plugin {name} v{installed} is installed, but the bundle needs v{required}")]
+    IncompatiblePlugin {
+        name: String,
+        required: String,
+        installed: String,
+    },
+    #[error("Card {id} is invalid: {reason}")]
+    InvalidCard { id: i32, reason: String },
+    #[error("Database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+impl From<zip::result::ZipError> for BundleError {
+    fn from(err: zip::result::ZipError) -> Self {
+        BundleError::InvalidBundle(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for BundleError {
+    fn from(err: std::io::Error) -> Self {
+        BundleError::InvalidBundle(err.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleDeck {
+    pub name: String,
+    pub is_public: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleCard {
+    /// Id of the card where the bundle was exported, new ids are assigned on import
+    pub id: i32,
+    pub plugin_name: String,
+    pub plugin_data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleMedia {
+    pub filename: String,
+    /// Informative only, imports go by the extension of `filename`
+    pub content_type: String,
+}
+
+/// Contents of `bundle.json`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub deck: BundleDeck,
+    /// Version of every plugin used by the cards, when the bundle was exported
+    pub plugins: BTreeMap<String, String>,
+    pub cards: Vec<BundleCard>,
+    #[serde(default)]
+    pub media: Vec<BundleMedia>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleImportReport {
+    pub deck: Deck,
+    /// Id of every card in the bundle, mapped to the id of the card created from it
+    pub card_ids: BTreeMap<i32, i32>,
+    pub imported_assets: usize,
+}
+
+/// `MAJOR.MINOR.PATCH` parts of a plugin version
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+            Some((major, minor, patch))
+        }
+        _ => None,
+    }
+}
+
+/// An installed plugin can render cards made for the same major version, up to its own version
+fn is_compatible(required: &str, installed: &str) -> bool {
+    match (parse_version(required), parse_version(installed)) {
+        (Some(required), Some(installed)) => required.0 == installed.0 && required <= installed,
+        _ => false,
+    }
+}
+
+/// Plugins used by cards of the bundle that are not in its list of plugin versions
+fn unlisted_plugins(manifest: &BundleManifest) -> Vec<String> {
+    manifest
+        .cards
+        .iter()
+        .map(|card| &card.plugin_name)
+        .filter(|name| !manifest.plugins.contains_key(*name))
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Writes the deck, its cards, the versions of their plugins and the deck assets to a zip bundle
+pub fn export_deck(
+    db_connection: &mut PgConnection,
+    registry: &PluginRegistry,
+    deck: &Deck,
+) -> Result<Vec<u8>, BundleError> {
+    let cards: Vec<(i32, String, serde_json::Value)> = card::table
+        .filter(card::deck_id.eq(deck.id))
+        .order_by(card::id.asc())
+        .select((card::id, card::plugin_name, card::plugin_data))
+        .load(db_connection)?;
+    let assets: Vec<(String, String, Vec<u8>)> = asset::table
+        .filter(asset::deck_id.eq(deck.id))
+        .order_by(asset::filename.asc())
+        .select((asset::filename, asset::content_type, asset::data))
+        .load(db_connection)?;
+
+    let mut plugins = BTreeMap::new();
+    for (_, plugin_name, _) in &cards {
+        // Cards of plugins that are no longer installed keep the version they were created for
+        let version = registry
+            .get(plugin_name)
+            .map(|plugin| plugin.manifest.version.clone())
+            .unwrap_or_else(|| "0.0.0".to_string());
+        plugins.entry(plugin_name.clone()).or_insert(version);
+    }
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        deck: BundleDeck {
+            name: deck.name.clone(),
+            is_public: deck.is_public,
+        },
+        plugins,
+        cards: cards
+            .into_iter()
+            .map(|(id, plugin_name, plugin_data)| BundleCard {
+                id,
+                plugin_name,
+                plugin_data,
+            })
+            .collect(),
+        media: assets
+            .iter()
+            .map(|(filename, content_type, _)| BundleMedia {
+                filename: filename.clone(),
+                content_type: content_type.clone(),
+            })
+            .collect(),
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    zip.start_file(MANIFEST_ENTRY, options)?;
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| BundleError::InvalidBundle(e.to_string()))?;
+    zip.write_all(&manifest)?;
+    for (filename, _, data) in &assets {
+        zip.start_file(format!("{MEDIA_DIR}{filename}"), options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Reads a bundle and creates its deck for `owner_id`, with new ids for the deck and the cards.
+/// Bundles are rejected as a whole when a plugin is missing or a card does not match its schema.
+pub fn import_bundle(
+    db_connection: &mut PgConnection,
+    registry: &PluginRegistry,
+    owner_id: i32,
+    bytes: &[u8],
+) -> Result<BundleImportReport, BundleError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut budget = UnpackBudget::new();
+
+    let manifest: BundleManifest = {
+        let mut file = archive
+            .by_name(MANIFEST_ENTRY)
+            .map_err(|_| BundleError::InvalidBundle(format!("{MANIFEST_ENTRY} not found")))?;
+        let contents = budget.read(&mut file)?;
+        // Check the version before the rest of the fields, which may have changed meaning
+        let format_version = serde_json::from_slice::<serde_json::Value>(&contents)
+            .ok()
+            .and_then(|manifest| manifest.get("format_version")?.as_u64());
+        if let Some(version) = format_version
+            && version != u64::from(BUNDLE_FORMAT_VERSION)
+        {
+            return Err(BundleError::UnsupportedVersion(
+                u32::try_from(version).unwrap_or(u32::MAX),
+            ));
+        }
+        serde_json::from_slice(&contents)
+            .map_err(|e| BundleError::InvalidBundle(format!("invalid {MANIFEST_ENTRY}: {e}")))?
+    };
+
+    validate_deck_name(&manifest.deck.name)
+        .map_err(|e| BundleError::InvalidBundle(format!("invalid deck name: {e}")))?;
+
+    // Only the listed plugins get their version checked
+    let unlisted = unlisted_plugins(&manifest);
+    if !unlisted.is_empty() {
+        return Err(BundleError::InvalidBundle(format!(
+            "plugins of cards missing from {MANIFEST_ENTRY}: {}",
+            unlisted.join(", ")
+        )));
+    }
+    let missing: Vec<String> = manifest
+        .plugins
+        .keys()
+        .filter(|name| registry.get(name).is_none())
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !missing.is_empty() {
+        return Err(BundleError::MissingPlugins(missing));
+    }
+    for (name, required) in &manifest.plugins {
+        let installed = &registry.get(name).unwrap().manifest.version;
+        if !is_compatible(required, installed) {
+            return Err(BundleError::IncompatiblePlugin {
+                name: name.clone(),
+                required: required.clone(),
+                installed: installed.clone(),
+            });
+        }
+    }
+    for card in &manifest.cards {
+        registry
+            .get(&card.plugin_name)
+            .unwrap()
+            .schema
+            .validate(&card.plugin_data)
+            .map_err(|violations| BundleError::InvalidCard {
+                id: card.id,
+                reason: violations
+                    .iter()
+                    .map(|violation| violation.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            })?;
+    }
+
+    let mut filenames = BTreeSet::new();
+    for entry in &manifest.media {
+        if !filenames.insert(&entry.filename) {
+            return Err(BundleError::InvalidBundle(format!(
+                "media file {} is listed twice",
+                entry.filename
+            )));
+        }
+        // The declared type is not trusted: assets are served from the API origin, where HTML
+        // or SVG would run as a page of the app
+        if !is_media_type(&content_type_of(&entry.filename)) {
+            return Err(BundleError::InvalidBundle(format!(
+                "media file {} is not an image, audio or video",
+                entry.filename
+            )));
+        }
+    }
+
+    let mut media = Vec::new();
+    for entry in &manifest.media {
+        let mut file = archive
+            .by_name(&format!("{MEDIA_DIR}{}", entry.filename))
+            .map_err(|_| {
+                BundleError::InvalidBundle(format!("media file {} not found", entry.filename))
+            })?;
+        media.push(budget.read(&mut file)?);
+    }
+
+    let report = db_connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let deck: Deck = diesel::insert_into(deck::table)
+            .values(InsertableDeck {
+                name: manifest.deck.name,
+                owner_id,
+                is_public: manifest.deck.is_public,
+                parent_id: None,
+            })
+            .returning(deck::all_columns)
+            .get_result(conn)?;
+
+        let mut card_ids = BTreeMap::new();
+        for bundle_card in manifest.cards {
+            let new_id: i32 = diesel::insert_into(card::table)
+                .values(InsertableCard {
+                    deck_id: deck.id,
+                    plugin_id: registry.get(&bundle_card.plugin_name).unwrap().id,
+                    plugin_name: bundle_card.plugin_name,
+                    plugin_data: bundle_card.plugin_data,
+                })
+                .returning(card::id)
+                .get_result(conn)?;
+            card_ids.insert(bundle_card.id, new_id);
+        }
+
+        let assets: Vec<NewAsset> = manifest
+            .media
+            .into_iter()
+            .zip(media)
+            .map(|(entry, data)| NewAsset {
+                deck_id: deck.id,
+                content_type: content_type_of(&entry.filename),
+                filename: entry.filename,
+                data,
+            })
+            .collect();
+        let imported_assets = assets.len();
+        diesel::insert_into(asset::table)
+            .values(&assets)
+            .execute(conn)?;
+
+        Ok(BundleImportReport {
+            deck,
+            card_ids,
+            imported_assets,
+        })
+    })?;
+
+    rocket::info!(
+        "Imported bundle into deck {}: {} cards, {} assets",
+        report.deck.id,
+        report.card_ids.len(),
+        report.imported_assets
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn manifest(plugins: &[&str], card_plugins: &[&str]) -> BundleManifest {
+        BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            deck: BundleDeck {
+                name: "Deck".to_string(),
+                is_public: false,
+            },
+            plugins: plugins
+                .iter()
+                .map(|name| (name.to_string(), "1.0.0".to_string()))
+                .collect(),
+            cards: card_plugins
+                .iter()
+                .zip(1..)
+                .map(|(name, id)| BundleCard {
+                    id,
+                    plugin_name: name.to_string(),
+                    plugin_data: json!({}),
+                })
+                .collect(),
+            media: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accepts_cards_of_listed_plugins() {
+        assert!(unlisted_plugins(&manifest(&["basic", "cube"], &["basic", "basic"])).is_empty());
+        assert!(unlisted_plugins(&manifest(&[], &[])).is_empty());
+    }
+
+    #[test]
+    fn finds_cards_of_unlisted_plugins() {
+        assert_eq!(
+            unlisted_plugins(&manifest(&["basic"], &["cube", "basic", "cube", "audio"])),
+            ["audio", "cube"]
+        );
+    }
+}
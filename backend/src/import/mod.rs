@@ -0,0 +1,8 @@
+pub mod anki;
+mod archive;
+pub mod bundle;
+mod routes;
+
+pub use anki::{AnkiImportError, AnkiPackage, ImportReport, SkippedNote};
+pub use bundle::{BundleError, BundleImportReport, BundleManifest};
+pub use routes::routes;
@@ -0,0 +1,172 @@
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::{ContentType, Header};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Responder, Route, State, get, post, routes as rocket_routes};
+
+use super::anki::{self, AnkiImportError, AnkiPackage, ImportReport};
+use super::bundle::{self, BundleError, BundleImportReport};
+use crate::auth::BearerAuth;
+use crate::deck::{DeckAccess, authorize_deck};
+use crate::plugin::PluginRegistry;
+use crate::utils::api_error::ApiError;
+use crate::utils::db::{DbConn, DbPool};
+
+/// Upload limit for packages and bundles, unless `limits.apkg` or `limits.bundle` is set in
+/// the Rocket config
+const DEFAULT_PACKAGE_LIMIT_MIB: u64 = 64;
+
+#[derive(Responder)]
+struct BundleFile {
+    data: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+async fn read_upload(
+    upload: Data<'_>,
+    limits: &Limits,
+    limit_name: &str,
+) -> Result<Vec<u8>, ApiError> {
+    let limit = limits
+        .get(limit_name)
+        .unwrap_or(DEFAULT_PACKAGE_LIMIT_MIB.mebibytes());
+    let bytes = upload
+        .open(limit)
+        .into_bytes()
+        .await
+        .map_err(|e| ApiError::BadRequest(format!("Failed to read the upload: {e}")))?;
+    if !bytes.is_complete() {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "Uploads cannot be larger than {limit}"
+        )));
+    }
+    Ok(bytes.into_inner())
+}
+
+/// Uploads are read before taking a connection, so that slow clients do not hold one
+async fn connect(pool: &DbPool) -> Result<DbConn, ApiError> {
+    DbConn::get(pool).await.map_err(|e| {
+        eprintln!("{e}");
+        ApiError::Unavailable("Database unavailable".to_string())
+    })
+}
+
+/// Runs the unpacking of an import off the async workers
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    spawn_blocking(work).await.map_err(|e| {
+        eprintln!("Import task failed: {e}");
+        ApiError::Internal("Import failed".to_string())
+    })?
+}
+
+fn import_error(e: AnkiImportError) -> ApiError {
+    match e {
+        AnkiImportError::InvalidPackage(_) | AnkiImportError::Unsupported(_) => {
+            ApiError::Validation(e.to_string())
+        }
+        AnkiImportError::MissingPlugin(_) | AnkiImportError::Database(_) => {
+            eprintln!("Error importing Anki package: {:?}", e);
+            ApiError::Internal("Failed to import Anki package".to_string())
+        }
+    }
+}
+
+#[post("/import/anki?<name>", data = "<package>")]
+async fn import_anki(
+    name: Option<&str>,
+    package: Data<'_>,
+    limits: &Limits,
+    user_data: BearerAuth,
+    registry: &State<PluginRegistry>,
+    pool: &State<DbPool>,
+) -> Result<status::Created<Json<ImportReport>>, ApiError> {
+    let bytes = read_upload(package, limits, "apkg").await?;
+
+    let package = run_blocking(move || AnkiPackage::read(&bytes).map_err(import_error)).await?;
+    let db_connection = connect(pool).await?;
+    let registry = registry.inner().clone();
+    let owner_id = user_data.user.id;
+    let name = name.map(String::from);
+    let report = db_connection
+        .run(move |db_connection| {
+            anki::import_package(db_connection, &registry, owner_id, package, name.as_deref())
+                .map_err(import_error)
+        })
+        .await?;
+
+    Ok(status::Created::new(format!("/decks/{}", report.deck.id)).body(Json(report)))
+}
+
+fn bundle_error(e: BundleError) -> ApiError {
+    match e {
+        BundleError::Database(_) => {
+            eprintln!("Error importing bundle: {:?}", e);
+            ApiError::Internal("Failed to import bundle".to_string())
+        }
+        _ => ApiError::Validation(e.to_string()),
+    }
+}
+
+#[get("/<id>/export")]
+async fn export_deck(
+    id: i32,
+    user_data: Option<BearerAuth>,
+    registry: &State<PluginRegistry>,
+    db_connection: DbConn,
+) -> Result<BundleFile, ApiError> {
+    let registry = registry.inner().clone();
+    db_connection
+        .run(move |db_connection| {
+            let deck = authorize_deck(
+                db_connection,
+                id,
+                user_data.as_ref().map(|auth| &auth.user),
+                DeckAccess::Read,
+            )?;
+
+            let data = bundle::export_deck(db_connection, &registry, &deck).map_err(|e| {
+                eprintln!("Error exporting deck: {:?}", e);
+                ApiError::Internal("Failed to export deck".to_string())
+            })?;
+
+            Ok(BundleFile {
+                data,
+                content_type: ContentType::ZIP,
+                disposition: Header::new(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"deck-{}.fqc.zip\"", deck.id),
+                ),
+            })
+        })
+        .await
+}
+
+#[post("/import", data = "<bundle>")]
+async fn import_bundle(
+    bundle: Data<'_>,
+    limits: &Limits,
+    user_data: BearerAuth,
+    registry: &State<PluginRegistry>,
+    pool: &State<DbPool>,
+) -> Result<status::Created<Json<BundleImportReport>>, ApiError> {
+    let bytes = read_upload(bundle, limits, "bundle").await?;
+
+    let db_connection = connect(pool).await?;
+    let registry = registry.inner().clone();
+    let owner_id = user_data.user.id;
+    let report = db_connection
+        .run(move |db_connection| {
+            bundle::import_bundle(db_connection, &registry, owner_id, &bytes).map_err(bundle_error)
+        })
+        .await?;
+
+    Ok(status::Created::new(format!("/decks/{}", report.deck.id)).body(Json(report)))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket_routes![export_deck, import_anki, import_bundle]
+}
@@ -1,8 +1,14 @@
 pub mod auth;
 pub mod card;
 pub mod deck;
+pub mod import;
 pub mod models;
+pub mod pagination;
+pub mod plugin;
 pub mod root;
+pub mod scheduler;
 pub mod schema;
+pub mod search;
+pub mod stats;
 pub mod system;
 pub mod utils;
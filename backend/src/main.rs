@@ -1,19 +1,28 @@
 use rocket::launch;
 extern crate rocket;
-use std::sync::Arc;
-use std::sync::Mutex;
 
+use flashqc_backend::plugin::{PluginHosting, PluginRegistry};
 use flashqc_backend::utils::token_store::TokenStore;
-use flashqc_backend::{auth, card, deck, root, system};
+use flashqc_backend::utils::{api_error, db};
+use flashqc_backend::{auth, card, deck, import, plugin, root, search, stats, system};
 
 #[launch]
 fn rocket() -> _ {
     rocket::build()
-        .manage(Arc::new(Mutex::new(TokenStore::new())))
+        .attach(db::fairing())
+        .attach(TokenStore::fairing())
         .attach(system::Cors)
+        .attach(system::Metrics)
+        .attach(PluginRegistry::fairing())
+        .attach(PluginHosting::fairing())
+        .register("/", api_error::catchers())
         .mount("/", root::routes())
         .mount("/auth", auth::routes())
         .mount("/system", system::routes())
         .mount("/decks", deck::routes())
+        .mount("/decks", import::routes())
         .mount("/cards", card::routes())
+        .mount("/plugins", plugin::routes())
+        .mount("/search", search::routes())
+        .mount("/stats", stats::routes())
 }
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +8,49 @@ use serde::{Deserialize, Serialize};
 pub struct Deck {
     pub id: i32,
     pub name: String,
+    pub owner_id: i32,
+    pub is_public: bool,
+    pub created_at: DateTime<Utc>,
+    /// Deck this one is a sub-deck of, `None` for top-level decks
+    pub parent_id: Option<i32>,
+}
+
+/// Deck as sent by clients: the owner is always the authenticated user
+#[derive(Debug, Deserialize)]
+pub struct NewDeck {
+    pub name: String,
+    #[serde(default)]
+    pub is_public: bool,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::deck)]
+pub struct InsertableDeck {
+    pub name: String,
+    pub owner_id: i32,
+    pub is_public: bool,
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::deck)]
+pub struct DeckChanges {
+    pub name: Option<String>,
+    pub is_public: Option<bool>,
+    /// `null` moves the deck to the top level, a missing field leaves it where it is
+    #[serde(default, deserialize_with = "present")]
+    pub parent_id: Option<Option<i32>>,
+}
+
+/// Tells a field set to `null` apart from a missing one
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Deserialize, Insertable, Serialize, Queryable)]
@@ -15,9 +59,19 @@ pub struct Deck {
 pub struct Plugin {
     pub id: i32,
     pub name: String,
+    pub version: String,
+    pub searchable_fields: Vec<String>,
 }
 
-#[derive(Debug, Insertable, Deserialize, Selectable, Serialize, Queryable)]
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::plugin)]
+pub struct NewPlugin<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub searchable_fields: &'a [String],
+}
+
+#[derive(Debug, Insertable, Deserialize, Selectable, Serialize, Queryable, QueryableByName)]
 #[diesel(table_name = crate::schema::card)]
 #[diesel(belongs_to(Deck), belongs_to(Plugin))]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -29,8 +83,103 @@ pub struct Card {
     pub plugin_data: serde_json::Value,
 }
 
+/// Card as sent by clients: the plugin is referenced by name only
+#[derive(Debug, Deserialize)]
+pub struct NewCard {
+    pub deck_id: i32,
+    pub plugin_name: String,
+    pub plugin_data: serde_json::Value,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::card)]
+pub struct InsertableCard {
+    pub deck_id: i32,
+    pub plugin_id: i32,
+    pub plugin_name: String,
+    pub plugin_data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CardChanges {
+    pub deck_id: Option<i32>,
+    pub plugin_name: Option<String>,
+    pub plugin_data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = crate::schema::card)]
+pub struct CardChangeset {
+    pub deck_id: Option<i32>,
+    pub plugin_id: Option<i32>,
+    pub plugin_name: Option<String>,
+    pub plugin_data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::asset)]
+pub struct NewAsset {
+    pub deck_id: i32,
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::session)]
+pub struct NewSession {
+    pub token_hash: String,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct DeckWithCards {
     pub deck: Deck,
     pub card_ids: Vec<i32>,
+    /// Direct sub-decks the user can see
+    pub sub_decks: Vec<Deck>,
+    /// Visible decks below this one, at any depth
+    pub descendant_count: i64,
+    /// Cards of this deck and of all its visible descendants
+    pub total_card_count: i64,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::tag)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Insertable, Deserialize, Selectable, Serialize, Queryable, AsChangeset)]
+#[diesel(table_name = crate::schema::review_state)]
+#[diesel(primary_key(user_id, card_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ReviewState {
+    pub user_id: i32,
+    pub card_id: i32,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+    pub lapses: i32,
+    pub due_at: DateTime<Utc>,
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// One entry of the append-only review history
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::review_log)]
+pub struct NewReviewLog {
+    pub user_id: i32,
+    pub card_id: i32,
+    pub grade: String,
+    pub reason: Option<String>,
+    pub duration_ms: Option<i32>,
+    pub score: Option<i32>,
+    pub reviewed_at: DateTime<Utc>,
 }
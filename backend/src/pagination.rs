@@ -0,0 +1,78 @@
+use rocket::FromFormField;
+use serde::Serialize;
+
+use crate::utils::api_error::ApiError;
+
+pub const DEFAULT_PER_PAGE: i64 = 50;
+pub const MAX_PER_PAGE: i64 = 200;
+
+/// Offset-based pagination read from the `page` (starting at 1) and `per_page` query params
+#[derive(Clone, Copy, Debug)]
+pub struct Pagination {
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Pagination {
+    pub fn new(page: Option<i64>, per_page: Option<i64>) -> Result<Self, ApiError> {
+        let page = page.unwrap_or(1);
+        let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page < 1 {
+            return Err(ApiError::Validation("page must be at least 1".to_string()));
+        }
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(ApiError::Validation(format!(
+                "per_page must be between 1 and {MAX_PER_PAGE}"
+            )));
+        }
+        // Rejected here, so that `offset` cannot overflow
+        if (page - 1).checked_mul(per_page).is_none() {
+            return Err(ApiError::Validation("page is too large".to_string()));
+        }
+        Ok(Pagination { page, per_page })
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.per_page
+    }
+}
+
+/// One page of a listing, with what clients need to request the others
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    /// Number of items across all pages
+    pub total: i64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, pagination: Pagination, total: i64) -> Self {
+        Page {
+            items,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Escapes `%`, `_` and `\` so user input matches literally in a `LIKE` pattern
+pub fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
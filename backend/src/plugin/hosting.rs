@@ -0,0 +1,237 @@
+use rand::{Rng, distr::Alphanumeric, rng};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::uri::Absolute;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use serde::Deserialize;
+
+use super::manifest::{Capability, PluginManifest};
+
+const DEFAULT_PATH_PREFIX: &str = "/api/plugin";
+const NONCE_LENGTH: usize = 22;
+
+/// Where browsers load the plugins from, the `plugins` table of the Rocket config.
+///
+/// Plugins run someone else's code, so the API must not trust what they send: requests coming
+/// from a plugin page are refused by the authentication guards, see
+/// [`PluginHosting::is_plugin_request`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PluginHosting {
+    /// Origin the plugins are served from instead of the one of the API, e.g.
+    /// `https://plugins.flash-qc.example`. Its requests are never authorized.
+    pub origin: Option<String>,
+    /// Path of the plugin routes as seen by browsers, behind the reverse proxy
+    pub path_prefix: String,
+}
+
+impl Default for PluginHosting {
+    fn default() -> Self {
+        PluginHosting {
+            origin: None,
+            path_prefix: DEFAULT_PATH_PREFIX.to_string(),
+        }
+    }
+}
+
+impl PluginHosting {
+    /// Reads the `plugins` table of the config when Rocket ignites, and refuses to launch if it
+    /// is invalid
+    pub fn fairing() -> impl Fairing {
+        AdHoc::try_on_ignite("Plugin hosting", |rocket| async {
+            let hosting = match rocket.figment().extract_inner::<PluginHosting>("plugins") {
+                Ok(hosting) => hosting,
+                Err(e) if e.missing() => PluginHosting::default(),
+                Err(e) => {
+                    rocket::error!("Invalid plugins config: {e}");
+                    return Err(rocket);
+                }
+            };
+            match hosting.validated() {
+                Ok(hosting) => Ok(rocket.manage(hosting)),
+                Err(e) => {
+                    rocket::error!("Invalid plugins config: {e}");
+                    Err(rocket)
+                }
+            }
+        })
+    }
+
+    fn validated(mut self) -> Result<Self, String> {
+        if let Some(origin) = &self.origin {
+            let valid = Absolute::parse(origin).is_ok_and(|uri| {
+                matches!(uri.scheme(), "http" | "https")
+                    && uri.authority().is_some()
+                    && uri.path().is_empty()
+                    && uri.query().is_none()
+            });
+            if !valid {
+                return Err(format!(
+                    "origin '{origin}' must be a scheme and a host, like https://plugins.example.com"
+                ));
+            }
+        }
+        let prefix = self.path_prefix.trim_end_matches('/');
+        if !prefix.starts_with('/') {
+            return Err(format!(
+                "path_prefix '{}' must start with /",
+                self.path_prefix
+            ));
+        }
+        self.path_prefix = prefix.to_string();
+        Ok(self)
+    }
+
+    /// Whether the request was sent by a plugin page rather than by the flash-qc frontend: it
+    /// comes from a sandboxed document (origin `null`), from the plugin origin, or from a page
+    /// under the plugin path prefix
+    pub fn is_plugin_request(&self, req: &Request<'_>) -> bool {
+        let headers = req.headers();
+        let from_origin = headers
+            .get_one("Origin")
+            .is_some_and(|origin| origin == "null" || Some(origin) == self.origin.as_deref());
+        let to_plugin_host = self
+            .origin
+            .as_deref()
+            .and_then(host_of)
+            .is_some_and(|plugin_host| {
+                req.host()
+                    .is_some_and(|host| host.to_string().eq_ignore_ascii_case(plugin_host))
+            });
+        if from_origin || to_plugin_host {
+            return true;
+        }
+        headers
+            .get_one("Referer")
+            .and_then(|referer| Absolute::parse(referer).ok())
+            .is_some_and(|referer| {
+                let path = referer.path().as_str();
+                path == self.path_prefix || path.starts_with(&format!("{}/", self.path_prefix))
+            })
+    }
+}
+
+/// `host[:port]` of an origin
+fn host_of(origin: &str) -> Option<&str> {
+    origin.split_once("://").map(|(_, host)| host)
+}
+
+/// The plugin URLs as the browser sees them. They are absolute: pages loaded with `srcdoc`
+/// have no URL of their own to resolve relative ones against.
+pub struct PluginSite<'r> {
+    hosting: &'r PluginHosting,
+    /// `None` when the request does not tell its host
+    origin: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PluginSite<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let hosting = req
+            .rocket()
+            .state::<PluginHosting>()
+            .expect("the plugin hosting fairing is attached");
+        let origin = hosting.origin.clone().or_else(|| {
+            // The reverse proxy terminates TLS
+            let scheme = req
+                .headers()
+                .get_one("X-Forwarded-Proto")
+                .filter(|scheme| matches!(*scheme, "http" | "https"))
+                .unwrap_or("http");
+            req.host().map(|host| format!("{scheme}://{host}"))
+        });
+        Outcome::Success(PluginSite { hosting, origin })
+    }
+}
+
+impl PluginSite<'_> {
+    /// URL of the folder of a plugin, for the `<base>` tag of its page
+    pub fn base_url(&self, plugin_name: &str) -> String {
+        format!(
+            "{}{}/{}/",
+            self.origin.as_deref().unwrap_or_default(),
+            self.hosting.path_prefix,
+            plugin_name
+        )
+    }
+
+    /// Policy of the pages and files of a plugin: it loads nothing but its own files, and only
+    /// gets what its manifest asks for among the [`Capability`]s. `nonce` allows the inline
+    /// scripts of the entry page.
+    pub fn content_security_policy(
+        &self,
+        manifest: &PluginManifest,
+        nonce: Option<&str>,
+    ) -> String {
+        let own_files = if self.origin.is_some() {
+            self.base_url(&manifest.name)
+        } else {
+            "'self'".to_string()
+        };
+        let has = |capability| manifest.capabilities.contains(&capability);
+
+        let mut script_src = own_files.clone();
+        if let Some(nonce) = nonce {
+            script_src.push_str(&format!(" 'nonce-{nonce}'"));
+        }
+        if has(Capability::WasmUnsafeEval) {
+            script_src.push_str(" 'wasm-unsafe-eval'");
+        }
+        let connect_src = if has(Capability::Network) {
+            format!("{own_files} https:")
+        } else {
+            own_files.clone()
+        };
+        let worker_src = if has(Capability::Workers) {
+            format!("{own_files} blob:")
+        } else {
+            "'none'".to_string()
+        };
+
+        [
+            "default-src 'none'".to_string(),
+            format!("script-src {script_src}"),
+            format!("style-src {own_files} 'unsafe-inline'"),
+            format!("img-src {own_files} data: blob:"),
+            format!("font-src {own_files}"),
+            format!("connect-src {connect_src}"),
+            format!("worker-src {worker_src}"),
+            format!("base-uri {own_files}"),
+            "form-action 'none'".to_string(),
+        ]
+        .join("; ")
+    }
+}
+
+/// Random value allowing the inline scripts of one response
+pub fn script_nonce() -> String {
+    rng()
+        .sample_iter(&Alphanumeric)
+        .take(NONCE_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// A plugin response along with its Content-Security-Policy. The header also puts the page in a
+/// sandbox with an opaque origin, so it cannot read the storage nor the cookies of flash-qc even
+/// when opened outside of the frontend.
+pub struct Sandboxed<R> {
+    pub inner: R,
+    pub policy: String,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Sandboxed<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.inner.respond_to(req)?)
+            .raw_header(
+                "Content-Security-Policy",
+                format!("{}; sandbox allow-scripts", self.policy),
+            )
+            // Sandboxed pages load their module scripts and WebAssembly with CORS requests
+            .raw_header("Access-Control-Allow-Origin", "*")
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .ok()
+    }
+}
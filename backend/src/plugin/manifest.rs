@@ -0,0 +1,161 @@
+use flashqc_plugin_protocol::{HostMessage, PluginMessage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+use super::is_valid_plugin_name;
+use super::schema::DataSchema;
+
+pub const MANIFEST_FILE: &str = "plugin.json";
+
+/// Features a plugin needs from the browser, beyond rendering its own HTML, CSS and JS
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    /// Perform HTTP requests
+    Network,
+    /// Compile and run WebAssembly modules
+    WasmUnsafeEval,
+    /// Start web workers
+    Workers,
+}
+
+/// postMessage types exchanged between the plugin iframe and the host page, from the
+/// `flashqc-plugin-protocol` crate
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PluginMessages {
+    #[serde(default)]
+    pub sends: Vec<String>,
+    #[serde(default)]
+    pub receives: Vec<String>,
+}
+
+/// Contents of the `plugin.json` file every plugin ships in its folder
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// HTML page rendered in the iframe, relative to the plugin folder
+    pub entry: String,
+    /// JSON Schema of the card `plugin_data`, relative to the plugin folder
+    pub data_schema: String,
+    /// Top-level string fields of `plugin_data` indexed for full-text search
+    #[serde(default)]
+    pub searchable: Vec<String>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub messages: PluginMessages,
+}
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("cannot read {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("cannot parse {0}: {1}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("{0}: {1}")]
+    Invalid(PathBuf, String),
+}
+
+/// Version in the `MAJOR.MINOR.PATCH` form
+fn is_valid_version(version: &str) -> bool {
+    let parts: Vec<&str> = version.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Paths in a manifest must point inside the plugin folder
+fn is_relative_file_path(path: &str) -> bool {
+    let path = Path::new(path);
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+impl PluginManifest {
+    /// Reads and validates the manifest found in `plugin_dir`. The data schema is compiled,
+    /// so a plugin with a broken schema is rejected as a whole.
+    pub fn load(plugin_dir: &Path) -> Result<(PluginManifest, DataSchema), ManifestError> {
+        let path = plugin_dir.join(MANIFEST_FILE);
+        let invalid = |message: String| ManifestError::Invalid(path.clone(), message);
+
+        let contents = fs::read_to_string(&path).map_err(|e| ManifestError::Io(path.clone(), e))?;
+        let manifest: PluginManifest =
+            serde_json::from_str(&contents).map_err(|e| ManifestError::Parse(path.clone(), e))?;
+
+        if !is_valid_plugin_name(&manifest.name) {
+            return Err(invalid(format!(
+                "invalid plugin name '{}': only lowercase letters, digits and '-' are allowed",
+                manifest.name
+            )));
+        }
+        let dir_name = plugin_dir.file_name().and_then(|name| name.to_str());
+        if dir_name != Some(manifest.name.as_str()) {
+            return Err(invalid(format!(
+                "plugin name '{}' does not match its folder name",
+                manifest.name
+            )));
+        }
+        if !is_valid_version(&manifest.version) {
+            return Err(invalid(format!(
+                "invalid version '{}': expected MAJOR.MINOR.PATCH",
+                manifest.version
+            )));
+        }
+        for (field, file) in [
+            ("entry", &manifest.entry),
+            ("data_schema", &manifest.data_schema),
+        ] {
+            if !is_relative_file_path(file) {
+                return Err(invalid(format!(
+                    "{field} '{file}' must be a path inside the plugin folder"
+                )));
+            }
+            if !plugin_dir.join(file).is_file() {
+                return Err(invalid(format!("{field} '{file}' does not exist")));
+            }
+        }
+
+        for (field, messages, known) in [
+            ("sends", &manifest.messages.sends, PluginMessage::TYPES),
+            ("receives", &manifest.messages.receives, HostMessage::TYPES),
+        ] {
+            if let Some(unknown) = messages
+                .iter()
+                .find(|message| !known.contains(&message.as_str()))
+            {
+                return Err(invalid(format!(
+                    "messages.{field} lists '{unknown}', which is not part of the plugin protocol"
+                )));
+            }
+        }
+
+        let schema_path = plugin_dir.join(&manifest.data_schema);
+        let schema = fs::read_to_string(&schema_path)
+            .map_err(|e| ManifestError::Io(schema_path.clone(), e))?;
+        let schema: serde_json::Value = serde_json::from_str(&schema)
+            .map_err(|e| ManifestError::Parse(schema_path.clone(), e))?;
+        for field in &manifest.searchable {
+            let field_type = schema
+                .pointer(&format!("/properties/{field}/type"))
+                .and_then(|field_type| field_type.as_str());
+            if field_type != Some("string") {
+                return Err(invalid(format!(
+                    "searchable field '{field}' is not a string property of the data schema"
+                )));
+            }
+        }
+        let schema = DataSchema::compile(schema)
+            .map_err(|e| invalid(format!("invalid data schema: {e}")))?;
+
+        Ok((manifest, schema))
+    }
+}
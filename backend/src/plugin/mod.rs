@@ -0,0 +1,23 @@
+mod hosting;
+mod manifest;
+mod registry;
+mod routes;
+mod schema;
+
+pub use hosting::{PluginHosting, PluginSite, Sandboxed, script_nonce};
+pub use manifest::{Capability, ManifestError, PluginManifest, PluginMessages};
+pub use registry::{PluginRegistry, RegisteredPlugin};
+pub use routes::routes;
+pub use schema::{DataSchema, SchemaViolation};
+
+/// Folder the plugins are served from, relative to the working directory of the backend
+pub(crate) const PLUGINS_DIR: &str = "plugins";
+
+/// Validates that a plugin name contains only lowercase alphanumeric characters.
+/// This prevents path traversal attacks and ensures safe filesystem operations.
+pub(crate) fn is_valid_plugin_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
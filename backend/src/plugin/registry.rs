@@ -0,0 +1,146 @@
+use diesel::prelude::*;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::tokio::task::spawn_blocking;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::PLUGINS_DIR;
+use super::manifest::PluginManifest;
+use super::schema::DataSchema;
+use crate::models::NewPlugin;
+use crate::schema::sql_types::Tsvector;
+use crate::schema::{card, plugin};
+use crate::utils::db::DbPool;
+
+diesel::define_sql_function! {
+    /// Defined in the `card_search` migration, also used by the trigger indexing new cards
+    fn card_search_vector(
+        plugin_data: diesel::sql_types::Jsonb,
+        fields: diesel::sql_types::Array<diesel::sql_types::Text>,
+    ) -> Tsvector;
+}
+
+/// A plugin found in the plugins folder, with its row in the `plugin` table
+pub struct RegisteredPlugin {
+    pub id: i32,
+    pub manifest: PluginManifest,
+    pub schema: DataSchema,
+}
+
+/// Plugins available in this backend, loaded from their manifests at startup. Clones share the
+/// plugins, e.g. to hand them to a blocking task.
+#[derive(Clone)]
+pub struct PluginRegistry {
+    plugins: Arc<BTreeMap<String, RegisteredPlugin>>,
+}
+
+impl PluginRegistry {
+    /// Loads the manifest of every folder in `plugins_dir`, and upserts them in the `plugin`
+    /// table so that the database always knows the plugins that are actually served
+    pub fn load(
+        plugins_dir: &Path,
+        db_connection: &mut PgConnection,
+    ) -> Result<PluginRegistry, String> {
+        let entries = fs::read_dir(plugins_dir)
+            .map_err(|e| format!("cannot read {}: {e}", plugins_dir.display()))?;
+
+        let mut manifests = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if !entry.path().is_dir() {
+                continue;
+            }
+            let loaded = PluginManifest::load(&entry.path()).map_err(|e| e.to_string())?;
+            manifests.push(loaded);
+        }
+
+        let plugins = db_connection
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let mut plugins = BTreeMap::new();
+                for (manifest, schema) in manifests {
+                    let previous_fields: Option<Vec<String>> = plugin::table
+                        .filter(plugin::name.eq(&manifest.name))
+                        .select(plugin::searchable_fields)
+                        .first(conn)
+                        .optional()?;
+                    let row = NewPlugin {
+                        name: &manifest.name,
+                        version: &manifest.version,
+                        searchable_fields: &manifest.searchable,
+                    };
+                    let id: i32 = diesel::insert_into(plugin::table)
+                        .values(&row)
+                        .on_conflict(plugin::name)
+                        .do_update()
+                        .set(&row)
+                        .returning(plugin::id)
+                        .get_result(conn)?;
+
+                    // The search index of existing cards follows the fields of the manifest
+                    if previous_fields.as_ref() != Some(&manifest.searchable) {
+                        let reindexed =
+                            diesel::update(card::table.filter(card::plugin_id.eq(id)))
+                                .set(card::search_vector.eq(card_search_vector(
+                                    card::plugin_data,
+                                    &manifest.searchable,
+                                )))
+                                .execute(conn)?;
+                        rocket::info!("Reindexed {} cards of plugin {}", reindexed, manifest.name);
+                    }
+
+                    rocket::info!("Registered plugin {} v{}", manifest.name, manifest.version);
+                    plugins.insert(
+                        manifest.name.clone(),
+                        RegisteredPlugin {
+                            id,
+                            manifest,
+                            schema,
+                        },
+                    );
+                }
+                Ok(plugins)
+            })
+            .map_err(|e| format!("cannot sync plugin table: {e}"))?;
+
+        Ok(PluginRegistry {
+            plugins: Arc::new(plugins),
+        })
+    }
+
+    /// Loads the registry when Rocket ignites, and refuses to launch if any manifest is invalid
+    pub fn fairing() -> impl Fairing {
+        AdHoc::try_on_ignite("Plugin registry", |rocket| async {
+            let Some(pool) = rocket.state::<DbPool>() else {
+                rocket::error!("Failed to load plugins: the database pool is not attached");
+                return Err(rocket);
+            };
+            let pool = pool.clone();
+            // Reading the manifests and syncing the table block, keep them off the async workers
+            let loaded = spawn_blocking(move || {
+                let mut db_connection = pool
+                    .get()
+                    .map_err(|e| format!("no database connection: {e}"))?;
+                PluginRegistry::load(Path::new(PLUGINS_DIR), &mut db_connection)
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("loading task failed: {e}")));
+            match loaded {
+                Ok(registry) => Ok(rocket.manage(registry)),
+                Err(e) => {
+                    rocket::error!("Failed to load plugins: {e}");
+                    Err(rocket)
+                }
+            }
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredPlugin> {
+        self.plugins.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredPlugin> {
+        self.plugins.values()
+    }
+}
@@ -0,0 +1,54 @@
+use rocket::serde::json::Json;
+use rocket::{Route, State, get, routes as rocket_routes};
+use serde::Serialize;
+
+use super::manifest::PluginManifest;
+use super::registry::{PluginRegistry, RegisteredPlugin};
+use crate::utils::api_error::ApiError;
+
+#[derive(Serialize)]
+struct PluginSummary<'a> {
+    id: i32,
+    #[serde(flatten)]
+    manifest: &'a PluginManifest,
+}
+
+impl<'a> From<&'a RegisteredPlugin> for PluginSummary<'a> {
+    fn from(plugin: &'a RegisteredPlugin) -> Self {
+        PluginSummary {
+            id: plugin.id,
+            manifest: &plugin.manifest,
+        }
+    }
+}
+
+/// A plugin together with the JSON Schema its card data must follow
+#[derive(Serialize)]
+struct PluginDetails<'a> {
+    #[serde(flatten)]
+    summary: PluginSummary<'a>,
+    schema: &'a serde_json::Value,
+}
+
+#[get("/")]
+fn get_plugins(registry: &State<PluginRegistry>) -> Json<Vec<PluginSummary<'_>>> {
+    Json(registry.iter().map(PluginSummary::from).collect())
+}
+
+#[get("/<name>")]
+fn get_plugin<'r>(
+    name: &str,
+    registry: &'r State<PluginRegistry>,
+) -> Result<Json<PluginDetails<'r>>, ApiError> {
+    let plugin = registry
+        .get(name)
+        .ok_or_else(|| ApiError::NotFound(format!("Plugin {} is not installed", name)))?;
+    Ok(Json(PluginDetails {
+        summary: PluginSummary::from(plugin),
+        schema: plugin.schema.as_json(),
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket_routes![get_plugin, get_plugins]
+}
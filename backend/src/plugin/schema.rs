@@ -0,0 +1,56 @@
+use jsonschema::Validator;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// One place where the card data does not match the plugin schema
+#[derive(Debug, Serialize)]
+pub struct SchemaViolation {
+    /// JSON pointer to the failing value, e.g. `/scramble`. Empty for the document root.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// JSON Schema that the `plugin_data` of every card of a plugin must follow
+pub struct DataSchema {
+    schema: serde_json::Value,
+    validator: Validator,
+}
+
+impl DataSchema {
+    pub fn compile(schema: serde_json::Value) -> Result<Self, String> {
+        let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+        Ok(DataSchema { schema, validator })
+    }
+
+    pub fn as_json(&self) -> &serde_json::Value {
+        &self.schema
+    }
+
+    pub fn validate(&self, plugin_data: &serde_json::Value) -> Result<(), Vec<SchemaViolation>> {
+        let violations: Vec<SchemaViolation> = self
+            .validator
+            .iter_errors(plugin_data)
+            .map(|error| SchemaViolation {
+                path: error.instance_path.to_string(),
+                message: error.to_string(),
+            })
+            .collect();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
@@ -1,9 +1,21 @@
 use rocket::fs::NamedFile;
 use rocket::response::content::RawHtml;
-use rocket::{Route, get, routes as rocket_routes};
+use rocket::{Route, State, get, routes as rocket_routes};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use flashqc_plugin_protocol::{CARD_BOOTSTRAP_ID, HostMessage, encode};
+
+use crate::auth::BearerAuth;
+use crate::card::authorize_card;
+use crate::deck::DeckAccess;
+use crate::models::Card;
+use crate::plugin::{
+    PLUGINS_DIR, PluginRegistry, PluginSite, Sandboxed, is_valid_plugin_name, script_nonce,
+};
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
+
 const DEFAULT_PLUGIN: &str = "flip-word";
 
 #[get("/")]
@@ -11,58 +23,148 @@ pub fn index() -> &'static str {
     "Hello, world!"
 }
 
-/// Validates that a plugin name contains only lowercase alphanumeric characters.
-/// This prevents path traversal attacks and ensures safe filesystem operations.
-fn is_valid_plugin_name(name: &str) -> bool {
-    !name.is_empty()
-        && name
-            .chars()
-            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+#[get("/main-frame")]
+fn main_frame(
+    registry: &State<PluginRegistry>,
+    site: PluginSite<'_>,
+) -> Result<Sandboxed<RawHtml<String>>, ApiError> {
+    inject_base_tag(registry, &site, DEFAULT_PLUGIN, None)
 }
 
-#[get("/main-frame")]
-fn main_frame() -> Option<RawHtml<String>> {
-    inject_base_tag(DEFAULT_PLUGIN)
+fn plugin_not_found(plugin_name: &str) -> ApiError {
+    ApiError::NotFound(format!("Plugin {} is not installed", plugin_name))
 }
 
-fn inject_base_tag(plugin_name: &str) -> Option<RawHtml<String>> {
-    // Validate plugin_name to prevent path traversal
-    if !is_valid_plugin_name(plugin_name) {
-        return None;
-    }
+/// The card as the `cardData` message of the plugin protocol, for the plugin to read from the
+/// page instead of fetching it
+fn card_bootstrap(card: &Card) -> Result<String, ApiError> {
+    let message = encode(HostMessage::CardData {
+        card_id: card.id,
+        data: card.plugin_data.clone(),
+    })
+    .map_err(|e| {
+        eprintln!("Cannot encode card {}: {:?}", card.id, e);
+        ApiError::Internal(format!("Card with id {} cannot be loaded", card.id))
+    })?;
+    // Escaping `<` keeps the data from closing the script element, the JSON is the same
+    Ok(format!(
+        r#"<script type="application/json" id="{CARD_BOOTSTRAP_ID}">{}</script>"#,
+        message.replace('<', "\\u003c")
+    ))
+}
+
+fn inject_base_tag(
+    registry: &PluginRegistry,
+    site: &PluginSite<'_>,
+    plugin_name: &str,
+    card: Option<&Card>,
+) -> Result<Sandboxed<RawHtml<String>>, ApiError> {
+    // Only plugins with a valid manifest are served
+    let plugin = registry
+        .get(plugin_name)
+        .ok_or_else(|| plugin_not_found(plugin_name))?;
 
-    let path = Path::new("plugins").join(plugin_name).join("index.html");
-    let html = fs::read_to_string(path).ok()?;
+    let path = Path::new(PLUGINS_DIR)
+        .join(plugin_name)
+        .join(&plugin.manifest.entry);
+    let html = fs::read_to_string(&path).map_err(|e| {
+        eprintln!("Cannot read {}: {:?}", path.display(), e);
+        ApiError::Internal(format!("Plugin {} cannot be loaded", plugin_name))
+    })?;
 
-    // Inject base tag after <head>
-    let base_tag = format!(r#"<base href="/api/plugin/{}/">"#, plugin_name);
-    let modified_html = html.replace("<head>", &format!("<head>\n  {}", base_tag));
+    let nonce = script_nonce();
+    let policy = site.content_security_policy(&plugin.manifest, Some(&nonce));
+    let html = html.replace("<script", &format!(r#"<script nonce="{nonce}""#));
 
-    Some(RawHtml(modified_html))
+    // Inject the policy and base tag after <head>, along with the card to render. The frontend
+    // loads the page with srcdoc, which ignores the headers of the response.
+    let mut head = format!(
+        r#"<meta http-equiv="Content-Security-Policy" content="{}">
+  <base href="{}">"#,
+        policy,
+        site.base_url(plugin_name)
+    );
+    if let Some(card) = card {
+        head.push_str("\n  ");
+        head.push_str(&card_bootstrap(card)?);
+    }
+    let modified_html = html.replace("<head>", &format!("<head>\n  {}", head));
+
+    Ok(Sandboxed {
+        inner: RawHtml(modified_html),
+        policy,
+    })
 }
 
 #[get("/plugin/<plugin_name>/<file..>", rank = 2)]
-async fn serve_plugin_file(plugin_name: &str, file: PathBuf) -> Option<NamedFile> {
+async fn serve_plugin_file(
+    plugin_name: &str,
+    file: PathBuf,
+    registry: &State<PluginRegistry>,
+    site: PluginSite<'_>,
+) -> Result<Sandboxed<NamedFile>, ApiError> {
     // Validate plugin_name to prevent path traversal
-    if !is_valid_plugin_name(plugin_name) {
-        return None;
-    }
+    let plugin = is_valid_plugin_name(plugin_name)
+        .then(|| registry.get(plugin_name))
+        .flatten()
+        .ok_or_else(|| plugin_not_found(plugin_name))?;
 
+    let not_found = || {
+        ApiError::NotFound(format!(
+            "File {} not found in plugin {}",
+            file.display(),
+            plugin_name
+        ))
+    };
     // Validate file path to prevent path traversal
     if file
         .components()
         .any(|c| matches!(c, std::path::Component::ParentDir))
     {
-        return None;
+        return Err(not_found());
     }
 
-    let path = Path::new("plugins").join(plugin_name).join(file);
-    NamedFile::open(path).await.ok()
+    let path = Path::new(PLUGINS_DIR).join(plugin_name).join(&file);
+    let file = NamedFile::open(path).await.map_err(|_| not_found())?;
+    Ok(Sandboxed {
+        inner: file,
+        policy: site.content_security_policy(&plugin.manifest, None),
+    })
 }
 
-#[get("/plugin/<plugin_name>", rank = 1)]
-fn serve_plugin(plugin_name: &str) -> Option<RawHtml<String>> {
-    inject_base_tag(plugin_name)
+/// Entry page of the plugin, with the card to render when `card_id` is given
+#[get("/plugin/<plugin_name>?<card_id>", rank = 1)]
+async fn serve_plugin(
+    plugin_name: &str,
+    card_id: Option<i32>,
+    registry: &State<PluginRegistry>,
+    site: PluginSite<'_>,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Sandboxed<RawHtml<String>>, ApiError> {
+    let card = match card_id {
+        Some(card_id) => {
+            let card = db_connection
+                .run(move |db_connection| {
+                    authorize_card(
+                        db_connection,
+                        card_id,
+                        user_data.as_ref().map(|auth| &auth.user),
+                        DeckAccess::Read,
+                    )
+                })
+                .await?;
+            if card.plugin_name != plugin_name {
+                return Err(ApiError::Validation(format!(
+                    "Card with id {} is rendered by plugin {}, not {}",
+                    card_id, card.plugin_name, plugin_name
+                )));
+            }
+            Some(card)
+        }
+        None => None,
+    };
+    inject_base_tag(registry, &site, plugin_name, card.as_ref())
 }
 
 pub fn routes() -> Vec<Route> {
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::ReviewState;
+
+const INITIAL_EASE_FACTOR: f64 = 2.5;
+const MINIMUM_EASE_FACTOR: f64 = 1.3;
+/// About a century. Intervals grow exponentially with each success, and would soon go past the
+/// dates chrono and Postgres can represent.
+const MAX_INTERVAL_DAYS: i32 = 36500;
+
+/// How well the user remembered a card, from worst to best
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Grade::Again => "again",
+            Grade::Hard => "hard",
+            Grade::Good => "good",
+            Grade::Easy => "easy",
+        }
+    }
+
+    /// SM-2 response quality, in the 0..=5 range
+    fn quality(self) -> f64 {
+        match self {
+            Grade::Again => 1.0,
+            Grade::Hard => 3.0,
+            Grade::Good => 4.0,
+            Grade::Easy => 5.0,
+        }
+    }
+}
+
+/// Reason sent by a plugin in its `finish` message (see `frontend/src/models/plugin-message.ts`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinishReason {
+    Success,
+    Failure,
+    Timeout,
+}
+
+impl FinishReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FinishReason::Success => "success",
+            FinishReason::Failure => "failure",
+            FinishReason::Timeout => "timeout",
+        }
+    }
+}
+
+impl From<FinishReason> for Grade {
+    fn from(reason: FinishReason) -> Self {
+        match reason {
+            FinishReason::Success => Grade::Good,
+            FinishReason::Timeout => Grade::Hard,
+            FinishReason::Failure => Grade::Again,
+        }
+    }
+}
+
+/// A review can either carry an explicit grade, or the raw outcome reported by a plugin
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ReviewOutcome {
+    Graded { grade: Grade },
+    Finished { reason: FinishReason },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    #[serde(flatten)]
+    pub outcome: ReviewOutcome,
+    /// Time the user spent on the card, in milliseconds
+    #[serde(default)]
+    pub duration_ms: Option<i32>,
+    /// `statusCode` of the plugin `finish` message, its meaning is up to the plugin
+    #[serde(default)]
+    pub score: Option<i32>,
+}
+
+impl ReviewRequest {
+    pub fn grade(&self) -> Grade {
+        match self.outcome {
+            ReviewOutcome::Graded { grade } => grade,
+            ReviewOutcome::Finished { reason } => reason.into(),
+        }
+    }
+
+    pub fn reason(&self) -> Option<FinishReason> {
+        match self.outcome {
+            ReviewOutcome::Graded { .. } => None,
+            ReviewOutcome::Finished { reason } => Some(reason),
+        }
+    }
+}
+
+impl ReviewState {
+    /// State of a card the user has never reviewed: due right away
+    pub fn new(user_id: i32, card_id: i32, now: DateTime<Utc>) -> Self {
+        ReviewState {
+            user_id,
+            card_id,
+            ease_factor: INITIAL_EASE_FACTOR,
+            interval_days: 0,
+            repetitions: 0,
+            lapses: 0,
+            due_at: now,
+            last_reviewed_at: None,
+        }
+    }
+
+    /// Updates the state following the SM-2 algorithm
+    pub fn apply_grade(&mut self, grade: Grade, now: DateTime<Utc>) {
+        let quality = grade.quality();
+
+        if quality < 3.0 {
+            // Forgotten: start the repetitions over
+            self.repetitions = 0;
+            self.interval_days = 1;
+            if self.last_reviewed_at.is_some() {
+                self.lapses += 1;
+            }
+        } else {
+            self.repetitions += 1;
+            self.interval_days = match self.repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval_days as f64 * self.ease_factor)
+                    .round()
+                    .min(MAX_INTERVAL_DAYS.into()) as i32,
+            };
+        }
+
+        let distance = 5.0 - quality;
+        self.ease_factor =
+            (self.ease_factor + 0.1 - distance * (0.08 + distance * 0.02)).max(MINIMUM_EASE_FACTOR);
+        // Only `now` itself can be out of range here, a card is then due right away
+        self.due_at = now
+            .checked_add_signed(Duration::days(self.interval_days.into()))
+            .unwrap_or(now);
+        self.last_reviewed_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_the_interval() {
+        let now = Utc::now();
+        let mut state = ReviewState::new(1, 1, now);
+        for _ in 0..100 {
+            state.apply_grade(Grade::Easy, now);
+        }
+        assert_eq!(state.interval_days, MAX_INTERVAL_DAYS);
+        assert_eq!(state.due_at, now + Duration::days(MAX_INTERVAL_DAYS.into()));
+    }
+
+    #[test]
+    fn starts_over_after_a_lapse() {
+        let now = Utc::now();
+        let mut state = ReviewState::new(1, 1, now);
+        for grade in [Grade::Good, Grade::Good, Grade::Good] {
+            state.apply_grade(grade, now);
+        }
+        assert_eq!(state.interval_days, 15);
+        state.apply_grade(Grade::Again, now);
+        assert_eq!((state.interval_days, state.repetitions), (1, 0));
+        assert_eq!(state.lapses, 1);
+        assert!(state.ease_factor >= MINIMUM_EASE_FACTOR);
+    }
+}
@@ -1,12 +1,39 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
 diesel::table! {
+    asset (id) {
+        id -> Int4,
+        deck_id -> Int4,
+        filename -> Text,
+        content_type -> Text,
+        data -> Bytea,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     card (id) {
         id -> Int4,
         deck_id -> Int4,
         plugin_id -> Int4,
         plugin_name -> Text,
         plugin_data -> Jsonb,
+        search_vector -> Tsvector,
+    }
+}
+
+diesel::table! {
+    card_tag (card_id, tag_id) {
+        card_id -> Int4,
+        tag_id -> Int4,
     }
 }
 
@@ -14,6 +41,10 @@ diesel::table! {
     deck (id) {
         id -> Int4,
         name -> Text,
+        owner_id -> Int4,
+        is_public -> Bool,
+        created_at -> Timestamptz,
+        parent_id -> Nullable<Int4>,
     }
 }
 
@@ -30,10 +61,77 @@ diesel::table! {
     plugin (id) {
         id -> Int4,
         name -> Text,
+        version -> Text,
+        searchable_fields -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    review_log (id) {
+        id -> Int4,
+        user_id -> Int4,
+        card_id -> Int4,
+        grade -> Text,
+        reason -> Nullable<Text>,
+        duration_ms -> Nullable<Int4>,
+        score -> Nullable<Int4>,
+        reviewed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    review_state (user_id, card_id) {
+        user_id -> Int4,
+        card_id -> Int4,
+        ease_factor -> Float8,
+        interval_days -> Int4,
+        repetitions -> Int4,
+        lapses -> Int4,
+        due_at -> Timestamptz,
+        last_reviewed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    session (id) {
+        id -> Int4,
+        token_hash -> Text,
+        user_id -> Int4,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        last_used_at -> Timestamptz,
+        user_agent -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    tag (id) {
+        id -> Int4,
+        name -> Text,
     }
 }
 
+diesel::joinable!(asset -> deck (deck_id));
 diesel::joinable!(card -> deck (deck_id));
 diesel::joinable!(card -> plugin (plugin_id));
+diesel::joinable!(card_tag -> card (card_id));
+diesel::joinable!(card_tag -> tag (tag_id));
+diesel::joinable!(deck -> flashqc_user (owner_id));
+diesel::joinable!(review_log -> card (card_id));
+diesel::joinable!(review_log -> flashqc_user (user_id));
+diesel::joinable!(review_state -> card (card_id));
+diesel::joinable!(review_state -> flashqc_user (user_id));
+diesel::joinable!(session -> flashqc_user (user_id));
 
-diesel::allow_tables_to_appear_in_same_query!(card, deck, flashqc_user, plugin,);
+diesel::allow_tables_to_appear_in_same_query!(
+    asset,
+    card,
+    card_tag,
+    deck,
+    flashqc_user,
+    plugin,
+    review_log,
+    review_state,
+    session,
+    tag,
+);
@@ -0,0 +1,88 @@
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Float4, Integer, Nullable, Text};
+use rocket::serde::json::Json;
+use rocket::{Route, get, routes as rocket_routes};
+use serde::Serialize;
+
+use crate::auth::BearerAuth;
+use crate::models::Card;
+use crate::pagination::{Page, Pagination};
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
+
+/// A card matching the search, with the deck it belongs to
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct SearchResult {
+    #[diesel(embed)]
+    #[serde(flatten)]
+    pub card: Card,
+    #[diesel(sql_type = Text)]
+    pub deck_name: String,
+    /// Relevance of the card for the query, higher is better
+    #[diesel(sql_type = Float4)]
+    pub rank: f32,
+}
+
+#[derive(QueryableByName)]
+struct Total {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+}
+
+/// Cards matching `query` in the searchable fields declared by their plugin manifest,
+/// `query` uses the web search syntax: `"exact phrase"`, `or`, `-excluded`
+const MATCHING_CARDS: &str = "FROM card \
+    JOIN deck ON deck.id = card.deck_id, \
+    WEBSEARCH_TO_TSQUERY('simple', $1) query \
+    WHERE card.search_vector @@ query \
+    AND (deck.is_public OR deck.owner_id = $2)";
+
+/// Full-text search over the cards of the decks the caller can see, best matches first
+#[get("/?<q>&<page>&<per_page>")]
+async fn search(
+    q: String,
+    page: Option<i64>,
+    per_page: Option<i64>,
+    user_data: Option<BearerAuth>,
+    db_connection: DbConn,
+) -> Result<Json<Page<SearchResult>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let pagination = Pagination::new(page, per_page)?;
+            let q = q.trim();
+            if q.is_empty() {
+                return Err(ApiError::Validation(
+                    "Search query cannot be empty".to_string(),
+                ));
+            }
+            let user_id = user_data.map(|auth| auth.user.id);
+
+            let load = |conn: &mut PgConnection| -> QueryResult<Page<SearchResult>> {
+                let total = diesel::sql_query(format!("SELECT COUNT(*) AS total {MATCHING_CARDS}"))
+                    .bind::<Text, _>(q)
+                    .bind::<Nullable<Integer>, _>(user_id)
+                    .get_result::<Total>(conn)?
+                    .total;
+                let results = diesel::sql_query(format!(
+                    "SELECT card.id, card.deck_id, card.plugin_id, card.plugin_name, card.plugin_data, \
+                     deck.name AS deck_name, TS_RANK(card.search_vector, query) AS rank \
+                     {MATCHING_CARDS} ORDER BY rank DESC, card.id LIMIT $3 OFFSET $4"
+                ))
+                .bind::<Text, _>(q)
+                .bind::<Nullable<Integer>, _>(user_id)
+                .bind::<BigInt, _>(pagination.limit())
+                .bind::<BigInt, _>(pagination.offset())
+                .load(conn)?;
+                Ok(Page::new(results, pagination, total))
+            };
+            load(db_connection).map(Json).map_err(|e| {
+                eprintln!("Database error searching cards: {:?}", e);
+                ApiError::Internal("Failed to search cards".to_string())
+            })
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket_routes![search]
+}
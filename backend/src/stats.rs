@@ -0,0 +1,315 @@
+use chrono::{Days, NaiveDate, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Double, Integer, Nullable, Text};
+use rocket::serde::json::Json;
+use rocket::{Route, get, routes as rocket_routes};
+use serde::Serialize;
+
+use crate::auth::BearerAuth;
+use crate::models::Card;
+use crate::utils::api_error::ApiError;
+use crate::utils::db::DbConn;
+
+const DEFAULT_DAYS: i32 = 30;
+const MAX_DAYS: i32 = 365;
+const DEFAULT_HARDEST_LIMIT: i64 = 10;
+const MAX_HARDEST_LIMIT: i64 = 100;
+
+/// Number of days the statistics look back, today included
+fn period(days: Option<i32>) -> Result<i32, ApiError> {
+    let days = days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(ApiError::Validation(format!(
+            "days must be between 1 and {MAX_DAYS}"
+        )));
+    }
+    Ok(days)
+}
+
+/// Share of the reviews where the card was remembered, `None` without reviews
+fn retention(reviews: i64, remembered: i64) -> Option<f64> {
+    (reviews > 0).then(|| remembered as f64 / reviews as f64)
+}
+
+fn internal_error(e: diesel::result::Error) -> ApiError {
+    eprintln!("Database error loading statistics: {:?}", e);
+    ApiError::Internal("Failed to load statistics".to_string())
+}
+
+#[derive(QueryableByName)]
+struct DeckReviewCounts {
+    #[diesel(sql_type = Integer)]
+    deck_id: i32,
+    #[diesel(sql_type = Text)]
+    deck_name: String,
+    #[diesel(sql_type = BigInt)]
+    reviews: i64,
+    #[diesel(sql_type = BigInt)]
+    remembered: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeckRetention {
+    pub deck_id: i32,
+    pub deck_name: String,
+    pub reviews: i64,
+    /// Reviews not graded `again`
+    pub remembered: i64,
+    pub retention: Option<f64>,
+}
+
+/// Retention of the user in each deck they reviewed during the last `days` days
+#[get("/decks?<days>")]
+async fn deck_retention(
+    days: Option<i32>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<DeckRetention>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let days = period(days)?;
+
+            let counts: Vec<DeckReviewCounts> = diesel::sql_query(
+                "SELECT deck.id AS deck_id, deck.name AS deck_name, COUNT(*) AS reviews, \
+                 COUNT(*) FILTER (WHERE review_log.grade <> 'again') AS remembered \
+                 FROM review_log \
+                 JOIN card ON card.id = review_log.card_id \
+                 JOIN deck ON deck.id = card.deck_id \
+                 WHERE review_log.user_id = $1 \
+                 AND review_log.reviewed_at >= NOW() - MAKE_INTERVAL(days => $2) \
+                 AND (deck.is_public OR deck.owner_id = $1) \
+                 GROUP BY deck.id, deck.name \
+                 ORDER BY deck.name, deck.id",
+            )
+            .bind::<Integer, _>(user_data.user.id)
+            .bind::<Integer, _>(days)
+            .load(db_connection)
+            .map_err(internal_error)?;
+
+            Ok(Json(
+                counts
+                    .into_iter()
+                    .map(|counts| DeckRetention {
+                        deck_id: counts.deck_id,
+                        deck_name: counts.deck_name,
+                        reviews: counts.reviews,
+                        remembered: counts.remembered,
+                        retention: retention(counts.reviews, counts.remembered),
+                    })
+                    .collect(),
+            ))
+        })
+        .await
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct DailyReviews {
+    #[diesel(sql_type = Date)]
+    pub day: NaiveDate,
+    #[diesel(sql_type = BigInt)]
+    pub reviews: i64,
+    #[diesel(sql_type = BigInt)]
+    pub remembered: i64,
+}
+
+/// Reviews of the user on each of the last `days` days (UTC), days without reviews included
+#[get("/daily?<days>")]
+async fn daily_reviews(
+    days: Option<i32>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<DailyReviews>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let days = period(days)?;
+
+            diesel::sql_query(
+                "WITH today AS (SELECT (NOW() AT TIME ZONE 'UTC')::DATE AS day) \
+                 SELECT series.day::DATE AS day, COUNT(review_log.id) AS reviews, \
+                 COUNT(review_log.id) FILTER (WHERE review_log.grade <> 'again') AS remembered \
+                 FROM today, GENERATE_SERIES(today.day - ($2 - 1), today.day, INTERVAL '1 day') series (day) \
+                 LEFT JOIN review_log ON review_log.user_id = $1 \
+                 AND (review_log.reviewed_at AT TIME ZONE 'UTC')::DATE = series.day::DATE \
+                 GROUP BY series.day \
+                 ORDER BY series.day",
+            )
+            .bind::<Integer, _>(user_data.user.id)
+            .bind::<Integer, _>(days)
+            .load(db_connection)
+            .map(Json)
+            .map_err(internal_error)
+        })
+        .await
+}
+
+#[derive(QueryableByName)]
+struct ReviewDay {
+    #[diesel(sql_type = Date)]
+    day: NaiveDate,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Streak {
+    /// Consecutive days with reviews up to today, or up to yesterday if the user has not
+    /// reviewed yet today
+    pub current: u32,
+    pub longest: u32,
+    pub last_review_day: Option<NaiveDate>,
+}
+
+impl Streak {
+    /// `days` must be sorted and without duplicates
+    fn from_days(days: &[NaiveDate], today: NaiveDate) -> Self {
+        let mut streak = Streak::default();
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for &day in days {
+            run = match previous {
+                Some(previous) if previous.checked_add_days(Days::new(1)) == Some(day) => run + 1,
+                _ => 1,
+            };
+            streak.longest = streak.longest.max(run);
+            previous = Some(day);
+        }
+        streak.last_review_day = previous;
+        let yesterday = today.checked_sub_days(Days::new(1));
+        if previous == Some(today) || (previous.is_some() && previous == yesterday) {
+            streak.current = run;
+        }
+        streak
+    }
+}
+
+/// Days in a row (UTC) the user reviewed at least one card
+#[get("/streak")]
+async fn streak(user_data: BearerAuth, db_connection: DbConn) -> Result<Json<Streak>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let days: Vec<ReviewDay> = diesel::sql_query(
+                "SELECT DISTINCT (reviewed_at AT TIME ZONE 'UTC')::DATE AS day \
+                 FROM review_log WHERE user_id = $1 ORDER BY day",
+            )
+            .bind::<Integer, _>(user_data.user.id)
+            .load(db_connection)
+            .map_err(internal_error)?;
+
+            let days: Vec<NaiveDate> = days.into_iter().map(|day| day.day).collect();
+            Ok(Json(Streak::from_days(&days, Utc::now().date_naive())))
+        })
+        .await
+}
+
+/// A card the user keeps forgetting
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct HardCard {
+    #[diesel(embed)]
+    #[serde(flatten)]
+    pub card: Card,
+    #[diesel(sql_type = Text)]
+    pub deck_name: String,
+    #[diesel(sql_type = BigInt)]
+    pub reviews: i64,
+    /// Reviews graded `again`
+    #[diesel(sql_type = BigInt)]
+    pub failures: i64,
+    #[diesel(sql_type = Double)]
+    pub failure_rate: f64,
+}
+
+/// Cards the user failed the most, relative to how often they were reviewed, optionally only
+/// those of one deck
+#[get("/hardest?<deck_id>&<limit>")]
+async fn hardest_cards(
+    deck_id: Option<i32>,
+    limit: Option<i64>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<HardCard>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let limit = limit.unwrap_or(DEFAULT_HARDEST_LIMIT);
+            if !(1..=MAX_HARDEST_LIMIT).contains(&limit) {
+                return Err(ApiError::Validation(format!(
+                    "limit must be between 1 and {MAX_HARDEST_LIMIT}"
+                )));
+            }
+
+            diesel::sql_query(
+                "SELECT card.id, card.deck_id, card.plugin_id, card.plugin_name, card.plugin_data, \
+                 deck.name AS deck_name, COUNT(*) AS reviews, \
+                 COUNT(*) FILTER (WHERE review_log.grade = 'again') AS failures, \
+                 (COUNT(*) FILTER (WHERE review_log.grade = 'again'))::FLOAT8 / COUNT(*) AS failure_rate \
+                 FROM review_log \
+                 JOIN card ON card.id = review_log.card_id \
+                 JOIN deck ON deck.id = card.deck_id \
+                 WHERE review_log.user_id = $1 \
+                 AND (deck.is_public OR deck.owner_id = $1) \
+                 AND ($2::INTEGER IS NULL OR card.deck_id = $2) \
+                 GROUP BY card.id, deck.name \
+                 HAVING COUNT(*) FILTER (WHERE review_log.grade = 'again') > 0 \
+                 ORDER BY failure_rate DESC, failures DESC, card.id \
+                 LIMIT $3",
+            )
+            .bind::<Integer, _>(user_data.user.id)
+            .bind::<Nullable<Integer>, _>(deck_id)
+            .bind::<BigInt, _>(limit)
+            .load(db_connection)
+            .map(Json)
+            .map_err(internal_error)
+        })
+        .await
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct PluginTime {
+    #[diesel(sql_type = Text)]
+    pub plugin_name: String,
+    #[diesel(sql_type = BigInt)]
+    pub reviews: i64,
+    /// Reviews that reported how long they took
+    #[diesel(sql_type = BigInt)]
+    pub timed_reviews: i64,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub average_duration_ms: Option<f64>,
+}
+
+/// Average time the user spent on the cards of each plugin during the last `days` days
+#[get("/plugins?<days>")]
+async fn plugin_times(
+    days: Option<i32>,
+    user_data: BearerAuth,
+    db_connection: DbConn,
+) -> Result<Json<Vec<PluginTime>>, ApiError> {
+    db_connection
+        .run(move |db_connection| {
+            let days = period(days)?;
+
+            diesel::sql_query(
+                "SELECT card.plugin_name, COUNT(*) AS reviews, \
+                 COUNT(review_log.duration_ms) AS timed_reviews, \
+                 AVG(review_log.duration_ms)::FLOAT8 AS average_duration_ms \
+                 FROM review_log \
+                 JOIN card ON card.id = review_log.card_id \
+                 WHERE review_log.user_id = $1 \
+                 AND review_log.reviewed_at >= NOW() - MAKE_INTERVAL(days => $2) \
+                 GROUP BY card.plugin_name \
+                 ORDER BY card.plugin_name",
+            )
+            .bind::<Integer, _>(user_data.user.id)
+            .bind::<Integer, _>(days)
+            .load(db_connection)
+            .map(Json)
+            .map_err(internal_error)
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket_routes![
+        daily_reviews,
+        deck_retention,
+        hardest_cards,
+        plugin_times,
+        streak
+    ]
+}
@@ -1,26 +1,192 @@
-use rocket::Request;
-use rocket::Response;
+use std::io::Cursor;
+use std::str::FromStr;
+
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::Header;
+use rocket::http::{Header, Method, Status};
+use rocket::{Build, Request, Response, Rocket};
+use serde::Deserialize;
+
+const DEFAULT_MAX_AGE_SECONDS: u64 = 86400;
+
+/// The `cors` table of the Rocket config. Browsers only let the listed origins call the API
+/// from another origin; the frontend is served behind the same origin and needs none.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins like `https://app.flash-qc.example`, or `*` for any origin without credentials
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers clients may send, or `*` for any without credentials
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the answer to a preflight request
+    pub max_age: u64,
+    /// Whether browsers send cookies and HTTP authentication along
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: ["Authorization", "Content-Type"].map(String::from).to_vec(),
+            max_age: DEFAULT_MAX_AGE_SECONDS,
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    fn validate(&self) -> Result<(), String> {
+        let any_origin = self.allowed_origins.iter().any(|origin| origin == "*");
+        let any_header = self.allowed_headers.iter().any(|header| header == "*");
+        if self.allow_credentials && (any_origin || any_header) {
+            return Err("browsers reject * along with allow_credentials".to_string());
+        }
+        if let Some(origin) = self
+            .allowed_origins
+            .iter()
+            .find(|origin| *origin != "*" && (!origin.contains("://") || origin.ends_with('/')))
+        {
+            return Err(format!(
+                "origin '{origin}' must be a scheme and a host, like https://app.example.com"
+            ));
+        }
+        if let Some(method) = self
+            .allowed_methods
+            .iter()
+            .find(|method| Method::from_str(method).is_err())
+        {
+            return Err(format!("unknown method '{method}'"));
+        }
+        Ok(())
+    }
+
+    /// The value of `Access-Control-Allow-Origin` for a request from `origin`, if allowed
+    fn allow_origin<'a>(&self, origin: &'a str) -> Option<&'a str> {
+        if self.allowed_origins.iter().any(|allowed| allowed == origin) {
+            Some(origin)
+        } else if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            Some("*")
+        } else {
+            None
+        }
+    }
 
+    fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
+    }
+
+    /// Whether every header of an `Access-Control-Request-Headers` list is allowed
+    fn allows_headers(&self, headers: &str) -> bool {
+        if self.allowed_headers.iter().any(|allowed| allowed == "*") {
+            return true;
+        }
+        headers
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            })
+    }
+}
+
+/// Adds the CORS headers the `cors` config allows, and answers preflight requests
 pub struct Cors;
 
 #[rocket::async_trait]
 impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
-            name: "Add CORS headers to responses",
-            kind: Kind::Response,
+            name: "CORS",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    /// Reads the `cors` table of the config, and refuses to launch if it is invalid
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = match rocket.figment().extract_inner::<CorsConfig>("cors") {
+            Ok(config) => config,
+            Err(e) if e.missing() => CorsConfig::default(),
+            Err(e) => {
+                rocket::error!("Invalid cors config: {e}");
+                return Err(rocket);
+            }
+        };
+        if let Err(e) = config.validate() {
+            rocket::error!("Invalid cors config: {e}");
+            return Err(rocket);
         }
+        Ok(rocket.manage(config))
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<CorsConfig>() else {
+            return;
+        };
+        let headers = request.headers();
+        let requested_method = headers.get_one("Access-Control-Request-Method");
+        let preflight = request.method() == Method::Options && requested_method.is_some();
+        if !config.allowed_origins.is_empty() {
+            // The answer depends on the origin, caches must not share it
+            response.adjoin_header(Header::new("Vary", "Origin"));
+        }
+
+        let allowed_origin = headers
+            .get_one("Origin")
+            .and_then(|origin| config.allow_origin(origin));
+        let Some(allowed_origin) = allowed_origin else {
+            if preflight {
+                // No route answers OPTIONS, replace the 404 with a refusal
+                reject_preflight(response);
+            }
+            return;
+        };
+
+        if preflight {
+            let allowed = requested_method.is_some_and(|method| config.allows_method(method))
+                && headers
+                    .get_one("Access-Control-Request-Headers")
+                    .is_none_or(|requested| config.allows_headers(requested));
+            if !allowed {
+                reject_preflight(response);
+                return;
+            }
+            response.set_status(Status::NoContent);
+            response.remove_header("Content-Type");
+            response.set_sized_body(0, Cursor::new(""));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                config.allowed_methods.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                config.allowed_headers.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Max-Age",
+                config.max_age.to_string(),
+            ));
+        }
         response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
+            "Access-Control-Allow-Origin",
+            allowed_origin.to_string(),
         ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        if config.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
     }
 }
+
+fn reject_preflight(response: &mut Response<'_>) {
+    response.set_status(Status::Forbidden);
+    response.remove_header("Content-Type");
+    response.set_sized_body(0, Cursor::new(""));
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Data, Request, Response, Rocket};
+
+use crate::utils::db::DbPool;
+
+/// Upper bounds, in seconds, of the request duration histogram
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Requests that matched no route share one label, so that scanners cannot add series
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// When Rocket started handling the request
+struct RequestStart(Instant);
+
+#[derive(Default)]
+struct RouteStats {
+    /// Responses by status code
+    statuses: BTreeMap<u16, u64>,
+    /// Requests that took at most each of the `DURATION_BUCKETS`, not cumulative
+    buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    count: u64,
+}
+
+/// Counts and durations of the requests answered since the backend started, by method and route
+#[derive(Default)]
+pub struct RequestMetrics {
+    routes: Mutex<BTreeMap<(String, String), RouteStats>>,
+}
+
+impl RequestMetrics {
+    fn record(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = routes
+            .entry((method.to_string(), route.to_string()))
+            .or_default();
+        *stats.statuses.entry(status).or_default() += 1;
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|&le| seconds <= le) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.duration_sum += seconds;
+        stats.count += 1;
+    }
+
+    /// The metrics in the Prometheus text format, along with the state of the database pool
+    pub fn render(&self, pool: &DbPool) -> String {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        out.push_str("# HELP flashqc_http_requests_total HTTP requests answered\n");
+        out.push_str("# TYPE flashqc_http_requests_total counter\n");
+        for ((method, route), stats) in routes.iter() {
+            for (status, count) in &stats.statuses {
+                let _ = writeln!(
+                    out,
+                    "flashqc_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {count}",
+                    escape(method),
+                    escape(route),
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP flashqc_http_request_duration_seconds Time spent answering requests\n",
+        );
+        out.push_str("# TYPE flashqc_http_request_duration_seconds histogram\n");
+        for ((method, route), stats) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            let mut cumulative = 0;
+            for (le, count) in DURATION_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "flashqc_http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "flashqc_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                stats.count
+            );
+            let _ = writeln!(
+                out,
+                "flashqc_http_request_duration_seconds_sum{{{labels}}} {}",
+                stats.duration_sum
+            );
+            let _ = writeln!(
+                out,
+                "flashqc_http_request_duration_seconds_count{{{labels}}} {}",
+                stats.count
+            );
+        }
+
+        let state = pool.state();
+        out.push_str(
+            "# HELP flashqc_db_pool_connections Connections opened by the database pool\n",
+        );
+        out.push_str("# TYPE flashqc_db_pool_connections gauge\n");
+        let _ = writeln!(
+            out,
+            "flashqc_db_pool_connections{{state=\"idle\"}} {}",
+            state.idle_connections
+        );
+        let _ = writeln!(
+            out,
+            "flashqc_db_pool_connections{{state=\"in_use\"}} {}",
+            state.connections - state.idle_connections
+        );
+        out.push_str("# HELP flashqc_db_pool_max_connections Most connections the pool opens\n");
+        out.push_str("# TYPE flashqc_db_pool_max_connections gauge\n");
+        let _ = writeln!(out, "flashqc_db_pool_max_connections {}", pool.max_size());
+
+        out
+    }
+}
+
+/// Label values are quoted, backslashes, quotes and line breaks must be escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Records every request in the managed [`RequestMetrics`], served by `/system/metrics`
+pub struct Metrics;
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        Ok(rocket.manage(RequestMetrics::default()))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(metrics) = request.rocket().state::<RequestMetrics>() else {
+            return;
+        };
+        let started = request.local_cache(|| RequestStart(Instant::now()));
+        // The mounted path with its parameters, e.g. `/decks/<id>`, not the requested URI
+        let route = request
+            .route()
+            .map(|route| route.uri.path())
+            .unwrap_or(UNMATCHED_ROUTE);
+        metrics.record(
+            request.method().as_str(),
+            route,
+            response.status().code,
+            started.0.elapsed().as_secs_f64(),
+        );
+    }
+}
@@ -1,5 +1,7 @@
 mod cors;
+mod metrics;
 mod routes;
 
-pub use cors::Cors;
+pub use cors::{Cors, CorsConfig};
+pub use metrics::{Metrics, RequestMetrics};
 pub use routes::routes;
@@ -1,11 +1,157 @@
-use rocket::http::Status;
-use rocket::{Route, get, routes as rocket_routes};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::sql_types::{Text, Timestamp};
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
+use rocket::{Route, State, get, routes as rocket_routes};
+use serde::Serialize;
 
+use super::metrics::RequestMetrics;
+use crate::plugin::PluginRegistry;
+use crate::utils::api_error::ApiError;
+use crate::utils::db::{DbConn, DbPool};
+
+/// Folder names of the migrations this build expects, in the order they apply
+const MIGRATIONS: &[&str] = include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+const GIT_SHA: &str = env!("FLASHQC_GIT_SHA");
+
+/// Version diesel records for a migration folder, its name without the separators and the
+/// description, e.g. `20261018180000` for `2026-10-18-180000_review_log`
+fn migration_version(name: &str) -> String {
+    name.split('_')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect()
+}
+
+#[derive(QueryableByName)]
+struct MigrationRun {
+    #[diesel(sql_type = Text)]
+    version: String,
+    #[diesel(sql_type = Timestamp)]
+    run_on: NaiveDateTime,
+}
+
+/// Migrations recorded by the diesel CLI in the database
+fn applied_migrations(db_connection: &mut PgConnection) -> Result<Vec<MigrationRun>, ApiError> {
+    diesel::sql_query("SELECT version, run_on FROM __diesel_schema_migrations ORDER BY version")
+        .load(db_connection)
+        .map_err(|e| {
+            eprintln!("Database error loading migrations: {:?}", e);
+            ApiError::Unavailable("Migrations have not been run".to_string())
+        })
+}
+
+/// The process is up, whatever the state of its dependencies
 #[get("/health")]
 fn health() -> Status {
     Status::Ok
 }
 
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub database: &'static str,
+    pub migrations: usize,
+}
+
+/// The backend can serve requests: the database answers and has every migration of this build
+#[get("/ready")]
+async fn ready(pool: &State<DbPool>) -> Result<Json<Readiness>, ApiError> {
+    let db_connection = DbConn::get(pool).await.map_err(|e| {
+        eprintln!("{e}");
+        ApiError::Unavailable("Database unavailable".to_string())
+    })?;
+    let applied: Vec<String> = db_connection
+        .run(applied_migrations)
+        .await?
+        .into_iter()
+        .map(|run| run.version)
+        .collect();
+
+    let pending: Vec<&str> = MIGRATIONS
+        .iter()
+        .copied()
+        .filter(|name| !applied.contains(&migration_version(name)))
+        .collect();
+    if !pending.is_empty() {
+        return Err(ApiError::Unavailable(format!(
+            "Migrations not applied: {}",
+            pending.join(", ")
+        )));
+    }
+
+    Ok(Json(Readiness {
+        database: "ok",
+        migrations: MIGRATIONS.len(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppliedMigration {
+    pub version: String,
+    /// Folder of the migration, `None` when this build does not know it
+    pub name: Option<&'static str>,
+    pub run_on: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoadedPlugin {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub migrations: Vec<AppliedMigration>,
+    pub plugins: Vec<LoadedPlugin>,
+}
+
+/// What is running: the build, the migrations applied to its database and its plugins
+#[get("/info")]
+async fn info(
+    registry: &State<PluginRegistry>,
+    db_connection: DbConn,
+) -> Result<Json<BuildInfo>, ApiError> {
+    let migrations = db_connection
+        .run(applied_migrations)
+        .await?
+        .into_iter()
+        .map(|run| AppliedMigration {
+            name: MIGRATIONS
+                .iter()
+                .copied()
+                .find(|name| migration_version(name) == run.version),
+            version: run.version,
+            run_on: run.run_on,
+        })
+        .collect();
+    let plugins = registry
+        .iter()
+        .map(|plugin| LoadedPlugin {
+            name: plugin.manifest.name.clone(),
+            version: plugin.manifest.version.clone(),
+        })
+        .collect();
+
+    Ok(Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: GIT_SHA,
+        migrations,
+        plugins,
+    }))
+}
+
+/// Request counts, latencies and database pool usage in the Prometheus text format
+#[get("/metrics")]
+fn metrics(metrics: &State<RequestMetrics>, pool: &State<DbPool>) -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, metrics.render(pool))
+}
+
 pub fn routes() -> Vec<Route> {
-    rocket_routes![health,]
+    rocket_routes![health, info, metrics, ready,]
 }
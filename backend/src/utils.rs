@@ -1,3 +1,3 @@
+pub mod api_error;
 pub mod db;
-pub mod gen_error;
 pub mod token_store;
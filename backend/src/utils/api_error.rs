@@ -0,0 +1,123 @@
+use rocket::http::Status;
+use rocket::request::{Outcome, Request};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::{Catcher, catch, catchers as rocket_catchers};
+use serde::Serialize;
+use thiserror::Error;
+
+/// Error returned by every API route. Each variant has a fixed HTTP status and a stable `code`
+/// that clients can match on, the message is meant for humans and may change.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// The request cannot be read at all, e.g. a truncated upload
+    #[error("{0}")]
+    BadRequest(String),
+    /// No valid credentials or session
+    #[error("{0}")]
+    Unauthorized(String),
+    /// Authenticated, but not allowed to touch the resource
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The request clashes with existing data, e.g. a username already taken
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// The request is well-formed, but its content is not acceptable
+    #[error("{0}")]
+    Validation(String),
+    /// Something went wrong on our side. Details are logged, not sent to the client.
+    #[error("{0}")]
+    Internal(String),
+    /// A dependency, like the database, cannot be reached right now
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+/// JSON body of every error response
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    status: &'static str,
+    code: &'static str,
+    message: &'a str,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            ApiError::Validation(_) => Status::UnprocessableEntity,
+            ApiError::Internal(_) => Status::InternalServerError,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Internal(_) => "internal_error",
+            ApiError::Unavailable(_) => "service_unavailable",
+        }
+    }
+
+    /// Fails a request guard with this error. Rocket only keeps the status of failed guards, so
+    /// the error is stashed in the request for the catcher to answer with.
+    pub fn fail<S>(self, req: &Request<'_>) -> Outcome<S, ApiError> {
+        req.local_cache(|| Some(self.clone()));
+        Outcome::Error((self.status(), self))
+    }
+
+    /// Error for a status Rocket produced itself, e.g. a failed request guard or a missing route
+    pub fn from_status(status: Status) -> ApiError {
+        let message = status.reason_lossy().to_string();
+        match status.code {
+            401 => ApiError::Unauthorized(message),
+            403 => ApiError::Forbidden(message),
+            404 => ApiError::NotFound(message),
+            409 => ApiError::Conflict(message),
+            413 => ApiError::PayloadTooLarge(message),
+            422 => ApiError::Validation(message),
+            503 => ApiError::Unavailable(message),
+            400..=499 => ApiError::BadRequest(message),
+            _ => ApiError::Internal(message),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let body = ErrorBody {
+            status: "error",
+            code: self.code(),
+            message: &self.to_string(),
+        };
+        (self.status(), Json(body)).respond_to(req)
+    }
+}
+
+#[catch(default)]
+fn default_catcher(status: Status, req: &Request) -> ApiError {
+    match req.local_cache(|| None::<ApiError>) {
+        Some(error) if error.status() == status => error.clone(),
+        _ => ApiError::from_status(status),
+    }
+}
+
+/// Answers errors raised outside of routes, like failed guards, with the same JSON as routes
+pub fn catchers() -> Vec<Catcher> {
+    rocket_catchers![default_catcher]
+}
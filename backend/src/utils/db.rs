@@ -1,16 +1,137 @@
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use dotenvy::dotenv;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{State, tokio};
+use serde::Deserialize;
 use std::env;
-use std::sync::{Mutex, OnceLock};
+use std::panic;
+use std::time::Duration;
 
-static DB_CONNECTION: OnceLock<Mutex<PgConnection>> = OnceLock::new();
+use super::api_error::ApiError;
 
-pub fn get_connection() -> &'static Mutex<PgConnection> {
-    DB_CONNECTION.get_or_init(|| {
-        dotenv().ok();
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let connection = PgConnection::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
-        Mutex::new(connection)
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+/// `database` table of the Rocket config
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Defaults to the `DATABASE_URL` env var
+    pub url: Option<String>,
+    /// Most connections the pool opens at once
+    pub pool_size: u32,
+    /// Seconds a request waits for a free connection before getting a 503
+    pub connection_timeout: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: None,
+            pool_size: 10,
+            connection_timeout: 5,
+        }
+    }
+}
+
+fn database_url() -> Result<String, String> {
+    dotenv().ok();
+    env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set".to_string())
+}
+
+/// Opens a single connection outside of Rocket, e.g. for the CLI
+pub fn establish_connection() -> Result<PgConnection, String> {
+    let database_url = database_url()?;
+    PgConnection::establish(&database_url)
+        .map_err(|e| format!("Error connecting to {}: {}", database_url, e))
+}
+
+pub fn build_pool(config: &DatabaseConfig) -> Result<DbPool, String> {
+    let database_url = match &config.url {
+        Some(url) => url.clone(),
+        None => database_url()?,
+    };
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(Duration::from_secs(config.connection_timeout))
+        .build(ConnectionManager::new(database_url))
+        .map_err(|e| format!("cannot create the database pool: {e}"))
+}
+
+/// Manages a `DbPool` configured from the `database` table of the Rocket config. Must be
+/// attached before any fairing that needs the database.
+pub fn fairing() -> impl Fairing {
+    AdHoc::try_on_ignite("Database pool", |rocket| async {
+        let config: DatabaseConfig = match rocket.figment().extract_inner("database") {
+            Ok(config) => config,
+            Err(e) if e.missing() => DatabaseConfig::default(),
+            Err(e) => {
+                rocket::error!("Invalid database config: {e}");
+                return Err(rocket);
+            }
+        };
+        // Building the pool opens its first connections, which blocks
+        let pool_size = config.pool_size;
+        let built = tokio::task::spawn_blocking(move || build_pool(&config))
+            .await
+            .unwrap_or_else(|e| Err(format!("pool creation task failed: {e}")));
+        match built {
+            Ok(pool) => {
+                rocket::info!("Database pool ready with up to {} connections", pool_size);
+                Ok(rocket.manage(pool))
+            }
+            Err(e) => {
+                rocket::error!("Failed to connect to the database: {e}");
+                Err(rocket)
+            }
+        }
     })
 }
+
+/// A connection checked out from the pool for the duration of a request. Diesel blocks, so the
+/// connection is only reachable through `run`, on a thread meant for blocking work.
+pub struct DbConn(PooledConnection<ConnectionManager<PgConnection>>);
+
+impl DbConn {
+    /// Checks out a connection without a request guard, e.g. once a large upload has been read
+    pub async fn get(pool: &DbPool) -> Result<DbConn, String> {
+        let pool = pool.clone();
+        // Waiting for a free connection blocks, keep it off the async workers
+        match tokio::task::spawn_blocking(move || pool.get()).await {
+            Ok(Ok(connection)) => Ok(DbConn(connection)),
+            Ok(Err(e)) => Err(format!("No database connection available: {e}")),
+            Err(e) => Err(format!("Database connection task failed: {e}")),
+        }
+    }
+
+    /// Runs the queries of `work` off the async workers, and gives the connection back to the
+    /// pool once they are done. A panic in `work` carries on in the caller, like in a sync route.
+    pub async fn run<T, F>(self, work: F) -> T
+    where
+        F: FnOnce(&mut PgConnection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut connection = self.0;
+        match tokio::task::spawn_blocking(move || work(&mut connection)).await {
+            Ok(result) => result,
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DbConn {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool = req.guard::<&State<DbPool>>().await.unwrap();
+        match DbConn::get(pool).await {
+            Ok(connection) => Outcome::Success(connection),
+            Err(e) => {
+                eprintln!("{e}");
+                ApiError::Unavailable("Database unavailable".to_string()).fail(req)
+            }
+        }
+    }
+}
@@ -1,10 +1,28 @@
 use crate::auth::User;
+use crate::models::NewSession;
+use crate::schema::{flashqc_user, session};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
 use rand::{Rng, distr::Alphanumeric, rng};
-use std::collections::HashMap;
+use rocket::fairing::{AdHoc, Fairing};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-/// TODO: Make all of this private and make public accessors
+/// Lifetime of a session, unless `session_ttl_hours` is set in the Rocket config
+const DEFAULT_SESSION_TTL_HOURS: i64 = 24;
+
+/// Token handed to a client on login or refresh
+#[derive(Debug, Serialize)]
+pub struct IssuedToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issues and validates bearer tokens. Sessions live in the `session` table, so they survive
+/// restarts and are shared between backend replicas. Only a hash of each token is stored.
+#[derive(Clone)]
 pub struct TokenStore {
-    tokens: HashMap<String, User>,
+    ttl: Duration,
 }
 
 impl Default for TokenStore {
@@ -12,32 +30,187 @@ impl Default for TokenStore {
         Self::new()
     }
 }
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 impl TokenStore {
     pub fn new() -> Self {
-        TokenStore {
-            tokens: HashMap::new(),
-        }
+        TokenStore::with_ttl(Duration::hours(DEFAULT_SESSION_TTL_HOURS))
     }
 
-    pub fn generate_token(&mut self, user: User) -> String {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        TokenStore { ttl }
+    }
+
+    /// Manages a store whose session lifetime is read from the Rocket config
+    pub fn fairing() -> impl Fairing {
+        AdHoc::try_on_ignite("Session store", |rocket| async {
+            let hours = match rocket.figment().extract_inner::<i64>("session_ttl_hours") {
+                Ok(hours) => hours,
+                Err(e) if e.missing() => DEFAULT_SESSION_TTL_HOURS,
+                Err(e) => {
+                    rocket::error!("Invalid session_ttl_hours: {e}");
+                    return Err(rocket);
+                }
+            };
+            if hours <= 0 {
+                rocket::error!(
+                    "Invalid session_ttl_hours: {hours}, sessions must last at least an hour"
+                );
+                return Err(rocket);
+            }
+            // Expiry dates past what chrono can represent would panic on login
+            let Some(ttl) = Duration::try_hours(hours)
+                .filter(|ttl| Utc::now().checked_add_signed(*ttl).is_some())
+            else {
+                rocket::error!("Invalid session_ttl_hours: {hours} is too long");
+                return Err(rocket);
+            };
+            Ok(rocket.manage(TokenStore::with_ttl(ttl)))
+        })
+    }
+
+    pub fn generate_token(
+        &self,
+        db_connection: &mut PgConnection,
+        user: &User,
+        user_agent: Option<&str>,
+    ) -> QueryResult<IssuedToken> {
         let token: String = rng()
             .sample_iter(Alphanumeric)
             .take(30)
             .map(char::from)
             .collect();
-        self.tokens.insert(token.clone(), user);
-        token
+        let now = Utc::now();
+        let expires_at = now + self.ttl;
+
+        // Expired sessions are useless, clean them up while we are at it
+        diesel::delete(session::table.filter(session::expires_at.le(now)))
+            .execute(db_connection)?;
+        diesel::insert_into(session::table)
+            .values(NewSession {
+                token_hash: hash_token(&token),
+                user_id: user.id,
+                created_at: now,
+                expires_at,
+                last_used_at: now,
+                user_agent: user_agent.map(String::from),
+            })
+            .execute(db_connection)?;
+
+        Ok(IssuedToken { token, expires_at })
     }
 
-    pub fn remove_token(&mut self, token: &str) {
-        self.tokens.remove(token);
+    pub fn remove_token(&self, db_connection: &mut PgConnection, token: &str) -> QueryResult<()> {
+        diesel::delete(session::table.filter(session::token_hash.eq(hash_token(token))))
+            .execute(db_connection)?;
+        Ok(())
+    }
+
+    /// Ends every session of a user, e.g. when their password changes
+    pub fn remove_user_tokens(
+        &self,
+        db_connection: &mut PgConnection,
+        user_id: i32,
+    ) -> QueryResult<()> {
+        diesel::delete(session::table.filter(session::user_id.eq(user_id)))
+            .execute(db_connection)?;
+        Ok(())
+    }
+
+    /// User owning the token, if its session has not expired. Marks the session as used.
+    pub fn get_user(
+        &self,
+        db_connection: &mut PgConnection,
+        token: &str,
+    ) -> QueryResult<Option<User>> {
+        let now = Utc::now();
+        let token_hash = hash_token(token);
+
+        let user: Option<User> = session::table
+            .inner_join(flashqc_user::table)
+            .filter(session::token_hash.eq(&token_hash))
+            .filter(session::expires_at.gt(now))
+            .select(flashqc_user::all_columns)
+            .first(db_connection)
+            .optional()?;
+        if user.is_some() {
+            diesel::update(session::table.filter(session::token_hash.eq(&token_hash)))
+                .set(session::last_used_at.eq(now))
+                .execute(db_connection)?;
+        }
+        Ok(user)
+    }
+
+    /// Replaces a valid token with a new one, expiring a full TTL from now
+    pub fn refresh(
+        &self,
+        db_connection: &mut PgConnection,
+        user: &User,
+        token: &str,
+        user_agent: Option<&str>,
+    ) -> QueryResult<IssuedToken> {
+        db_connection.transaction(|conn| {
+            self.remove_token(conn, token)?;
+            self.generate_token(conn, user, user_agent)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::error::ErrorKind;
+    use rocket::figment::Figment;
+
+    async fn ttl_from(config: Figment) -> Option<Duration> {
+        let rocket = match rocket::custom(config)
+            .attach(TokenStore::fairing())
+            .ignite()
+            .await
+        {
+            Ok(rocket) => rocket,
+            Err(e) => {
+                assert!(matches!(e.kind(), ErrorKind::FailedFairings(_)));
+                return None;
+            }
+        };
+        rocket.state::<TokenStore>().map(|store| store.ttl)
     }
 
-    pub fn is_valid(&self, token: &str) -> bool {
-        self.tokens.contains_key(token)
+    #[rocket::async_test]
+    async fn reads_the_session_lifetime() {
+        let config = rocket::Config::figment();
+        assert_eq!(
+            ttl_from(config.clone()).await,
+            Some(Duration::hours(DEFAULT_SESSION_TTL_HOURS))
+        );
+        assert_eq!(
+            ttl_from(config.clone().merge(("session_ttl_hours", 2))).await,
+            Some(Duration::hours(2))
+        );
     }
 
-    pub fn get_user(&self, token: &str) -> Option<&User> {
-        self.tokens.get(token)
+    #[rocket::async_test]
+    async fn refuses_invalid_session_lifetimes() {
+        let config = rocket::Config::figment();
+        assert_eq!(
+            ttl_from(config.clone().merge(("session_ttl_hours", "a day"))).await,
+            None
+        );
+        assert_eq!(
+            ttl_from(config.clone().merge(("session_ttl_hours", 0))).await,
+            None
+        );
+        assert_eq!(
+            ttl_from(config.clone().merge(("session_ttl_hours", -5))).await,
+            None
+        );
+        assert_eq!(
+            ttl_from(config.merge(("session_ttl_hours", i64::MAX))).await,
+            None
+        );
     }
 }
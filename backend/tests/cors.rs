@@ -0,0 +1,186 @@
+use flashqc_backend::system::Cors;
+use flashqc_backend::utils::api_error;
+use rocket::error::ErrorKind;
+use rocket::figment::Figment;
+use rocket::http::{Header, Status};
+use rocket::local::blocking::Client;
+
+const FRONTEND: &str = "https://app.flash-qc.example";
+const OTHER: &str = "https://evil.example";
+
+/// The system routes need the database, CORS does not
+#[rocket::get("/health")]
+fn health() -> Status {
+    Status::Ok
+}
+
+fn client(cors: Figment) -> Client {
+    let figment = Figment::from(rocket::Config::debug_default()).merge(cors);
+    let rocket = rocket::custom(figment)
+        .attach(Cors)
+        .register("/", api_error::catchers())
+        .mount("/system", rocket::routes![health]);
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+/// Only the frontend, with the default methods, headers and no credentials
+fn allowlist_client() -> Client {
+    client(Figment::new().merge(("cors.allowed_origins", vec![FRONTEND])))
+}
+
+fn credentials_client() -> Client {
+    client(
+        Figment::new()
+            .merge(("cors.allowed_origins", vec![FRONTEND]))
+            .merge(("cors.allow_credentials", true))
+            .merge(("cors.max_age", 600)),
+    )
+}
+
+#[test]
+fn allowed_origin_gets_its_origin_back() {
+    let client = credentials_client();
+    let response = client
+        .get("/system/health")
+        .header(Header::new("Origin", FRONTEND))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let headers = response.headers();
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Origin"),
+        Some(FRONTEND)
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert_eq!(headers.get_one("Vary"), Some("Origin"));
+}
+
+#[test]
+fn rejected_origin_gets_no_cors_headers() {
+    let client = credentials_client();
+    let response = client
+        .get("/system/health")
+        .header(Header::new("Origin", OTHER))
+        .dispatch();
+
+    // The request is served, the browser keeps the response from the page
+    assert_eq!(response.status(), Status::Ok);
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), None);
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), None);
+}
+
+#[test]
+fn same_origin_requests_are_left_alone() {
+    let client = allowlist_client();
+    let response = client.get("/system/health").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn preflight_from_allowed_origin_is_answered() {
+    let client = credentials_client();
+    let response = client
+        .options("/system/health")
+        .header(Header::new("Origin", FRONTEND))
+        .header(Header::new("Access-Control-Request-Method", "DELETE"))
+        .header(Header::new(
+            "Access-Control-Request-Headers",
+            "authorization, content-type",
+        ))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+    let headers = response.headers();
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Origin"),
+        Some(FRONTEND)
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Methods"),
+        Some("GET, POST, PUT, PATCH, DELETE")
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Headers"),
+        Some("Authorization, Content-Type")
+    );
+    assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("600"));
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert!(response.into_string().unwrap_or_default().is_empty());
+}
+
+#[test]
+fn preflight_from_rejected_origin_is_refused() {
+    let client = credentials_client();
+    let response = client
+        .options("/system/health")
+        .header(Header::new("Origin", OTHER))
+        .header(Header::new("Access-Control-Request-Method", "GET"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn preflight_with_unlisted_method_or_header_is_refused() {
+    let client = allowlist_client();
+    let response = client
+        .options("/system/health")
+        .header(Header::new("Origin", FRONTEND))
+        .header(Header::new("Access-Control-Request-Method", "CONNECT"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .options("/system/health")
+        .header(Header::new("Origin", FRONTEND))
+        .header(Header::new("Access-Control-Request-Method", "GET"))
+        .header(Header::new("Access-Control-Request-Headers", "x-secret"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn any_origin_without_credentials() {
+    let client = client(Figment::new().merge(("cors.allowed_origins", vec!["*"])));
+    let response = client
+        .get("/system/health")
+        .header(Header::new("Origin", OTHER))
+        .dispatch();
+
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), None);
+}
+
+#[test]
+fn any_origin_with_credentials_refuses_to_launch() {
+    let figment = Figment::from(rocket::Config::debug_default())
+        .merge(("cors.allowed_origins", vec!["*"]))
+        .merge(("cors.allow_credentials", true));
+    let rocket = rocket::custom(figment).attach(Cors);
+    match Client::tracked(rocket) {
+        // Inspecting the error keeps Rocket from panicking when it is dropped
+        Err(e) => assert!(matches!(e.kind(), ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("the cors config should be refused"),
+    }
+}
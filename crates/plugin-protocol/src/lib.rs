@@ -0,0 +1,229 @@
+//! Messages exchanged over `postMessage` between a plugin, rendered in an iframe, and the
+//! flash-qc page hosting it.
+//!
+//! Every message is a JSON object with the `protocol` version it follows and its `type`, e.g.
+//! `{"protocol": 1, "type": "finish", "reason": "success", "statusCode": 0}`. A plugin starts
+//! with `init` once it listens for messages, and the host answers with `theme`.
+//!
+//! The card to render comes with the page: the backend writes its `cardData` message in a
+//! `<script type="application/json">` element with the [`CARD_BOOTSTRAP_ID`] id, so plugins need
+//! no network access. Hosts that cannot do that send `cardData` in answer to `init` instead.
+//!
+//! The `wasm` feature adds [`PluginHost`], which plugins built with wasm-bindgen use instead
+//! of writing the glue in JS.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "wasm")]
+pub use wasm::PluginHost;
+
+/// Version of the protocol described by this crate. Bumped on every incompatible change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// `id` of the element holding the `cardData` message in the plugin page
+pub const CARD_BOOTSTRAP_ID: &str = "flashqc-card-data";
+
+/// A message along with the version of the protocol it follows
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<M> {
+    pub protocol: u32,
+    #[serde(flatten)]
+    pub message: M,
+}
+
+impl<M> Envelope<M> {
+    pub fn new(message: M) -> Self {
+        Envelope {
+            protocol: PROTOCOL_VERSION,
+            message,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("unsupported protocol version {0}, expected {PROTOCOL_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("invalid message: {0}")]
+    Invalid(#[from] serde_json::Error),
+}
+
+/// Reads a message, rejecting the ones following another version of the protocol
+pub fn decode<M: DeserializeOwned>(json: &str) -> Result<M, ProtocolError> {
+    #[derive(Deserialize)]
+    struct Version {
+        protocol: u32,
+    }
+
+    let Version { protocol } = serde_json::from_str(json)?;
+    if protocol != PROTOCOL_VERSION {
+        return Err(ProtocolError::UnsupportedVersion(protocol));
+    }
+    let envelope: Envelope<M> = serde_json::from_str(json)?;
+    Ok(envelope.message)
+}
+
+/// Writes a message for the current version of the protocol
+pub fn encode<M: Serialize>(message: M) -> Result<String, ProtocolError> {
+    Ok(serde_json::to_string(&Envelope::new(message))?)
+}
+
+/// How a card ended, from the point of view of the plugin
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinishReason {
+    Success,
+    Failure,
+    Timeout,
+}
+
+/// Messages a plugin sends to the host
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum PluginMessage {
+    /// The plugin is loaded and listens for host messages
+    Init,
+    /// The user is done with the card, the host records the review and moves on
+    Finish {
+        reason: FinishReason,
+        /// Score of the attempt, its meaning is up to the plugin
+        status_code: i32,
+    },
+    /// Share of the card done so far, between 0 and 1. Keeps the host from timing out.
+    Progress { value: f64 },
+}
+
+impl PluginMessage {
+    /// Every `type` a plugin can send, as listed in the `messages.sends` of its manifest
+    pub const TYPES: &'static [&'static str] = &["init", "finish", "progress"];
+}
+
+/// Colors of the host page, for plugins to blend in
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    /// CSS color
+    pub background: String,
+    /// CSS color, `None` leaves the text color of the plugin alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+}
+
+/// Messages the host sends to a plugin
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum HostMessage {
+    /// The card to render, written in the page or sent in answer to `init`
+    CardData {
+        card_id: i32,
+        /// `plugin_data` of the card, valid against the data schema of the plugin
+        data: serde_json::Value,
+    },
+    Theme(Theme),
+    /// The user asks for help with the card
+    RequestHint,
+}
+
+impl HostMessage {
+    /// Every `type` a plugin can receive, as listed in the `messages.receives` of its manifest
+    pub const TYPES: &'static [&'static str] = &["cardData", "theme", "requestHint"];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn wire<M: Serialize>(message: M) -> serde_json::Value {
+        serde_json::from_str(&encode(message).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn writes_camel_case_messages_in_an_envelope() {
+        assert_eq!(
+            wire(PluginMessage::Finish {
+                reason: FinishReason::Success,
+                status_code: 3,
+            }),
+            json!({"protocol": 1, "type": "finish", "reason": "success", "statusCode": 3})
+        );
+        assert_eq!(
+            wire(PluginMessage::Init),
+            json!({"protocol": 1, "type": "init"})
+        );
+        assert_eq!(
+            wire(HostMessage::CardData {
+                card_id: 7,
+                data: json!({"front": "a"}),
+            }),
+            json!({"protocol": 1, "type": "cardData", "cardId": 7, "data": {"front": "a"}})
+        );
+        assert_eq!(
+            wire(HostMessage::Theme(Theme {
+                background: "#fff".to_string(),
+                foreground: None,
+            })),
+            json!({"protocol": 1, "type": "theme", "background": "#fff"})
+        );
+    }
+
+    #[test]
+    fn reads_messages_of_this_version() {
+        let message: HostMessage = decode(r#"{"protocol": 1, "type": "requestHint"}"#).unwrap();
+        assert_eq!(message, HostMessage::RequestHint);
+
+        let message: PluginMessage =
+            decode(r#"{"protocol": 1, "type": "progress", "value": 0.5}"#).unwrap();
+        assert_eq!(message, PluginMessage::Progress { value: 0.5 });
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let result = decode::<PluginMessage>(r#"{"protocol": 2, "type": "init"}"#);
+        assert!(matches!(result, Err(ProtocolError::UnsupportedVersion(2))));
+
+        let result = decode::<PluginMessage>(r#"{"type": "init"}"#);
+        assert!(matches!(result, Err(ProtocolError::Invalid(_))));
+    }
+
+    #[test]
+    fn lists_every_message_type() {
+        let plugin = [
+            PluginMessage::Init,
+            PluginMessage::Finish {
+                reason: FinishReason::Timeout,
+                status_code: 0,
+            },
+            PluginMessage::Progress { value: 1.0 },
+        ];
+        for message in plugin {
+            assert!(PluginMessage::TYPES.contains(&wire(message)["type"].as_str().unwrap()));
+        }
+        let host = [
+            HostMessage::CardData {
+                card_id: 1,
+                data: json!(null),
+            },
+            HostMessage::Theme(Theme {
+                background: "black".to_string(),
+                foreground: Some("white".to_string()),
+            }),
+            HostMessage::RequestHint,
+        ];
+        for message in host {
+            assert!(HostMessage::TYPES.contains(&wire(message)["type"].as_str().unwrap()));
+        }
+    }
+}
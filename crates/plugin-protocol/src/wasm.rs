@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{MessageEvent, Window};
+
+use crate::{CARD_BOOTSTRAP_ID, FinishReason, HostMessage, PluginMessage, decode, encode};
+
+type HostHandler = Box<dyn FnMut(&HostMessage)>;
+
+/// Callbacks for the host messages, from Rust or from the plugin page
+#[derive(Default)]
+struct Handlers {
+    rust: Option<HostHandler>,
+    card_data: Option<js_sys::Function>,
+    theme: Option<js_sys::Function>,
+    request_hint: Option<js_sys::Function>,
+}
+
+/// Hands a host message to the handlers. Callbacks run without the handlers borrowed, so they
+/// can register other callbacks.
+fn dispatch(handlers: &RefCell<Handlers>, message: HostMessage) -> Result<(), JsValue> {
+    let rust = handlers.borrow_mut().rust.take();
+    if let Some(mut handler) = rust {
+        handler(&message);
+        // Unless the handler replaced itself
+        handlers.borrow_mut().rust.get_or_insert(handler);
+    }
+    let handlers = handlers.borrow();
+    match &message {
+        HostMessage::CardData { card_id, data } => {
+            if let Some(callback) = handlers.card_data.clone() {
+                drop(handlers);
+                callback.call2(&JsValue::NULL, &to_js(data)?, &(*card_id).into())?;
+            }
+        }
+        HostMessage::Theme(theme) => {
+            if let Some(callback) = handlers.theme.clone() {
+                drop(handlers);
+                callback.call1(&JsValue::NULL, &to_js(theme)?)?;
+            }
+        }
+        HostMessage::RequestHint => {
+            if let Some(callback) = handlers.request_hint.clone() {
+                drop(handlers);
+                callback.call0(&JsValue::NULL)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The `cardData` message the backend wrote in the page, if any
+fn bootstrap_card_data(window: &Window) -> Result<Option<HostMessage>, JsValue> {
+    let Some(json) = window
+        .document()
+        .and_then(|document| document.get_element_by_id(CARD_BOOTSTRAP_ID))
+        .and_then(|element| element.text_content())
+    else {
+        return Ok(None);
+    };
+    decode(&json)
+        .map(Some)
+        .map_err(|e| JsValue::from_str(&format!("Invalid card data in the page: {e}")))
+}
+
+fn to_js<T: serde::Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+/// Connection of a plugin to the page hosting its iframe: host messages are handed to the
+/// registered callbacks, plugin messages are posted to the parent window
+#[wasm_bindgen]
+pub struct PluginHost {
+    parent: Window,
+    handlers: Rc<RefCell<Handlers>>,
+    listener: Option<Closure<dyn FnMut(MessageEvent)>>,
+}
+
+#[wasm_bindgen]
+impl PluginHost {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<PluginHost, JsValue> {
+        let window = web_sys::window().ok_or("No window")?;
+        // Outside of an iframe, the parent is the window itself
+        let parent = window.parent()?.unwrap_or_else(|| window.clone());
+
+        Ok(PluginHost {
+            parent,
+            handlers: Rc::new(RefCell::new(Handlers::default())),
+            listener: None,
+        })
+    }
+
+    /// `callback(data, cardId)` receives the `plugin_data` of the card to render
+    pub fn on_card_data(&self, callback: js_sys::Function) {
+        self.handlers.borrow_mut().card_data = Some(callback);
+    }
+
+    /// `callback({background, foreground})` receives the colors of the host page
+    pub fn on_theme(&self, callback: js_sys::Function) {
+        self.handlers.borrow_mut().theme = Some(callback);
+    }
+
+    pub fn on_request_hint(&self, callback: js_sys::Function) {
+        self.handlers.borrow_mut().request_hint = Some(callback);
+    }
+
+    /// Hands the card written in the page to the callbacks, starts listening for host messages
+    /// and sends `init`. Register the callbacks first.
+    pub fn connect(&mut self) -> Result<(), JsValue> {
+        let window = web_sys::window().ok_or("No window")?;
+        if let Some(message) = bootstrap_card_data(&window)? {
+            dispatch(&self.handlers, message)?;
+        }
+        if self.listener.is_none() {
+            let parent = self.parent.clone();
+            let handlers = self.handlers.clone();
+            let closure = Closure::wrap(Box::new(move |event: MessageEvent| {
+                // Only the host page talks to the plugin
+                let from_parent = event
+                    .source()
+                    .is_some_and(|source| js_sys::Object::is(&source, &parent));
+                if !from_parent {
+                    return;
+                }
+                let Some(json) = js_sys::JSON::stringify(&event.data())
+                    .ok()
+                    .and_then(|json| json.as_string())
+                else {
+                    return;
+                };
+                match decode::<HostMessage>(&json) {
+                    Ok(message) => {
+                        if let Err(e) = dispatch(&handlers, message) {
+                            web_sys::console::error_2(&"Plugin message handler failed:".into(), &e);
+                        }
+                    }
+                    Err(e) => {
+                        web_sys::console::warn_1(&format!("Ignored host message: {e}").into());
+                    }
+                }
+            }) as Box<dyn FnMut(_)>);
+            window.add_event_listener_with_callback("message", closure.as_ref().unchecked_ref())?;
+            self.listener = Some(closure);
+        }
+        self.send(&PluginMessage::Init)
+    }
+
+    /// Ends the card, `reason` is `success`, `failure` or `timeout`
+    pub fn finish(&self, reason: &str, status_code: i32) -> Result<(), JsValue> {
+        let reason: FinishReason = serde_json::from_value(reason.into())
+            .map_err(|_| JsValue::from_str(&format!("Invalid finish reason '{reason}'")))?;
+        self.send(&PluginMessage::Finish {
+            reason,
+            status_code,
+        })
+    }
+
+    /// Reports how much of the card is done, between 0 and 1
+    pub fn progress(&self, value: f64) -> Result<(), JsValue> {
+        self.send(&PluginMessage::Progress {
+            value: value.clamp(0.0, 1.0),
+        })
+    }
+}
+
+impl PluginHost {
+    /// Receives every host message, before the callbacks registered from JS
+    pub fn set_handler(&self, handler: impl FnMut(&HostMessage) + 'static) {
+        self.handlers.borrow_mut().rust = Some(Box::new(handler));
+    }
+
+    pub fn send(&self, message: &PluginMessage) -> Result<(), JsValue> {
+        let json = encode(message).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        // Plugins run in a sandbox with an opaque origin and cannot name the one of the host,
+        // the messages carry nothing secret
+        self.parent.post_message(&js_sys::JSON::parse(&json)?, "*")
+    }
+}
+
+impl Drop for PluginHost {
+    fn drop(&mut self) {
+        if let (Some(window), Some(listener)) = (web_sys::window(), &self.listener) {
+            let _ = window
+                .remove_event_listener_with_callback("message", listener.as_ref().unchecked_ref());
+        }
+    }
+}
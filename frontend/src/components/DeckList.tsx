@@ -1,5 +1,6 @@
 import { useEffect, useState } from "react";
 import { Button } from "@/components/ui/button";
+import { authHeaders } from "@/scripts/auth";
 
 interface Deck {
   id: string;
@@ -12,8 +13,10 @@ export default function DeckList() {
   useEffect(() => {
     async function loadDecks() {
       try {
-        const userDecks = await fetch("/api/decks").then((res) => res.json());
-        setDecks(userDecks);
+        const userDecks = await fetch("/api/decks?per_page=200", {
+          headers: authHeaders(),
+        }).then((res) => res.json());
+        setDecks(userDecks.items);
       } catch (error) {
         console.error("Failed to load decks:", error);
       }
@@ -24,7 +27,10 @@ export default function DeckList() {
   async function handleDelete(deckId: string) {
     if (confirm("Are you sure you want to delete this deck?")) {
       try {
-        await fetch(`/api/decks/${deckId}`, { method: "DELETE" });
+        await fetch(`/api/decks/${deckId}`, {
+          method: "DELETE",
+          headers: authHeaders(),
+        });
         setDecks(decks.filter((deck) => deck.id !== deckId));
       } catch (error) {
         console.error("Failed to delete deck:", error);
@@ -1,19 +1,51 @@
+// Mirror of the flash-qc plugin protocol, see crates/plugin-protocol
+export const PROTOCOL_VERSION = 1;
+
 export type FinishReason = "success" | "failure" | "timeout";
 
-export interface PluginMessage {
-  type: "finish";
-  hasFinished: () => boolean;
-  reason: FinishReason;
-  statusCode: number;
+/** Messages a plugin sends to the host */
+export type PluginMessage =
+  | { type: "init" }
+  | { type: "finish"; reason: FinishReason; statusCode: number }
+  | { type: "progress"; value: number };
+
+export interface Theme {
+  background: string;
+  foreground?: string;
 }
 
+/** Messages the host sends to a plugin */
+export type HostMessage =
+  | { type: "cardData"; cardId: number; data: unknown }
+  | ({ type: "theme" } & Theme)
+  | { type: "requestHint" };
+
+/** Reads a plugin message, or returns null for anything that does not follow the protocol */
 export function parseEventData(eventData: any): PluginMessage | null {
-  return {
-    type: "finish",
-    hasFinished: () => true,
-    reason: parseReason(eventData.reason),
-    statusCode: eventData.statusCode ?? 1,
-  };
+  if (eventData?.protocol !== PROTOCOL_VERSION) return null;
+
+  switch (eventData.type) {
+    case "init":
+      return { type: "init" };
+    case "finish":
+      return {
+        type: "finish",
+        reason: parseReason(eventData.reason),
+        statusCode: Number.isInteger(eventData.statusCode)
+          ? eventData.statusCode
+          : 1,
+      };
+    case "progress":
+      return typeof eventData.value === "number"
+        ? { type: "progress", value: eventData.value }
+        : null;
+    default:
+      return null;
+  }
+}
+
+export function encodeHostMessage(message: HostMessage) {
+  return { protocol: PROTOCOL_VERSION, ...message };
 }
 
 function parseReason(reason?: any): FinishReason {
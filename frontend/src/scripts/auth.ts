@@ -1,4 +1,14 @@
-export function logout() {
+export async function logout() {
+  // End the session on the backend too, so the token cannot be reused
+  await fetch("/api/auth/logout", {
+    method: "POST",
+    headers: authHeaders(),
+  }).catch(() => undefined);
   localStorage.removeItem("token");
   window.location.href = "/";
 }
+
+export function authHeaders(): Record<string, string> {
+  const token = localStorage.getItem("token");
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
@@ -1,10 +1,21 @@
-import { parseEventData } from "../models/plugin-message";
+import {
+  encodeHostMessage,
+  parseEventData,
+  type FinishReason,
+  type HostMessage,
+  type Theme,
+} from "../models/plugin-message";
+import { authHeaders } from "./auth";
 
 let currentTimeoutCallbackHandler: NodeJS.Timeout | null = null;
+let currentCardShownAt = Date.now();
+let currentCard: { id: number; plugin_name: string } | null = null;
 const PLUGIN_TIMEOUT_MS = 20000;
 
 const colors = ["red", "green", "blue", "yellow"];
 let currentColorIndex = 0;
+// Colors picked by the user, plugins keep their own until then
+let currentTheme: Theme | null = null;
 
 async function checkBackendStatus() {
   const statusElement = document.getElementById("backend-status");
@@ -29,8 +40,14 @@ async function loadPlugin() {
   const cardId = (window as any).currentPluginCardIds[
     (window as any).currentCardIndex
   ];
-  const pluginName = await getPluginName(cardId);
-  iframe.src = `/api/plugin/${pluginName}?cardId=${cardId}`;
+  currentCard = await getCard(cardId);
+  const pluginName = currentCard.plugin_name;
+  // The page comes with the card written in it, which needs the credentials of the user
+  const response = await fetch(`/api/plugin/${pluginName}?card_id=${cardId}`, {
+    headers: authHeaders(),
+  });
+  iframe.srcdoc = await response.text();
+  currentCardShownAt = Date.now();
   const activePluginEl = document.getElementById("active-plugin");
   if (activePluginEl) {
     activePluginEl.textContent = `Plugin: ${pluginName} (${(window as any).currentCardIndex + 1}/${
@@ -38,19 +55,57 @@ async function loadPlugin() {
     })`;
   }
 
-  // Only set timeout for plugins that support it
-  if (!PLUGINS_WITHOUT_TIMEOUT.includes(pluginName)) {
+  restartPluginTimeout();
+  console.log(`Loaded plugin: ${pluginName}`);
+}
+
+// Only set timeout for plugins that support it
+function restartPluginTimeout() {
+  if (currentTimeoutCallbackHandler !== null) {
+    clearTimeout(currentTimeoutCallbackHandler);
+    currentTimeoutCallbackHandler = null;
+  }
+  if (currentCard && !PLUGINS_WITHOUT_TIMEOUT.includes(currentCard.plugin_name)) {
     currentTimeoutCallbackHandler = setTimeout(() => {
       nextPlugin();
     }, PLUGIN_TIMEOUT_MS);
   }
-  console.log(`Loaded plugin: ${pluginName}`);
 }
 
-async function getPluginName(cardId: number): Promise<string> {
-  const response = await fetch(`/api/cards/${cardId}`);
-  const { plugin_name } = await response.json();
-  return plugin_name;
+async function getCard(cardId: number) {
+  const response = await fetch(`/api/cards/${cardId}`, {
+    headers: authHeaders(),
+  });
+  return await response.json();
+}
+
+function sendToPlugin(message: HostMessage) {
+  const iframe = document.getElementById("main-iframe") as HTMLIFrameElement;
+  if (!iframe?.contentWindow) return;
+
+  // The plugin has an opaque origin in its sandbox, it can only be addressed with "*"
+  iframe.contentWindow.postMessage(encodeHostMessage(message), "*");
+}
+
+async function reviewCurrentCard(reason: FinishReason, score: number) {
+  if (!localStorage.getItem("token")) return;
+
+  const cardId = (window as any).currentPluginCardIds[
+    (window as any).currentCardIndex
+  ];
+  try {
+    await fetch(`/api/cards/${cardId}/review`, {
+      method: "POST",
+      body: JSON.stringify({
+        reason,
+        score,
+        duration_ms: Date.now() - currentCardShownAt,
+      }),
+      headers: { "Content-Type": "application/json", ...authHeaders() },
+    });
+  } catch (error) {
+    console.error("Failed to review card:", error);
+  }
 }
 
 async function nextPlugin() {
@@ -67,17 +122,10 @@ async function nextPlugin() {
 function changeBackgroundColor() {
   currentColorIndex = (currentColorIndex + 1) % colors.length;
   const color = colors[currentColorIndex];
-  const iframe = document.getElementById("main-iframe") as HTMLIFrameElement;
-  if (!iframe?.contentWindow) return;
 
-  // Send postMessage to iframe to change background color
-  iframe.contentWindow.postMessage(
-    {
-      type: "changeBackgroundColor",
-      color: color,
-    },
-    window.location.origin,
-  );
+  // Tell the plugin about the new background color
+  currentTheme = { background: color };
+  sendToPlugin({ type: "theme", ...currentTheme });
 
   console.log(`Changed background color to: ${color}`);
 }
@@ -88,8 +136,10 @@ async function loadDecks() {
   ) as HTMLSelectElement;
 
   try {
-    const response = await fetch("/api/decks");
-    const decks = await response.json();
+    const response = await fetch("/api/decks?per_page=200", {
+      headers: authHeaders(),
+    });
+    const decks = (await response.json()).items;
 
     dropdown.innerHTML = '<option value="">Select a deck...</option>';
 
@@ -107,7 +157,9 @@ async function loadDecks() {
 
 async function loadDeckCards(deckId: string) {
   try {
-    const response = await fetch(`/api/decks/${deckId}`);
+    const response = await fetch(`/api/decks/${deckId}`, {
+      headers: authHeaders(),
+    });
     const deckWithCards = await response.json();
 
     // Store cards in window so iframe can access them
@@ -131,16 +183,31 @@ dropdown.addEventListener("change", (e) => {
 
 // Listen for postMessage from iframe
 window.addEventListener("message", async (event) => {
-  if (event.origin !== window.location.origin) {
+  // Sandboxed plugins send messages from the "null" origin, only their window identifies them
+  const iframe = document.getElementById("main-iframe") as HTMLIFrameElement;
+  if (event.source !== iframe?.contentWindow) {
     return;
   }
 
   const eventData = parseEventData(event.data);
   console.log(`Received message from plugin: ${JSON.stringify(eventData)}`);
-
-  if (eventData && eventData.hasFinished()) {
-    console.log("User marked card as memorized, cycling to next plugin...");
-    await nextPlugin();
+  if (!eventData) return;
+
+  switch (eventData.type) {
+    case "init":
+      // The plugin listens, the card is already in its page: hand it the current colors
+      if (currentTheme) {
+        sendToPlugin({ type: "theme", ...currentTheme });
+      }
+      break;
+    case "progress":
+      restartPluginTimeout();
+      break;
+    case "finish":
+      console.log("Plugin finished the card, cycling to next plugin...");
+      await reviewCurrentCard(eventData.reason, eventData.statusCode);
+      await nextPlugin();
+      break;
   }
 });
 
@@ -1,3 +1,5 @@
+import { authHeaders } from "./auth";
+
 const addButton = (parent: HTMLElement, text: string) => {
   const btn = parent.appendChild(document.createElement("button"));
   btn.textContent = text;
@@ -9,7 +11,11 @@ const addButton = (parent: HTMLElement, text: string) => {
 };
 
 async function populateUserDecks() {
-  const userDecks = await fetch("/api/decks").then((res) => res.json());
+  const userDecks = await fetch("/api/decks?per_page=200", {
+    headers: authHeaders(),
+  })
+    .then((res) => res.json())
+    .then((page) => page.items);
   const deckList = document.getElementById("deck-list");
   if (!deckList) {
     console.error("Deck list element not found");
@@ -25,7 +31,10 @@ async function populateUserDecks() {
     let deleteButton = addButton(deckItem, "Delete");
     deleteButton.addEventListener("click", async () => {
       if (confirm("Are you sure you want to delete this deck?")) {
-        await fetch(`/api/decks/${deck.id}`, { method: "DELETE" });
+        await fetch(`/api/decks/${deck.id}`, {
+          method: "DELETE",
+          headers: authHeaders(),
+        });
         deckList.removeChild(deckItem);
       }
     });
@@ -1,41 +1,55 @@
 // >>> Mandatory part: contract to satisfy by plugin
-// Generates "cardId" and "getCardData", which are used to fetch card data
-async function getCardData(cardId) {
-  const response = await fetch(`/api/cards/${cardId}`);
-  const { plugin_data } = await response.json();
-  return plugin_data;
+// Speaks the flash-qc plugin protocol (see crates/plugin-protocol): the card to render is
+// written in the page as a "cardData" message, and the plugin announces itself with "init"
+const PROTOCOL_VERSION = 1;
+
+function sendToHost(message) {
+  if (window.parent !== window) {
+    // The plugin runs in a sandbox with an opaque origin, the messages carry nothing secret
+    window.parent.postMessage({ protocol: PROTOCOL_VERSION, ...message }, "*");
+  }
 }
 
-const urlParams = new URLSearchParams(window.location.search);
-const cardId = urlParams.get("cardId");
+function bootstrapCardData() {
+  const element = document.getElementById("flashqc-card-data");
+  const message = element ? JSON.parse(element.textContent) : null;
+  if (message?.protocol !== PROTOCOL_VERSION || message.type !== "cardData") {
+    return null;
+  }
+  return message.data;
+}
 // <<< Mandatory part: contract to satisfy by plugin
 
-async function showFact() {
-  const { fact } = await getCardData(cardId);
+function showFact({ fact }) {
   document.getElementById("fact-text").textContent = fact;
 }
 
 // Handle "Memorized" button click
 document.getElementById("memorized-btn").addEventListener("click", function () {
-  // Send postMessage to parent window
-  if (window.parent !== window) {
-    window.parent.postMessage(
-      {
-        type: "finish",
-        reason: "success",
-        statusCode: 0,
-      },
-      window.location.origin,
-    );
-  }
+  sendToHost({
+    type: "finish",
+    reason: "success",
+    statusCode: 0,
+  });
 });
 
-// Listen for background color change from parent
+const cardData = bootstrapCardData();
+if (cardData) {
+  showFact(cardData);
+}
+
+// Listen for the card and the colors of the host page
 window.addEventListener("message", function (event) {
-  if (event.data && event.data.type === "changeBackgroundColor") {
-    document.body.style.background = event.data.color;
-    console.log("Changed background to:", event.data.color);
+  if (event.source !== window.parent || event.data?.protocol !== PROTOCOL_VERSION) {
+    return;
+  }
+  if (event.data.type === "cardData") {
+    showFact(event.data.data);
+  }
+  if (event.data.type === "theme") {
+    document.body.style.background = event.data.background;
+    console.log("Changed background to:", event.data.background);
   }
 });
 
-await showFact();
+sendToHost({ type: "init" });
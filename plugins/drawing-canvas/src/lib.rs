@@ -4,6 +4,9 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, MouseEvent};
 
+/// Connection to the host page, see the `flashqc-plugin-protocol` crate
+pub use flashqc_plugin_protocol::PluginHost;
+
 #[wasm_bindgen]
 pub struct DrawingCanvas {
     canvas: HtmlCanvasElement,
@@ -1,45 +1,58 @@
 // >>> Mandatory part: contract to satisfy by plugin
-// Generates "cardId" and "getCardData", which are used to fetch card data
-async function getCardData(cardId) {
-  const response = await fetch(`/api/cards/${cardId}`);
-  const { plugin_data } = await response.json();
-  return plugin_data;
+// Speaks the flash-qc plugin protocol (see crates/plugin-protocol): the card to render is
+// written in the page as a "cardData" message, and the plugin announces itself with "init"
+const PROTOCOL_VERSION = 1;
+
+function sendToHost(message) {
+  if (window.parent !== window) {
+    // The plugin runs in a sandbox with an opaque origin, the messages carry nothing secret
+    window.parent.postMessage({ protocol: PROTOCOL_VERSION, ...message }, "*");
+  }
 }
 
-const urlParams = new URLSearchParams(window.location.search);
-const cardId = urlParams.get("cardId");
+function bootstrapCardData() {
+  const element = document.getElementById("flashqc-card-data");
+  const message = element ? JSON.parse(element.textContent) : null;
+  if (message?.protocol !== PROTOCOL_VERSION || message.type !== "cardData") {
+    return null;
+  }
+  return message.data;
+}
+
+window.addEventListener("message", function (event) {
+  if (event.source !== window.parent || event.data?.protocol !== PROTOCOL_VERSION) {
+    return;
+  }
+  if (event.data.type === "cardData") {
+    showWord(event.data.data);
+  }
+  // OPT2: Follow the colors of the host page
+  if (event.data.type === "theme") {
+    document.body.style.background = event.data.background;
+    console.log("Changed background to:", event.data.background);
+  }
+});
 // <<< Mandatory part: contract to satisfy by plugin
 
 // >>> Optional part: APIs that the plugin could offer
 // OPT1: Offer a "Memorized" button click
 document.getElementById("memorized-btn").addEventListener("click", function () {
-  // Send postMessage to parent window
-  if (window.parent !== window) {
-    window.parent.postMessage(
-      {
-        type: "finish",
-        reason: "success",
-        statusCode: 0,
-      },
-      window.location.origin,
-    );
-  }
-});
-
-// OPT2: Listen for background color change from parent
-window.addEventListener("message", function (event) {
-  if (event.data && event.data.type === "changeBackgroundColor") {
-    document.body.style.background = event.data.color;
-    console.log("Changed background to:", event.data.color);
-  }
+  sendToHost({
+    type: "finish",
+    reason: "success",
+    statusCode: 0,
+  });
 });
 // <<< Optional part: APIs that the plugin could offer
 
 // >>> Core plugin functionality
-async function changeDummyWord() {
-  const { word } = await getCardData(cardId);
+function showWord({ word }) {
   document.getElementById("word").textContent = word;
 }
 
-await changeDummyWord();
+const cardData = bootstrapCardData();
+if (cardData) {
+  showWord(cardData);
+}
+sendToHost({ type: "init" });
 // <<< Core plugin functionality
@@ -1,12 +1,36 @@
 // >>> Mandatory part: contract to satisfy by plugin
-// Generates "cardId" and "getCardData", which are used to fetch card data
-const urlParams = new URLSearchParams(window.location.search);
-const cardId = urlParams.get("cardId");
-
-async function getCardData() {
-  const response = await fetch(`/api/cards/${cardId}`);
-  const { plugin_data } = await response.json();
-  return plugin_data;
+// Speaks the flash-qc plugin protocol (see crates/plugin-protocol): the card to render is
+// written in the page as a "cardData" message, and the plugin announces itself with "init"
+const PROTOCOL_VERSION = 1;
+
+function sendToHost(message: { type: string; [field: string]: unknown }): void {
+  if (window.parent !== window) {
+    // The plugin runs in a sandbox with an opaque origin, the messages carry nothing secret
+    window.parent.postMessage({ protocol: PROTOCOL_VERSION, ...message }, "*");
+  }
+}
+
+function listenToHost(handlers: {
+  cardData: (data: any) => void;
+  theme: (theme: { background: string }) => void;
+}): void {
+  window.addEventListener("message", (event: MessageEvent) => {
+    if (
+      event.source !== window.parent ||
+      event.data?.protocol !== PROTOCOL_VERSION
+    ) {
+      return;
+    }
+    if (event.data.type === "cardData") handlers.cardData(event.data.data);
+    if (event.data.type === "theme") handlers.theme(event.data);
+  });
+
+  const bootstrap = document.getElementById("flashqc-card-data");
+  const message = bootstrap ? JSON.parse(bootstrap.textContent ?? "") : null;
+  if (message?.protocol === PROTOCOL_VERSION && message.type === "cardData") {
+    handlers.cardData(message.data);
+  }
+  sendToHost({ type: "init" });
 }
 // <<< Mandatory part: contract to satisfy by plugin
 
@@ -81,16 +105,29 @@ class Flashcard {
 /**
  * Initialize the flashcard when DOM is ready
  */
-document.addEventListener("DOMContentLoaded", async () => {
+document.addEventListener("DOMContentLoaded", () => {
   const cardElement = document.querySelector(".flashcard") as HTMLElement;
 
-  if (cardElement) {
-    const { frontContent, backContent } = await getCardData();
-    const flashcard = new Flashcard(cardElement, frontContent, backContent);
-
-    // Expose to window for debugging/testing if needed
-    (window as any).flashcard = flashcard;
-  } else {
+  if (!cardElement) {
     console.error("Flashcard element not found");
+    return;
   }
+
+  // Handle "Memorized" button click
+  document.getElementById("memorized-btn")?.addEventListener("click", () => {
+    sendToHost({ type: "finish", reason: "success", statusCode: 0 });
+  });
+
+  listenToHost({
+    cardData: ({ frontContent, backContent }) => {
+      const flashcard = new Flashcard(cardElement, frontContent, backContent);
+
+      // Expose to window for debugging/testing if needed
+      (window as any).flashcard = flashcard;
+    },
+    theme: ({ background }) => {
+      document.body.style.background = background;
+      console.log("Changed background to:", background);
+    },
+  });
 });
@@ -0,0 +1,560 @@
+//! The cube as its 8 corner and 12 edge pieces (cubies): where each one is and how it is turned.
+//! Positions and orientations follow Kociemba's conventions, and pieces are named after the
+//! centers they match, wherever those centers are.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use super::moves::{Layers, Move, Rotation, Side, Slice};
+use super::state::{B, Color, CubeState, D, F, L, R, U};
+
+pub const CORNERS: usize = 8;
+pub const EDGES: usize = 12;
+
+// Corner positions
+const URF: u8 = 0;
+const UFL: u8 = 1;
+const ULB: u8 = 2;
+const UBR: u8 = 3;
+const DFR: u8 = 4;
+const DLF: u8 = 5;
+const DBL: u8 = 6;
+const DRB: u8 = 7;
+
+// Edge positions
+const UR: u8 = 0;
+const UF: u8 = 1;
+const UL: u8 = 2;
+const UB: u8 = 3;
+const DR: u8 = 4;
+const DF: u8 = 5;
+const DL: u8 = 6;
+const DB: u8 = 7;
+const FR: u8 = 8;
+const FL: u8 = 9;
+const BL: u8 = 10;
+const BR: u8 = 11;
+
+/// Stickers of each corner position as (face, index), the U or D sticker first, then clockwise
+const CORNER_FACELETS: [[(usize, usize); 3]; CORNERS] = [
+    [(U, 8), (R, 0), (F, 2)],
+    [(U, 6), (F, 0), (L, 2)],
+    [(U, 0), (L, 0), (B, 2)],
+    [(U, 2), (B, 0), (R, 2)],
+    [(D, 2), (F, 8), (R, 6)],
+    [(D, 0), (L, 8), (F, 6)],
+    [(D, 6), (B, 8), (L, 6)],
+    [(D, 8), (R, 8), (B, 6)],
+];
+
+/// Stickers of each edge position as (face, index), the U, D, F or B sticker first
+const EDGE_FACELETS: [[(usize, usize); 2]; EDGES] = [
+    [(U, 5), (R, 1)],
+    [(U, 7), (F, 1)],
+    [(U, 3), (L, 1)],
+    [(U, 1), (B, 1)],
+    [(D, 5), (R, 7)],
+    [(D, 1), (F, 7)],
+    [(D, 3), (L, 7)],
+    [(D, 7), (B, 7)],
+    [(F, 5), (R, 3)],
+    [(F, 3), (L, 5)],
+    [(B, 5), (L, 3)],
+    [(B, 3), (R, 5)],
+];
+
+/// Stickers of each corner and edge position, as (face, index)
+pub fn pieces() -> impl Iterator<Item = &'static [(usize, usize)]> {
+    let corners = CORNER_FACELETS.iter().map(|facelets| facelets.as_slice());
+    let edges = EDGE_FACELETS.iter().map(|facelets| facelets.as_slice());
+    corners.chain(edges)
+}
+
+/// Why stickers or cubies do not make a cube that can be solved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubieError {
+    /// Two centers have the same color
+    Centers,
+    /// The stickers of a corner position match no corner
+    UnknownCorner(usize),
+    /// The stickers of an edge position match no edge
+    UnknownEdge(usize),
+    /// A piece shows up twice, so another one is missing
+    DuplicateCubie,
+    /// The corner orientations do not add up: a single corner is twisted
+    Twist,
+    /// The edge orientations do not add up: a single edge is flipped
+    Flip,
+    /// Corners and edges are permuted with different parities: two pieces are swapped
+    Parity,
+    /// The cube is not a 3x3, the only size made of these cubies
+    Size(usize),
+}
+
+impl fmt::Display for CubieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CubieError::Centers => write!(f, "two centers have the same color"),
+            CubieError::UnknownCorner(position) => {
+                write!(f, "corner {position} has impossible colors")
+            }
+            CubieError::UnknownEdge(position) => write!(f, "edge {position} has impossible colors"),
+            CubieError::DuplicateCubie => write!(f, "a piece appears twice"),
+            CubieError::Twist => write!(f, "a corner is twisted"),
+            CubieError::Flip => write!(f, "an edge is flipped"),
+            CubieError::Parity => write!(f, "two pieces are swapped"),
+            CubieError::Size(size) => write!(f, "a {size}x{size} cube is not a 3x3"),
+        }
+    }
+}
+
+impl std::error::Error for CubieError {}
+
+/// Cubie `cp[i]` sits at position `i`, turned by `co[i]` (corners, in thirds of a turn
+/// clockwise) or `eo[i]` (edges, flipped or not)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CubieCube {
+    pub cp: [u8; CORNERS],
+    pub co: [u8; CORNERS],
+    pub ep: [u8; EDGES],
+    pub eo: [u8; EDGES],
+}
+
+impl CubieCube {
+    pub const SOLVED: CubieCube = CubieCube {
+        cp: [URF, UFL, ULB, UBR, DFR, DLF, DBL, DRB],
+        co: [0; CORNERS],
+        ep: [UR, UF, UL, UB, DR, DF, DL, DB, FR, FL, BL, BR],
+        eo: [0; EDGES],
+    };
+
+    /// The cube after `self` then `other`
+    pub fn multiply(&self, other: &CubieCube) -> CubieCube {
+        let mut result = CubieCube::SOLVED;
+        for i in 0..CORNERS {
+            let from = other.cp[i] as usize;
+            result.cp[i] = self.cp[from];
+            result.co[i] = (self.co[from] + other.co[i]) % 3;
+        }
+        for i in 0..EDGES {
+            let from = other.ep[i] as usize;
+            result.ep[i] = self.ep[from];
+            result.eo[i] = (self.eo[from] + other.eo[i]) % 2;
+        }
+        result
+    }
+
+    /// The cube undoing `self` from solved
+    pub fn inverse(&self) -> CubieCube {
+        let mut result = CubieCube::SOLVED;
+        for i in 0..CORNERS {
+            let cubie = self.cp[i] as usize;
+            result.cp[cubie] = i as u8;
+            result.co[cubie] = (3 - self.co[i]) % 3;
+        }
+        for i in 0..EDGES {
+            let cubie = self.ep[i] as usize;
+            result.ep[cubie] = i as u8;
+            result.eo[cubie] = self.eo[i];
+        }
+        result
+    }
+
+    /// Turns the outer layer of a side 1 to 3 quarter turns, using the precomputed table of
+    /// face turns
+    pub fn apply_face_turn(&mut self, side: Side, turns: u8) {
+        *self = self.multiply(face_turn(side, turns));
+    }
+
+    /// Applies moves of any kind. Slices and wide moves are face turns followed by a rotation,
+    /// and a rotation relabels the pieces after the centers that moved. Moves of layers a 3x3
+    /// does not have do nothing, like on the stickers.
+    pub fn apply_moves(&mut self, moves: &[Move]) {
+        for m in moves {
+            if m.layers.span(3).is_none() {
+                continue;
+            }
+            for _ in 0..m.turns {
+                match m.layers {
+                    Layers::Outer(side) | Layers::Wide(side, 1) | Layers::Inner(side, 1) => {
+                        self.apply_face_turn(side, 1)
+                    }
+                    Layers::Wide(side, 2) => {
+                        let (rotation, turns) = rotation_like(side);
+                        self.apply_face_turn(side.opposite(), 1);
+                        self.apply_rotation(rotation, turns);
+                    }
+                    Layers::Wide(side, _) => {
+                        let (rotation, turns) = rotation_like(side);
+                        self.apply_rotation(rotation, turns);
+                    }
+                    Layers::Inner(side, 2) => {
+                        // The middle layer, e.g. 2R is L R' x
+                        let (rotation, turns) = rotation_like(side);
+                        self.apply_face_turn(side.opposite(), 1);
+                        self.apply_face_turn(side, 3);
+                        self.apply_rotation(rotation, turns);
+                    }
+                    Layers::Inner(side, _) => self.apply_face_turn(side.opposite(), 3),
+                    Layers::Slice(slice) => {
+                        // M is R L' x', E is U D' y' and S is F' B z
+                        let (side, rotation, turns) = match slice {
+                            Slice::M => (Side::R, Rotation::X, 3),
+                            Slice::E => (Side::U, Rotation::Y, 3),
+                            Slice::S => (Side::B, Rotation::Z, 1),
+                        };
+                        self.apply_face_turn(side, 1);
+                        self.apply_face_turn(side.opposite(), 3);
+                        self.apply_rotation(rotation, turns);
+                    }
+                    Layers::Rotation(rotation) => self.apply_rotation(rotation, 1),
+                }
+            }
+        }
+    }
+
+    fn apply_rotation(&mut self, rotation: Rotation, turns: u8) {
+        let rotated = &rotations()[rotation as usize];
+        for _ in 0..turns {
+            *self = rotated.inverse().multiply(self).multiply(rotated);
+        }
+    }
+
+    /// Checks that the cube can be solved: every piece once, and no twisted corner, flipped
+    /// edge or swapped pair
+    pub fn verify(&self) -> Result<(), CubieError> {
+        if !is_permutation(&self.cp) || !is_permutation(&self.ep) {
+            return Err(CubieError::DuplicateCubie);
+        }
+        if !self
+            .co
+            .iter()
+            .map(|&o| o as u32)
+            .sum::<u32>()
+            .is_multiple_of(3)
+        {
+            return Err(CubieError::Twist);
+        }
+        if !self
+            .eo
+            .iter()
+            .map(|&o| o as u32)
+            .sum::<u32>()
+            .is_multiple_of(2)
+        {
+            return Err(CubieError::Flip);
+        }
+        if permutation_parity(&self.cp) != permutation_parity(&self.ep) {
+            return Err(CubieError::Parity);
+        }
+        Ok(())
+    }
+}
+
+impl Default for CubieCube {
+    fn default() -> Self {
+        CubieCube::SOLVED
+    }
+}
+
+fn is_permutation(pieces: &[u8]) -> bool {
+    let mut seen = vec![false; pieces.len()];
+    pieces.iter().all(|&piece| {
+        let piece = piece as usize;
+        piece < seen.len() && !std::mem::replace(&mut seen[piece], true)
+    })
+}
+
+/// 1 for an odd permutation, 0 for an even one
+pub fn permutation_parity(pieces: &[u8]) -> u8 {
+    let mut parity = 0;
+    for i in 0..pieces.len() {
+        for j in i + 1..pieces.len() {
+            if pieces[i] > pieces[j] {
+                parity ^= 1;
+            }
+        }
+    }
+    parity
+}
+
+/// Quarter turns of the outer layers, in the order of `Side`
+fn basic_turns() -> [CubieCube; 6] {
+    let u = CubieCube {
+        cp: [UBR, URF, UFL, ULB, DFR, DLF, DBL, DRB],
+        co: [0; CORNERS],
+        ep: [UB, UR, UF, UL, DR, DF, DL, DB, FR, FL, BL, BR],
+        eo: [0; EDGES],
+    };
+    let d = CubieCube {
+        cp: [URF, UFL, ULB, UBR, DLF, DBL, DRB, DFR],
+        co: [0; CORNERS],
+        ep: [UR, UF, UL, UB, DF, DL, DB, DR, FR, FL, BL, BR],
+        eo: [0; EDGES],
+    };
+    let f = CubieCube {
+        cp: [UFL, DLF, ULB, UBR, URF, DFR, DBL, DRB],
+        co: [1, 2, 0, 0, 2, 1, 0, 0],
+        ep: [UR, FL, UL, UB, DR, FR, DL, DB, UF, DF, BL, BR],
+        eo: [0, 1, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0],
+    };
+    let b = CubieCube {
+        cp: [URF, UFL, UBR, DRB, DFR, DLF, ULB, DBL],
+        co: [0, 0, 1, 2, 0, 0, 2, 1],
+        ep: [UR, UF, UL, BR, DR, DF, DL, BL, FR, FL, UB, DB],
+        eo: [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1],
+    };
+    let l = CubieCube {
+        cp: [URF, ULB, DBL, UBR, DFR, UFL, DLF, DRB],
+        co: [0, 1, 2, 0, 0, 2, 1, 0],
+        ep: [UR, UF, BL, UB, DR, DF, FL, DB, FR, UL, DL, BR],
+        eo: [0; EDGES],
+    };
+    let r = CubieCube {
+        cp: [DFR, UFL, ULB, URF, DRB, DLF, DBL, UBR],
+        co: [2, 0, 0, 1, 1, 0, 0, 2],
+        ep: [FR, UF, UL, UB, BR, DF, DL, DB, DR, FL, BL, UR],
+        eo: [0; EDGES],
+    };
+    [u, d, f, b, l, r]
+}
+
+/// The 18 face turns, `side * 3 + turns - 1`, computed once
+fn face_turns() -> &'static [CubieCube; 18] {
+    static TABLE: OnceLock<[CubieCube; 18]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [CubieCube::SOLVED; 18];
+        for (side, turn) in basic_turns().iter().enumerate() {
+            let mut cube = CubieCube::SOLVED;
+            for turns in 0..3 {
+                cube = cube.multiply(turn);
+                table[side * 3 + turns] = cube;
+            }
+        }
+        table
+    })
+}
+
+/// A face turn of 1 to 3 quarter turns clockwise
+pub fn face_turn(side: Side, turns: u8) -> &'static CubieCube {
+    &face_turns()[side as usize * 3 + turns as usize - 1]
+}
+
+/// The rotation turning the whole cube like a side, e.g. x for R: wide moves are the opposite
+/// outer layer and this rotation, e.g. Rw is L x
+fn rotation_like(side: Side) -> (Rotation, u8) {
+    match side {
+        Side::R => (Rotation::X, 1),
+        Side::L => (Rotation::X, 3),
+        Side::U => (Rotation::Y, 1),
+        Side::D => (Rotation::Y, 3),
+        Side::F => (Rotation::Z, 1),
+        Side::B => (Rotation::Z, 3),
+    }
+}
+
+/// Where x, y and z take the pieces while the centers turn along, in the order of `Rotation`
+fn rotations() -> &'static [CubieCube; 3] {
+    static TABLE: OnceLock<[CubieCube; 3]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        [Rotation::X, Rotation::Y, Rotation::Z].map(|rotation| {
+            let mut state = CubeState::solved(3);
+            state.apply_move(Move::new(Layers::Rotation(rotation), 1));
+            // Read against the faces of a solved cube, not the centers that moved
+            read_cubies(&state, [U, D, F, B, L, R, NO_FACE]).expect("a rotated solved cube")
+        })
+    })
+}
+
+/// Face of the colors no face has, gray
+const NO_FACE: usize = usize::MAX;
+
+/// Face of each color, read from the centers
+fn face_of_colors(state: &CubeState) -> Result<[usize; 7], CubieError> {
+    let mut faces = [NO_FACE; 7];
+    for (face, stickers) in state.faces.iter().enumerate() {
+        let color = stickers.stickers[4];
+        if color == Color::Gray || faces[color as usize] != NO_FACE {
+            return Err(CubieError::Centers);
+        }
+        faces[color as usize] = face;
+    }
+    Ok(faces)
+}
+
+/// Pieces of the stickers, with `face_of` the face each color belongs to
+fn read_cubies(state: &CubeState, face_of: [usize; 7]) -> Result<CubieCube, CubieError> {
+    let face_at =
+        |(face, index): (usize, usize)| face_of[state.faces[face].stickers[index] as usize];
+
+    let mut cube = CubieCube::SOLVED;
+    for (position, facelets) in CORNER_FACELETS.iter().enumerate() {
+        let faces = facelets.map(face_at);
+        let unknown = CubieError::UnknownCorner(position);
+        let orientation = faces
+            .iter()
+            .position(|&face| face == U || face == D)
+            .ok_or(unknown)?;
+        let turned = [faces[(orientation + 1) % 3], faces[(orientation + 2) % 3]];
+        let cubie = CORNER_FACELETS
+            .iter()
+            .position(|corner| [corner[1].0, corner[2].0] == turned)
+            .ok_or(unknown)?;
+        cube.cp[position] = cubie as u8;
+        cube.co[position] = orientation as u8;
+    }
+    for (position, facelets) in EDGE_FACELETS.iter().enumerate() {
+        let faces = facelets.map(face_at);
+        let (cubie, orientation) = EDGE_FACELETS
+            .iter()
+            .enumerate()
+            .find_map(|(cubie, edge)| {
+                let edge = [edge[0].0, edge[1].0];
+                if edge == faces {
+                    Some((cubie, 0))
+                } else if edge == [faces[1], faces[0]] {
+                    Some((cubie, 1))
+                } else {
+                    None
+                }
+            })
+            .ok_or(CubieError::UnknownEdge(position))?;
+        cube.ep[position] = cubie as u8;
+        cube.eo[position] = orientation;
+    }
+    Ok(cube)
+}
+
+impl TryFrom<&CubeState> for CubieCube {
+    type Error = CubieError;
+
+    /// Reads the pieces from the stickers, relative to the centers, so however the cube is held.
+    /// Fails on stickers no real cube has, and on cubes that cannot be solved.
+    fn try_from(state: &CubeState) -> Result<Self, Self::Error> {
+        if state.size != 3 {
+            return Err(CubieError::Size(state.size));
+        }
+        let cube = read_cubies(state, face_of_colors(state)?)?;
+        cube.verify()?;
+        Ok(cube)
+    }
+}
+
+impl From<&CubieCube> for CubeState {
+    /// Stickers of the cube, with the centers in their usual place
+    fn from(cube: &CubieCube) -> Self {
+        let mut state = CubeState::solved(3);
+        let solved = CubeState::solved(3);
+        let color_of = |face: usize| -> Color { solved.faces[face].stickers[4] };
+        for (position, facelets) in CORNER_FACELETS.iter().enumerate() {
+            let cubie = &CORNER_FACELETS[cube.cp[position] as usize];
+            for n in 0..3 {
+                let (face, index) = facelets[(n + cube.co[position] as usize) % 3];
+                state.faces[face].stickers[index] = color_of(cubie[n].0);
+            }
+        }
+        for (position, facelets) in EDGE_FACELETS.iter().enumerate() {
+            let cubie = &EDGE_FACELETS[cube.ep[position] as usize];
+            for n in 0..2 {
+                let (face, index) = facelets[(n + cube.eo[position] as usize) % 2];
+                state.faces[face].stickers[index] = color_of(cubie[n].0);
+            }
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::parse_scramble;
+
+    /// Every kind of move a 3x3 has, turned by one of `TURNS`
+    const MOVES: [&str; 27] = [
+        "R", "L", "U", "D", "F", "B", "Rw", "Lw", "Uw", "Dw", "Fw", "Bw", "r", "b", "3Rw", "3Fw",
+        "2R", "2U", "3L", "M", "E", "S", "x", "y", "z", "1F", "3d",
+    ];
+    const TURNS: [&str; 3] = ["", "2", "'"];
+
+    /// Xorshift, enough to pick moves and stable across runs
+    fn next(seed: &mut u64) -> usize {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        (*seed >> 11) as usize
+    }
+
+    fn random_scramble(seed: &mut u64, length: usize) -> String {
+        (0..length)
+            .map(|_| {
+                let m = MOVES[next(seed) % MOVES.len()];
+                format!("{m}{}", TURNS[next(seed) % TURNS.len()])
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn stickers_after(moves: &[Move]) -> CubeState {
+        let mut state = CubeState::solved(3);
+        for &m in moves {
+            state.apply_move(m);
+        }
+        state
+    }
+
+    #[test]
+    fn matches_the_stickers() {
+        let mut seed = 0x5eed;
+        for _ in 0..500 {
+            let scramble = random_scramble(&mut seed, 25);
+            let moves = parse_scramble(&scramble).unwrap();
+            let mut cube = CubieCube::SOLVED;
+            cube.apply_moves(&moves);
+            assert_eq!(
+                CubieCube::try_from(&stickers_after(&moves)),
+                Ok(cube),
+                "{scramble}"
+            );
+        }
+    }
+
+    #[test]
+    fn writes_the_stickers_it_reads() {
+        let mut seed = 0xcafe;
+        for _ in 0..100 {
+            let scramble = random_scramble(&mut seed, 25);
+            let mut cube = CubieCube::SOLVED;
+            cube.apply_moves(&parse_scramble(&scramble).unwrap());
+            assert_eq!(
+                CubieCube::try_from(&CubeState::from(&cube)),
+                Ok(cube),
+                "{scramble}"
+            );
+            assert_eq!(cube.multiply(&cube.inverse()), CubieCube::SOLVED);
+        }
+    }
+
+    #[test]
+    fn finds_unsolvable_cubes() {
+        let mut cube = CubieCube::SOLVED;
+        cube.co[0] = 1;
+        assert_eq!(cube.verify(), Err(CubieError::Twist));
+
+        let mut cube = CubieCube::SOLVED;
+        cube.eo[0] = 1;
+        assert_eq!(cube.verify(), Err(CubieError::Flip));
+
+        let mut cube = CubieCube::SOLVED;
+        cube.ep.swap(0, 1);
+        assert_eq!(cube.verify(), Err(CubieError::Parity));
+
+        let mut cube = CubieCube::SOLVED;
+        cube.cp[0] = cube.cp[1];
+        assert_eq!(cube.verify(), Err(CubieError::DuplicateCubie));
+
+        assert_eq!(
+            CubieCube::try_from(&CubeState::solved(4)),
+            Err(CubieError::Size(4))
+        );
+    }
+}
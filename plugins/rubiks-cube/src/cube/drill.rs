@@ -0,0 +1,213 @@
+//! Algorithm drills: the cube starts in a named case, e.g. an OLL or PLL, and the learner has to
+//! reach the case's target with about as many moves as a known algorithm takes.
+
+use serde::Deserialize;
+use std::fmt;
+
+use super::cubie::pieces;
+use super::moves::{Layers, Move, ParseError, parse_scramble};
+use super::state::{Color, CubeState};
+
+/// Color of the last layer: the one on U when the case was set up from a solved cube
+const LAST_LAYER: Color = Color::White;
+
+/// What the learner has to reach for the case to be done
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// The first two layers, whatever the last layer looks like
+    F2l,
+    /// The first two layers, and the last layer's color all on one side
+    Oll,
+    /// The whole cube
+    #[default]
+    Solved,
+}
+
+impl Target {
+    /// Whether the stickers show the target, however the cube is held
+    pub fn reached(self, state: &CubeState) -> bool {
+        if self == Target::Solved {
+            return state.is_solved();
+        }
+        let color = |(face, index): (usize, usize)| state.faces[face].stickers[index];
+        let in_place = |facelet: (usize, usize)| color(facelet) == color((facelet.0, 4));
+        pieces().all(|piece| {
+            if !piece.iter().any(|&facelet| color(facelet) == LAST_LAYER) {
+                piece.iter().all(|&facelet| in_place(facelet))
+            } else if self == Target::Oll {
+                piece
+                    .iter()
+                    .all(|&facelet| color(facelet) != LAST_LAYER || in_place(facelet))
+            } else {
+                true
+            }
+        })
+    }
+
+    /// The stickers as shown to the learner, gray for those that do not matter: the last layer
+    /// for F2L, and all but its color for OLL
+    pub fn masked(self, state: &CubeState) -> CubeState {
+        let mut shown = state.clone();
+        if self == Target::Solved {
+            return shown;
+        }
+        for piece in pieces() {
+            let colors: Vec<Color> = piece
+                .iter()
+                .map(|&(face, index)| state.faces[face].stickers[index])
+                .collect();
+            if !colors.contains(&LAST_LAYER) {
+                continue;
+            }
+            for (&(face, index), &color) in piece.iter().zip(&colors) {
+                if self == Target::F2l || color != LAST_LAYER {
+                    shown.faces[face].stickers[index] = Color::Gray;
+                }
+            }
+        }
+        shown
+    }
+}
+
+/// Card data of an algorithm drill, `{"mode": "alg", ...}`
+#[derive(Debug, Deserialize)]
+pub struct AlgCard {
+    /// Name of the case, e.g. `T-perm`
+    pub case: String,
+    /// An algorithm solving the case, in WCA notation. The case is its inverse applied to a
+    /// solved cube.
+    pub setup: String,
+    /// Algorithms taught for the case
+    #[serde(default)]
+    pub accepted: Vec<String>,
+    #[serde(default)]
+    pub target: Target,
+    /// Most moves allowed, by default those of the longest of `setup` and `accepted`
+    pub budget: Option<u32>,
+}
+
+/// Why a card cannot be drilled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DrillError {
+    /// The setup or an accepted algorithm cannot be read
+    Algorithm(ParseError),
+    /// The setup leaves the cube at the target, so the drill would be done before any move
+    AlreadyReached(Target),
+}
+
+impl From<ParseError> for DrillError {
+    fn from(error: ParseError) -> Self {
+        DrillError::Algorithm(error)
+    }
+}
+
+impl fmt::Display for DrillError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrillError::Algorithm(error) => write!(f, "invalid algorithm: {error}"),
+            DrillError::AlreadyReached(target) => {
+                let target = format!("{target:?}").to_ascii_lowercase();
+                write!(f, "the setup already reaches the {target} target")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DrillError {}
+
+/// An algorithm drill in progress
+pub struct Drill {
+    pub target: Target,
+    pub budget: u32,
+    /// The case, to start over from
+    pub start: CubeState,
+}
+
+impl Drill {
+    pub fn new(card: &AlgCard) -> Result<Drill, DrillError> {
+        let setup = parse_scramble(&card.setup)?;
+        let mut longest = hand_moves(&setup);
+        for algorithm in &card.accepted {
+            longest = longest.max(hand_moves(&parse_scramble(algorithm)?));
+        }
+
+        let mut start = CubeState::solved(3);
+        for m in setup.iter().rev() {
+            start.apply_move(m.inverse());
+        }
+        if card.target.reached(&start) {
+            return Err(DrillError::AlreadyReached(card.target));
+        }
+        Ok(Drill {
+            target: card.target,
+            budget: card.budget.unwrap_or(longest),
+            start,
+        })
+    }
+}
+
+/// Moves as counted when dragging the layers: a rotation takes none since the camera turns
+/// instead
+fn hand_moves(moves: &[Move]) -> u32 {
+    moves
+        .iter()
+        .map(|m| match m.layers {
+            Layers::Rotation(_) => 0,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T_PERM: &str = "R U R' U' R' F R2 U' R' U' R U R' F'";
+
+    fn card(setup: &str, target: Target) -> AlgCard {
+        AlgCard {
+            case: "test".to_string(),
+            setup: setup.to_string(),
+            accepted: Vec::new(),
+            target,
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn starts_from_the_case() {
+        let mut card = card(T_PERM, Target::Solved);
+        card.accepted = vec!["x R2 D2 R U R' D2 R U' R x'".to_string()];
+        let drill = Drill::new(&card).unwrap();
+        assert_eq!(drill.budget, 14);
+        assert!(!Target::Solved.reached(&drill.start));
+        assert!(Target::Oll.reached(&drill.start));
+
+        let mut solved = drill.start.clone();
+        for m in parse_scramble(T_PERM).unwrap() {
+            solved.apply_move(m);
+        }
+        assert!(solved.is_solved());
+    }
+
+    #[test]
+    fn refuses_cases_already_at_their_target() {
+        assert_eq!(
+            Drill::new(&card("", Target::Solved)).err(),
+            Some(DrillError::AlreadyReached(Target::Solved))
+        );
+        assert_eq!(
+            Drill::new(&card(T_PERM, Target::F2l)).err(),
+            Some(DrillError::AlreadyReached(Target::F2l))
+        );
+        assert_eq!(
+            Drill::new(&card(T_PERM, Target::Oll)).err(),
+            Some(DrillError::AlreadyReached(Target::Oll))
+        );
+        assert!(matches!(
+            Drill::new(&card("R U Q", Target::Solved)),
+            Err(DrillError::Algorithm(ParseError { position: 4, .. }))
+        ));
+    }
+}
@@ -1,5 +1,11 @@
+mod cubie;
+mod drill;
 mod moves;
+mod solver;
 mod state;
 
-pub use moves::{Move, parse_scramble};
+pub use cubie::CubieCube;
+pub use drill::{AlgCard, Drill};
+pub use moves::{Axis, Layers, Move, Side, Slice, Span, parse_scramble};
+pub use solver::solve;
 pub use state::{B, CubeState, D, F, L, R, U};
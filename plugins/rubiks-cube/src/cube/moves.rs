@@ -1,56 +1,643 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
+/// Side of the cube a layer turn is named after
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Move {
-    R,
-    RPrime,
-    R2,
-    L,
-    LPrime,
-    L2,
+pub enum Side {
     U,
-    UPrime,
-    U2,
     D,
-    DPrime,
-    D2,
     F,
-    FPrime,
-    F2,
     B,
-    BPrime,
-    B2,
+    L,
+    R,
 }
 
-impl Move {
-    pub fn from_notation(s: &str) -> Option<Move> {
-        match s.trim() {
-            "R" => Some(Move::R),
-            "R'" => Some(Move::RPrime),
-            "R2" => Some(Move::R2),
-            "L" => Some(Move::L),
-            "L'" => Some(Move::LPrime),
-            "L2" => Some(Move::L2),
-            "U" => Some(Move::U),
-            "U'" => Some(Move::UPrime),
-            "U2" => Some(Move::U2),
-            "D" => Some(Move::D),
-            "D'" => Some(Move::DPrime),
-            "D2" => Some(Move::D2),
-            "F" => Some(Move::F),
-            "F'" => Some(Move::FPrime),
-            "F2" => Some(Move::F2),
-            "B" => Some(Move::B),
-            "B'" => Some(Move::BPrime),
-            "B2" => Some(Move::B2),
+impl Side {
+    fn from_letter(c: char) -> Option<Side> {
+        match c.to_ascii_uppercase() {
+            'U' => Some(Side::U),
+            'D' => Some(Side::D),
+            'F' => Some(Side::F),
+            'B' => Some(Side::B),
+            'L' => Some(Side::L),
+            'R' => Some(Side::R),
             _ => None,
         }
     }
+
+    fn letter(self) -> char {
+        match self {
+            Side::U => 'U',
+            Side::D => 'D',
+            Side::F => 'F',
+            Side::B => 'B',
+            Side::L => 'L',
+            Side::R => 'R',
+        }
+    }
+
+    /// Axis through the side, and whether the side is at its positive end: R, U and F are
+    fn axis(self) -> (Axis, bool) {
+        match self {
+            Side::R => (Axis::X, true),
+            Side::L => (Axis::X, false),
+            Side::U => (Axis::Y, true),
+            Side::D => (Axis::Y, false),
+            Side::F => (Axis::Z, true),
+            Side::B => (Axis::Z, false),
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::U => Side::D,
+            Side::D => Side::U,
+            Side::F => Side::B,
+            Side::B => Side::F,
+            Side::L => Side::R,
+            Side::R => Side::L,
+        }
+    }
+}
+
+/// Middle layer between two opposite sides
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slice {
+    /// Between L and R, turns like L
+    M,
+    /// Between U and D, turns like D
+    E,
+    /// Between F and B, turns like F
+    S,
+}
+
+/// Axis of a whole-cube rotation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    /// Turns like R
+    X,
+    /// Turns like U
+    Y,
+    /// Turns like F
+    Z,
+}
+
+/// Layers turned by a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layers {
+    /// The outer layer of a side, e.g. `R`
+    Outer(Side),
+    /// The given number of outer layers of a side, e.g. 2 for `Rw` or `r`, 3 for `3Rw`
+    Wide(Side, u8),
+    /// A single inner layer, counted from a side, e.g. 2 for `2R`
+    Inner(Side, u8),
+    /// The layers between two opposite sides, the middle one on a 3x3, e.g. `M`
+    Slice(Slice),
+    /// The whole cube, e.g. `x`
+    Rotation(Rotation),
+}
+
+/// Axis through the centers of two opposite sides, named after the rotation around it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    /// From L to R
+    X,
+    /// From D to U
+    Y,
+    /// From B to F
+    Z,
+}
+
+/// Layers of a cube turned together, by index along an axis from the L, D or B side
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub axis: Axis,
+    pub first: usize,
+    pub last: usize,
+    /// Whether a clockwise turn of the move is clockwise looking from R, U or F
+    pub positive: bool,
+}
+
+impl Span {
+    pub fn contains(&self, layer: usize) -> bool {
+        (self.first..=self.last).contains(&layer)
+    }
+}
+
+impl Layers {
+    /// The layers turned on a cube of `size` layers, `None` when the move needs more layers
+    pub fn span(self, size: usize) -> Option<Span> {
+        // Layers counted from a side, from 1 for the outer one
+        let from_side = |side: Side, outer: usize, inner: usize| {
+            let (axis, positive) = side.axis();
+            if inner == 0 || inner > size {
+                return None;
+            }
+            let (first, last) = if positive {
+                (size - inner, size - outer)
+            } else {
+                (outer - 1, inner - 1)
+            };
+            Some(Span {
+                axis,
+                first,
+                last,
+                positive,
+            })
+        };
+        match self {
+            Layers::Outer(side) => from_side(side, 1, 1),
+            Layers::Wide(side, depth) => from_side(side, 1, depth as usize),
+            Layers::Inner(side, layer) => from_side(side, layer as usize, layer as usize),
+            // Slices turn like L, D and F
+            Layers::Slice(slice) => {
+                let (axis, positive) = match slice {
+                    Slice::M => (Axis::X, false),
+                    Slice::E => (Axis::Y, false),
+                    Slice::S => (Axis::Z, true),
+                };
+                (size >= 3).then_some(Span {
+                    axis,
+                    first: 1,
+                    last: size - 2,
+                    positive,
+                })
+            }
+            // Rotations turn like R, U and F
+            Layers::Rotation(rotation) => {
+                let axis = match rotation {
+                    Rotation::X => Axis::X,
+                    Rotation::Y => Axis::Y,
+                    Rotation::Z => Axis::Z,
+                };
+                Some(Span {
+                    axis,
+                    first: 0,
+                    last: size - 1,
+                    positive: true,
+                })
+            }
+        }
+    }
+}
+
+/// A move in WCA notation: some layers turned by quarter turns, clockwise when looking at the
+/// side the move is named after
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub layers: Layers,
+    /// 1 for `R`, 2 for `R2` and 3 for `R'`
+    pub turns: u8,
+}
+
+impl Move {
+    pub fn new(layers: Layers, turns: u8) -> Move {
+        Move {
+            layers,
+            turns: turns % 4,
+        }
+    }
+
+    /// A quarter, half or counterclockwise turn of the outer layer of a side
+    pub fn outer(side: Side, turns: u8) -> Move {
+        Move::new(Layers::Outer(side), turns)
+    }
+
+    /// The move undoing this one
+    pub fn inverse(self) -> Move {
+        Move::new(self.layers, 4 - self.turns)
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.layers {
+            Layers::Outer(side) => write!(f, "{}", side.letter())?,
+            Layers::Wide(side, 2) => write!(f, "{}w", side.letter())?,
+            Layers::Wide(side, depth) => write!(f, "{depth}{}w", side.letter())?,
+            Layers::Inner(side, layer) => write!(f, "{layer}{}", side.letter())?,
+            Layers::Slice(slice) => write!(f, "{slice:?}")?,
+            Layers::Rotation(rotation) => {
+                write!(f, "{}", format!("{rotation:?}").to_ascii_lowercase())?
+            }
+        }
+        match self.turns {
+            2 => write!(f, "2"),
+            3 => write!(f, "'"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Most moves a scramble may expand to, repetitions of nested groups grow fast
+const MAX_MOVES: usize = 10_000;
+/// Most groups and brackets open at once, the parser recurses into each of them
+const MAX_DEPTH: usize = 64;
+
+/// Why a scramble cannot be read, and where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Offset of the offending character, in characters from the start of the notation
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reads WCA notation into the moves to apply, in order. Besides single moves, it reads the
+/// layer counts of bigger cubes like `3Rw` or `2R`, groups repeated a number of times like
+/// `(R U R' U')3`, commutators `[A, B]` (`A B A' B'`) and conjugates `[A: B]` (`A B A'`). A `'`
+/// after a group or bracket inverts it. Scrambles expanding to more than `MAX_MOVES` moves are
+/// refused.
+pub fn parse_scramble(notation: &str) -> Result<Vec<Move>, ParseError> {
+    let mut parser = Parser {
+        chars: notation.chars().collect(),
+        position: 0,
+        depth: 0,
+    };
+    let moves = parser.sequence()?;
+    match parser.peek() {
+        None => Ok(moves),
+        Some(c) => Err(parser.error(format!("unexpected '{c}'"))),
+    }
+}
+
+fn inverse_of(moves: &[Move]) -> Vec<Move> {
+    moves.iter().rev().map(|m| m.inverse()).collect()
+}
+
+/// `count` times the moves of the group starting at `start`, checked before allocating them
+fn repeat(moves: Vec<Move>, count: usize, start: usize) -> Result<Vec<Move>, ParseError> {
+    if moves.len().saturating_mul(count) > MAX_MOVES {
+        return Err(too_many_moves(start));
+    }
+    Ok(moves.repeat(count))
+}
+
+fn too_many_moves(position: usize) -> ParseError {
+    ParseError {
+        position,
+        message: format!("scramble expands to more than {MAX_MOVES} moves"),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// Groups and brackets opened and not closed yet
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<char> {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+        self.chars.get(self.position).copied()
+    }
+
+    /// The character right after the previous one, without skipping whitespace
+    fn next_if(&mut self, accept: impl Fn(char) -> bool) -> Option<char> {
+        let c = self
+            .chars
+            .get(self.position)
+            .copied()
+            .filter(|&c| accept(c))?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            position: self.position,
+            message,
+        }
+    }
+
+    /// Moves up to the end of the notation or of the enclosing group
+    fn sequence(&mut self) -> Result<Vec<Move>, ParseError> {
+        let mut moves = Vec::new();
+        while let Some(c) = self.peek() {
+            let start = self.position;
+            match c {
+                '(' => moves.extend(self.group()?),
+                '[' => moves.extend(self.bracket()?),
+                ')' | ']' | ',' | ':' => break,
+                _ => moves.push(self.single_move()?),
+            }
+            if moves.len() > MAX_MOVES {
+                return Err(too_many_moves(start));
+            }
+        }
+        Ok(moves)
+    }
+
+    /// Opens a group or bracket starting at `start`
+    fn enter(&mut self, start: usize) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(ParseError {
+                position: start,
+                message: format!("groups nested more than {MAX_DEPTH} deep"),
+            });
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// `(moves)`, optionally followed by a repetition count and a `'`
+    fn group(&mut self) -> Result<Vec<Move>, ParseError> {
+        let start = self.position;
+        self.enter(start)?;
+        let moves = self.sequence()?;
+        if self.peek() != Some(')') {
+            return Err(ParseError {
+                position: start,
+                message: "unclosed '('".to_string(),
+            });
+        }
+        self.position += 1;
+        self.depth -= 1;
+        let count = self.count()?;
+        let moves = self.prime_suffix(moves);
+        repeat(moves, count, start)
+    }
+
+    /// `[A, B]` or `[A: B]`, optionally followed by a repetition count and a `'`
+    fn bracket(&mut self) -> Result<Vec<Move>, ParseError> {
+        let start = self.position;
+        self.enter(start)?;
+        let a = self.sequence()?;
+        let separator = self.peek();
+        if !matches!(separator, Some(',' | ':')) {
+            return Err(self.error("expected ',' or ':' in brackets".to_string()));
+        }
+        self.position += 1;
+        let b = self.sequence()?;
+        if self.peek() != Some(']') {
+            return Err(ParseError {
+                position: start,
+                message: "unclosed '['".to_string(),
+            });
+        }
+        self.position += 1;
+        self.depth -= 1;
+
+        let mut moves = a.clone();
+        moves.extend(&b);
+        moves.extend(inverse_of(&a));
+        if separator == Some(',') {
+            moves.extend(inverse_of(&b));
+        }
+        let count = self.count()?;
+        let moves = self.prime_suffix(moves);
+        repeat(moves, count, start)
+    }
+
+    /// Repetitions of a group, 1 when not given
+    fn count(&mut self) -> Result<usize, ParseError> {
+        let start = self.position;
+        let mut digits = String::new();
+        while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        if digits.is_empty() {
+            return Ok(1);
+        }
+        match digits.parse::<usize>() {
+            Ok(count) if (1..=99).contains(&count) => Ok(count),
+            _ => Err(ParseError {
+                position: start,
+                message: format!("invalid repetition count {digits}"),
+            }),
+        }
+    }
+
+    /// Digits in front of a move, e.g. 3 in `3Rw`
+    fn layer_count(&mut self) -> Result<Option<u8>, ParseError> {
+        let start = self.position;
+        let mut digits = String::new();
+        while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        if self.chars.get(self.position).is_none() {
+            return Err(self.error("expected a side after the layer count".to_string()));
+        }
+        match digits.parse::<u8>() {
+            Ok(count) if count >= 1 => Ok(Some(count)),
+            _ => Err(ParseError {
+                position: start,
+                message: format!("invalid layer count {digits}"),
+            }),
+        }
+    }
+
+    fn prime_suffix(&mut self, moves: Vec<Move>) -> Vec<Move> {
+        if self.next_if(is_prime).is_some() {
+            inverse_of(&moves)
+        } else {
+            moves
+        }
+    }
+
+    fn single_move(&mut self) -> Result<Move, ParseError> {
+        let start = self.position;
+        let layer_count = self.layer_count()?;
+        let c = self.chars[self.position];
+        self.position += 1;
+        let layers = match c {
+            'U' | 'D' | 'F' | 'B' | 'L' | 'R' => {
+                let side = Side::from_letter(c).expect("a side letter");
+                let wide = self.next_if(|c| c == 'w').is_some();
+                match (layer_count, wide) {
+                    (None, true) => Layers::Wide(side, 2),
+                    (None | Some(1), _) => Layers::Outer(side),
+                    (Some(count), true) => Layers::Wide(side, count),
+                    (Some(count), false) => Layers::Inner(side, count),
+                }
+            }
+            'u' | 'd' | 'f' | 'b' | 'l' | 'r' => {
+                let side = Side::from_letter(c).expect("a side letter");
+                match layer_count {
+                    Some(1) => Layers::Outer(side),
+                    count => Layers::Wide(side, count.unwrap_or(2)),
+                }
+            }
+            _ if layer_count.is_some() => {
+                return Err(ParseError {
+                    position: self.position - 1,
+                    message: format!("expected a side after the layer count, not '{c}'"),
+                });
+            }
+            'M' => Layers::Slice(Slice::M),
+            'E' => Layers::Slice(Slice::E),
+            'S' => Layers::Slice(Slice::S),
+            'x' => Layers::Rotation(Rotation::X),
+            'y' => Layers::Rotation(Rotation::Y),
+            'z' => Layers::Rotation(Rotation::Z),
+            _ => {
+                return Err(ParseError {
+                    position: start,
+                    message: format!("unknown move '{c}'"),
+                });
+            }
+        };
+
+        // `2`, `'` or both, `R2'` is the same as `R2`
+        let turns = match (self.next_if(|c| c == '2'), self.next_if(is_prime)) {
+            (Some(_), _) => 2,
+            (None, Some(_)) => 3,
+            (None, None) => 1,
+        };
+        // Moves may be written without spaces, e.g. `RUR'U'`, but `R3` or `R''` mean nothing
+        let trailing = self.chars.get(self.position).copied();
+        if let Some(c) = trailing.filter(|&c| c.is_ascii_digit() || is_prime(c)) {
+            return Err(self.error(format!("unexpected '{c}'")));
+        }
+        Ok(Move::new(layers, turns))
+    }
+}
+
+/// `'`, or the typographic apostrophe text editors like to replace it with
+fn is_prime(c: char) -> bool {
+    c == '\'' || c == '’'
 }
 
-pub fn parse_scramble(notation: &str) -> Vec<Move> {
-    notation
-        .split_whitespace()
-        .filter_map(Move::from_notation)
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notation(moves: &[Move]) -> String {
+        moves
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn expands_to(scramble: &str, expected: &str) {
+        let moves = parse_scramble(scramble).expect("a valid scramble");
+        assert_eq!(notation(&moves), expected, "{scramble}");
+    }
+
+    fn fails_at(scramble: &str, position: usize) {
+        let error = parse_scramble(scramble).expect_err(scramble);
+        assert_eq!(error.position, position, "{scramble}: {error}");
+    }
+
+    #[test]
+    fn reads_single_moves() {
+        expands_to("R U2 F' R2'", "R U2 F' R2");
+        expands_to("RUR'U'", "R U R' U'");
+        expands_to("R’", "R'");
+        expands_to("  M E' S2 x y' z2 ", "M E' S2 x y' z2");
+        expands_to("", "");
+    }
+
+    #[test]
+    fn reads_layer_counts() {
+        assert_eq!(
+            parse_scramble("Rw r 3Rw 3r 2R 1R 1r").unwrap(),
+            vec![
+                Move::new(Layers::Wide(Side::R, 2), 1),
+                Move::new(Layers::Wide(Side::R, 2), 1),
+                Move::new(Layers::Wide(Side::R, 3), 1),
+                Move::new(Layers::Wide(Side::R, 3), 1),
+                Move::new(Layers::Inner(Side::R, 2), 1),
+                Move::outer(Side::R, 1),
+                Move::outer(Side::R, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_groups_and_brackets() {
+        expands_to("(R U)2", "R U R U");
+        expands_to("(R U)'", "U' R'");
+        expands_to("(R U2)2'", "U2 R' U2 R'");
+        expands_to("[R, U]", "R U R' U'");
+        expands_to("[R: U]", "R U R'");
+        expands_to("[R, U]'", "U R U' R'");
+        expands_to("[F: [R, U]]", "F R U R' U' F'");
+        expands_to("[R U: (D)2]3", "R U D D U' R' R U D D U' R' R U D D U' R'");
+    }
+
+    #[test]
+    fn reports_where_scrambles_fail() {
+        fails_at("R U X", 4);
+        fails_at("R)", 1);
+        fails_at("R3", 1);
+        fails_at("R''", 2);
+        fails_at("3", 1);
+        fails_at("R 3M", 3);
+        fails_at("0R", 0);
+        fails_at("R (U F", 2);
+        fails_at("[R U]", 4);
+        fails_at("R [U, F", 2);
+        fails_at("(R)100", 3);
+        fails_at("(R)0", 3);
+    }
+
+    fn span_of(notation: &str, size: usize) -> Option<(Axis, usize, usize, bool)> {
+        let m = parse_scramble(notation).unwrap()[0];
+        m.layers
+            .span(size)
+            .map(|span| (span.axis, span.first, span.last, span.positive))
+    }
+
+    #[test]
+    fn spans_the_layers_of_the_cube() {
+        assert_eq!(span_of("3Rw", 2), None);
+        assert_eq!(span_of("2R", 2), Some((Axis::X, 0, 0, true)));
+        assert_eq!(span_of("M", 2), None);
+        assert_eq!(span_of("x", 2), Some((Axis::X, 0, 1, true)));
+
+        assert_eq!(span_of("3Rw", 4), Some((Axis::X, 1, 3, true)));
+        assert_eq!(span_of("2R", 4), Some((Axis::X, 2, 2, true)));
+        assert_eq!(span_of("2L", 4), Some((Axis::X, 1, 1, false)));
+        assert_eq!(span_of("M", 4), Some((Axis::X, 1, 2, false)));
+        assert_eq!(span_of("Dw", 4), Some((Axis::Y, 0, 1, false)));
+        assert_eq!(span_of("4Fw", 4), Some((Axis::Z, 0, 3, true)));
+        assert_eq!(span_of("5R", 4), None);
+
+        assert_eq!(span_of("S", 7), Some((Axis::Z, 1, 5, true)));
+        assert_eq!(span_of("3u", 7), Some((Axis::Y, 4, 6, true)));
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}R{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse_scramble(&nested(64)).unwrap().len(), 1);
+        fails_at(&nested(65), 64);
+        fails_at(&nested(100_000), 64);
+        fails_at(&format!("{}R, U{}", "([".repeat(33), "])".repeat(33)), 64);
+        // Groups one after the other are not nested
+        assert_eq!(parse_scramble(&"(R) ".repeat(100)).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn caps_expanded_moves() {
+        assert_eq!(parse_scramble("((R U)50)50").unwrap().len(), 5000);
+        fails_at("(((R U)99)99)99", 1);
+        // 9801 moves in the group, twice as many in the commutator
+        fails_at("[((R)99)99, U]", 0);
+
+        // Groups under the cap on their own, but not together
+        let scramble = "(R)99 ".repeat(102);
+        assert_eq!(parse_scramble(&scramble[..6 * 101]).unwrap().len(), 9999);
+        fails_at(&scramble, 6 * 101);
+    }
 }
@@ -0,0 +1,383 @@
+//! Kociemba's two-phase algorithm. Phase 1 brings the cube into the group generated by
+//! U, D, R2, L2, F2 and B2: no twisted corner, no flipped edge and the middle-layer edges in the
+//! middle layer. Phase 2 solves it with those moves only. Both phases are iterative deepening
+//! searches on small coordinates of the cube, pruned by tables of distances to their goal.
+
+use std::sync::OnceLock;
+
+use super::cubie::{CubieCube, CubieError, face_turn};
+use super::moves::{Move, Side};
+
+/// Sides in the order of their index in move numbers, `side * 3 + turns - 1`
+const SIDES: [Side; 6] = [Side::U, Side::D, Side::F, Side::B, Side::L, Side::R];
+const MOVES: usize = 18;
+/// U, U2, U', D, D2, D', F2, B2, L2 and R2
+const PHASE2_MOVES: [usize; 10] = [0, 1, 2, 3, 4, 5, 7, 10, 13, 16];
+
+const TWISTS: usize = 2187;
+const FLIPS: usize = 2048;
+/// Positions of the 4 middle-layer edges among the 12, 12 choose 4
+const SLICES: usize = 495;
+const CORNER_PERMS: usize = 40320;
+/// Permutations of the 8 edges of the U and D layers, in phase 2
+const EDGE_PERMS: usize = 40320;
+/// Permutations of the 4 middle-layer edges, in phase 2
+const SLICE_PERMS: usize = 24;
+
+const MAX_PHASE1_LENGTH: usize = 12;
+const MAX_PHASE2_LENGTH: usize = 18;
+const MAX_LENGTH: usize = 30;
+/// Positions looked at before settling for the shortest solution found so far
+const NODE_BUDGET: usize = 2_000_000;
+
+/// Moves solving the cube, near the shortest possible: the search keeps looking for shorter
+/// solutions after the first one for a while.
+pub fn solve(cube: &CubieCube) -> Result<Vec<Move>, CubieError> {
+    cube.verify()?;
+    let mut search = Search {
+        tables: tables(),
+        cube: *cube,
+        moves: Vec::new(),
+        best: None,
+        nodes: 0,
+    };
+    search.run();
+    let best = search.best.expect("a solvable cube has a solution");
+    Ok(best.into_iter().map(face_move).collect())
+}
+
+fn face_move(index: usize) -> Move {
+    Move::outer(SIDES[index / 3], (index % 3 + 1) as u8)
+}
+
+fn turn(index: usize) -> &'static CubieCube {
+    face_turn(SIDES[index / 3], (index % 3 + 1) as u8)
+}
+
+/// Turning the same side twice in a row, or opposite sides in both orders, only makes longer
+/// versions of shorter sequences
+fn follows(index: usize, previous: Option<&usize>) -> bool {
+    let Some(&previous) = previous else {
+        return true;
+    };
+    let (side, previous_side) = (index / 3, previous / 3);
+    // Opposite sides are next to each other in `SIDES`
+    side != previous_side && !(side ^ 1 == previous_side && side < previous_side)
+}
+
+fn twist(cube: &CubieCube) -> usize {
+    // The last corner's orientation follows from the others
+    cube.co[..7]
+        .iter()
+        .fold(0, |index, &orientation| index * 3 + orientation as usize)
+}
+
+fn flip(cube: &CubieCube) -> usize {
+    cube.eo[..11]
+        .iter()
+        .fold(0, |index, &orientation| index * 2 + orientation as usize)
+}
+
+/// Which positions hold the middle-layer edges FR, FL, BL and BR, 0 when they are in place
+fn slice(cube: &CubieCube) -> usize {
+    let mut index = 0;
+    let mut found = 0;
+    for (position, &edge) in cube.ep.iter().enumerate().rev() {
+        if edge >= 8 {
+            found += 1;
+            index += binomial(11 - position, found);
+        }
+    }
+    index
+}
+
+fn corner_perm(cube: &CubieCube) -> usize {
+    permutation_index(&cube.cp)
+}
+
+fn edge_perm(cube: &CubieCube) -> usize {
+    permutation_index(&cube.ep[..8])
+}
+
+fn slice_perm(cube: &CubieCube) -> usize {
+    permutation_index(&cube.ep[8..])
+}
+
+/// Rank of a permutation of distinct values among all of their orders, 0 when sorted
+fn permutation_index(pieces: &[u8]) -> usize {
+    let mut index = 0;
+    for (i, &piece) in pieces.iter().enumerate() {
+        let smaller_after = pieces[i + 1..].iter().filter(|&&p| p < piece).count();
+        index = index * (pieces.len() - i) + smaller_after;
+    }
+    index
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |result, i| result * (n - i) / (i + 1))
+}
+
+struct Tables {
+    twist_moves: Vec<u16>,
+    flip_moves: Vec<u16>,
+    slice_moves: Vec<u16>,
+    corner_perm_moves: Vec<u16>,
+    edge_perm_moves: Vec<u16>,
+    slice_perm_moves: Vec<u16>,
+    /// Phase 1 moves needed to fix the twist and the middle-layer edges, by
+    /// `slice * TWISTS + twist`
+    slice_twist_depth: Vec<u8>,
+    slice_flip_depth: Vec<u8>,
+    /// Phase 2 moves needed to solve the corners and the middle layer, by
+    /// `slice_perm * CORNER_PERMS + corner_perm`
+    slice_corner_depth: Vec<u8>,
+    slice_edge_depth: Vec<u8>,
+}
+
+/// The tables take a moment to compute, so they are computed on the first solve and kept
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let all_moves: Vec<usize> = (0..MOVES).collect();
+        let twist_moves = move_table(TWISTS, twist, &all_moves);
+        let flip_moves = move_table(FLIPS, flip, &all_moves);
+        let slice_moves = move_table(SLICES, slice, &all_moves);
+        let corner_perm_moves = move_table(CORNER_PERMS, corner_perm, &PHASE2_MOVES);
+        let edge_perm_moves = move_table(EDGE_PERMS, edge_perm, &PHASE2_MOVES);
+        let slice_perm_moves = move_table(SLICE_PERMS, slice_perm, &PHASE2_MOVES);
+
+        Tables {
+            slice_twist_depth: depth_table(
+                (&slice_moves, SLICES),
+                (&twist_moves, TWISTS),
+                &all_moves,
+            ),
+            slice_flip_depth: depth_table((&slice_moves, SLICES), (&flip_moves, FLIPS), &all_moves),
+            slice_corner_depth: depth_table(
+                (&slice_perm_moves, SLICE_PERMS),
+                (&corner_perm_moves, CORNER_PERMS),
+                &PHASE2_MOVES,
+            ),
+            slice_edge_depth: depth_table(
+                (&slice_perm_moves, SLICE_PERMS),
+                (&edge_perm_moves, EDGE_PERMS),
+                &PHASE2_MOVES,
+            ),
+            twist_moves,
+            flip_moves,
+            slice_moves,
+            corner_perm_moves,
+            edge_perm_moves,
+            slice_perm_moves,
+        }
+    })
+}
+
+/// The coordinate after each move, by `coordinate * MOVES + move`. Every value is reached from
+/// solved with `moves`, and any cube with that value stands for all of them.
+fn move_table(size: usize, coordinate: fn(&CubieCube) -> usize, moves: &[usize]) -> Vec<u16> {
+    let mut table = vec![0; size * MOVES];
+    let mut seen = vec![false; size];
+    seen[0] = true;
+    let mut reached = vec![CubieCube::SOLVED];
+    let mut next = 0;
+    while let Some(cube) = reached.get(next).copied() {
+        let from = coordinate(&cube);
+        for &m in moves {
+            let turned = cube.multiply(turn(m));
+            let to = coordinate(&turned);
+            table[from * MOVES + m] = to as u16;
+            if !seen[to] {
+                seen[to] = true;
+                reached.push(turned);
+            }
+        }
+        next += 1;
+    }
+    table
+}
+
+/// Fewest `moves` from each pair of coordinates to both being 0, by `outer * inner size + inner`
+fn depth_table(outer: (&[u16], usize), inner: (&[u16], usize), moves: &[usize]) -> Vec<u8> {
+    let (outer_moves, outer_size) = outer;
+    let (inner_moves, inner_size) = inner;
+    let mut depth = vec![u8::MAX; outer_size * inner_size];
+    depth[0] = 0;
+    let mut queue = vec![0u32];
+    let mut next = 0;
+    while let Some(index) = queue.get(next).map(|&index| index as usize) {
+        let (o, i) = (index / inner_size, index % inner_size);
+        for &m in moves {
+            let to = outer_moves[o * MOVES + m] as usize * inner_size
+                + inner_moves[i * MOVES + m] as usize;
+            if depth[to] == u8::MAX {
+                depth[to] = depth[index] + 1;
+                queue.push(to as u32);
+            }
+        }
+        next += 1;
+    }
+    depth
+}
+
+struct Search {
+    tables: &'static Tables,
+    cube: CubieCube,
+    /// Moves of the branch being searched, phase 1 then phase 2
+    moves: Vec<usize>,
+    best: Option<Vec<usize>>,
+    nodes: usize,
+}
+
+impl Search {
+    fn best_length(&self) -> usize {
+        self.best.as_ref().map_or(MAX_LENGTH + 1, Vec::len)
+    }
+
+    fn out_of_budget(&self) -> bool {
+        self.best.is_some() && self.nodes >= NODE_BUDGET
+    }
+
+    /// Phase 1 solutions of increasing length, each followed by the shortest phase 2 that makes
+    /// a better solution than the best so far
+    fn run(&mut self) {
+        let (twist, flip, slice) = (twist(&self.cube), flip(&self.cube), slice(&self.cube));
+        for length in 0..=MAX_PHASE1_LENGTH {
+            if length >= self.best_length() || self.out_of_budget() {
+                break;
+            }
+            self.phase1(twist, flip, slice, length);
+        }
+    }
+
+    fn phase1(&mut self, twist: usize, flip: usize, slice: usize, remaining: usize) {
+        if remaining == 0 {
+            // A phase 1 ending with a phase 2 move was already tried one move shorter
+            let last = self.moves.last();
+            if (twist, flip, slice) == (0, 0, 0) && last.is_none_or(|m| !PHASE2_MOVES.contains(m)) {
+                self.start_phase2();
+            }
+            return;
+        }
+        let t = self.tables;
+        for m in 0..MOVES {
+            if !follows(m, self.moves.last()) || self.out_of_budget() {
+                continue;
+            }
+            self.nodes += 1;
+            let twist = t.twist_moves[twist * MOVES + m] as usize;
+            let flip = t.flip_moves[flip * MOVES + m] as usize;
+            let slice = t.slice_moves[slice * MOVES + m] as usize;
+            let distance = t.slice_twist_depth[slice * TWISTS + twist]
+                .max(t.slice_flip_depth[slice * FLIPS + flip]) as usize;
+            if distance < remaining {
+                self.moves.push(m);
+                self.phase1(twist, flip, slice, remaining - 1);
+                self.moves.pop();
+            }
+        }
+    }
+
+    fn start_phase2(&mut self) {
+        let phase1_length = self.moves.len();
+        let mut cube = self.cube;
+        for &m in &self.moves {
+            cube = cube.multiply(turn(m));
+        }
+        let (corners, edges, slice) = (corner_perm(&cube), edge_perm(&cube), slice_perm(&cube));
+
+        let longest = MAX_PHASE2_LENGTH.min(self.best_length() - 1 - phase1_length);
+        for length in 0..=longest {
+            if self.phase2(corners, edges, slice, length) {
+                self.best = Some(self.moves.clone());
+                self.moves.truncate(phase1_length);
+                return;
+            }
+        }
+    }
+
+    /// Leaves the solution in `moves` when one of exactly `remaining` moves is found
+    fn phase2(&mut self, corners: usize, edges: usize, slice: usize, remaining: usize) -> bool {
+        if remaining == 0 {
+            return (corners, edges, slice) == (0, 0, 0);
+        }
+        let t = self.tables;
+        for m in PHASE2_MOVES {
+            if !follows(m, self.moves.last()) {
+                continue;
+            }
+            self.nodes += 1;
+            let corners = t.corner_perm_moves[corners * MOVES + m] as usize;
+            let edges = t.edge_perm_moves[edges * MOVES + m] as usize;
+            let slice = t.slice_perm_moves[slice * MOVES + m] as usize;
+            let distance = t.slice_corner_depth[slice * CORNER_PERMS + corners]
+                .max(t.slice_edge_depth[slice * EDGE_PERMS + edges])
+                as usize;
+            if distance < remaining {
+                self.moves.push(m);
+                if self.phase2(corners, edges, slice, remaining - 1) {
+                    return true;
+                }
+                self.moves.pop();
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xorshift, enough to pick moves and stable across runs
+    fn next(seed: &mut u64) -> usize {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        (*seed >> 11) as usize
+    }
+
+    fn assert_solves(cube: &CubieCube) {
+        let solution = solve(cube).expect("a solvable cube");
+        assert!(
+            solution.len() <= 27,
+            "{} moves for {cube:?}",
+            solution.len()
+        );
+        let mut solved = *cube;
+        solved.apply_moves(&solution);
+        assert_eq!(solved, CubieCube::SOLVED, "{cube:?}");
+    }
+
+    #[test]
+    fn solves_random_cubes() {
+        let mut seed = 0xc0be;
+        for _ in 0..30 {
+            let mut cube = CubieCube::SOLVED;
+            for _ in 0..40 {
+                cube = cube.multiply(turn(next(&mut seed) % MOVES));
+            }
+            assert_solves(&cube);
+        }
+    }
+
+    #[test]
+    fn solves_the_superflip() {
+        let superflip = CubieCube {
+            eo: [1; 12],
+            ..CubieCube::SOLVED
+        };
+        assert_solves(&superflip);
+    }
+
+    #[test]
+    fn refuses_unsolvable_cubes() {
+        let mut cube = CubieCube::SOLVED;
+        cube.co[0] = 2;
+        assert_eq!(solve(&cube), Err(CubieError::Twist));
+        assert_eq!(solve(&CubieCube::SOLVED), Ok(Vec::new()));
+    }
+}
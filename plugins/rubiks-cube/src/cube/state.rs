@@ -1,4 +1,5 @@
 use super::Move;
+use super::moves::{Axis, Layers, Rotation, Span};
 use serde::{Deserialize, Serialize};
 
 /// Face colors matching standard Rubik's cube convention
@@ -11,6 +12,8 @@ pub enum Color {
     Blue = 3,   // Back (B)
     Orange = 4, // Left (L)
     Red = 5,    // Right (R)
+    /// A sticker hidden because it does not matter, only in cubes made for display
+    Gray = 6,
 }
 
 impl Color {
@@ -22,38 +25,33 @@ impl Color {
             Color::Blue => [0.0, 0.32, 0.73],
             Color::Orange => [1.0, 0.35, 0.0],
             Color::Red => [0.72, 0.07, 0.2],
+            Color::Gray => [0.45, 0.45, 0.45],
         }
     }
 }
 
-/// Each face has 9 stickers in reading order:
+/// Each face has `size * size` stickers in reading order, e.g. on a 3x3:
 /// 0 1 2
 /// 3 4 5
 /// 6 7 8
+/// U is read with B at the top, D with F at the top, B with R on the left, and the side faces
+/// upright.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Face {
-    pub stickers: [Color; 9],
+    pub stickers: Vec<Color>,
 }
 
 impl Face {
-    pub fn new(color: Color) -> Self {
+    pub fn new(color: Color, size: usize) -> Self {
         Self {
-            stickers: [color; 9],
+            stickers: vec![color; size * size],
         }
     }
 
-    /// Rotate face clockwise
-    pub fn rotate_cw(&mut self) {
-        let old = self.stickers;
-        self.stickers = [
-            old[6], old[3], old[0], old[7], old[4], old[1], old[8], old[5], old[2],
-        ];
-    }
-
     /// Check if all stickers are the same color
     pub fn is_solved(&self) -> bool {
-        let center = self.stickers[4];
-        self.stickers.iter().all(|&s| s == center)
+        let first = self.stickers[0];
+        self.stickers.iter().all(|&s| s == first)
     }
 }
 
@@ -65,21 +63,34 @@ pub const B: usize = 3;
 pub const L: usize = 4;
 pub const R: usize = 5;
 
+/// Outward direction of each face
+const NORMALS: [[i32; 3]; 6] = [
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+    [-1, 0, 0],
+    [1, 0, 0],
+];
+
+/// A cube of `size` layers along each axis, 2 to 7 in practice
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CubeState {
+    pub size: usize,
     pub faces: [Face; 6],
 }
 
 impl CubeState {
-    pub fn solved() -> Self {
+    pub fn solved(size: usize) -> Self {
         Self {
+            size,
             faces: [
-                Face::new(Color::White),  // U
-                Face::new(Color::Yellow), // D
-                Face::new(Color::Green),  // F
-                Face::new(Color::Blue),   // B
-                Face::new(Color::Orange), // L
-                Face::new(Color::Red),    // R
+                Face::new(Color::White, size),  // U
+                Face::new(Color::Yellow, size), // D
+                Face::new(Color::Green, size),  // F
+                Face::new(Color::Blue, size),   // B
+                Face::new(Color::Orange, size), // L
+                Face::new(Color::Red, size),    // R
             ],
         }
     }
@@ -88,236 +99,246 @@ impl CubeState {
         self.faces.iter().all(|face| face.is_solved())
     }
 
-    pub fn apply_move(&mut self, m: Move) {
-        match m {
-            Move::R => self.move_r(),
-            Move::RPrime => self.move_r_prime(),
-            Move::R2 => {
-                self.move_r();
-                self.move_r();
-            }
-            Move::L => self.move_l(),
-            Move::LPrime => self.move_l_prime(),
-            Move::L2 => {
-                self.move_l();
-                self.move_l();
-            }
-            Move::U => self.move_u(),
-            Move::UPrime => self.move_u_prime(),
-            Move::U2 => {
-                self.move_u();
-                self.move_u();
-            }
-            Move::D => self.move_d(),
-            Move::DPrime => self.move_d_prime(),
-            Move::D2 => {
-                self.move_d();
-                self.move_d();
-            }
-            Move::F => self.move_f(),
-            Move::FPrime => self.move_f_prime(),
-            Move::F2 => {
-                self.move_f();
-                self.move_f();
-            }
-            Move::B => self.move_b(),
-            Move::BPrime => self.move_b_prime(),
-            Move::B2 => {
-                self.move_b();
-                self.move_b();
+    /// Whether the stickers are those of another cube, however either is held
+    pub fn matches(&self, other: &CubeState) -> bool {
+        let rotation = |rotation| Move::new(Layers::Rotation(rotation), 1);
+        let mut held = other.clone();
+        // Four turns around each axis go through all 24 ways to hold the cube, and back
+        for _ in 0..4 {
+            for _ in 0..4 {
+                for _ in 0..4 {
+                    let mut faces = held.faces.iter().zip(&self.faces);
+                    if faces.all(|(a, b)| a.stickers == b.stickers) {
+                        return true;
+                    }
+                    held.apply_move(rotation(Rotation::Z));
+                }
+                held.apply_move(rotation(Rotation::Y));
             }
+            held.apply_move(rotation(Rotation::X));
+        }
+        false
+    }
+
+    /// Whether the cube has the layers the move turns
+    pub fn fits(&self, m: Move) -> bool {
+        m.layers.span(self.size).is_some()
+    }
+
+    /// Applies a move, or nothing when the cube does not have the layers it turns
+    pub fn apply_move(&mut self, m: Move) {
+        let Some(span) = m.layers.span(self.size) else {
+            return;
+        };
+        for _ in 0..m.turns {
+            self.turn(span);
         }
     }
 
-    fn move_r(&mut self) {
-        self.faces[R].rotate_cw();
-        let temp = [
-            self.faces[F].stickers[2],
-            self.faces[F].stickers[5],
-            self.faces[F].stickers[8],
-        ];
-        // F right column -> U right column
-        self.faces[F].stickers[2] = self.faces[D].stickers[2];
-        self.faces[F].stickers[5] = self.faces[D].stickers[5];
-        self.faces[F].stickers[8] = self.faces[D].stickers[8];
-        // D right column -> B left column (reversed)
-        self.faces[D].stickers[2] = self.faces[B].stickers[6];
-        self.faces[D].stickers[5] = self.faces[B].stickers[3];
-        self.faces[D].stickers[8] = self.faces[B].stickers[0];
-        // B left column -> U right column (reversed)
-        self.faces[B].stickers[6] = self.faces[U].stickers[2];
-        self.faces[B].stickers[3] = self.faces[U].stickers[5];
-        self.faces[B].stickers[0] = self.faces[U].stickers[8];
-        // U right column -> F right column (from temp)
-        self.faces[U].stickers[2] = temp[0];
-        self.faces[U].stickers[5] = temp[1];
-        self.faces[U].stickers[8] = temp[2];
+    /// Color of a face at a cubelet, given by its layer along x (L to R), y (D to U) and z (B
+    /// to F)
+    pub fn sticker(&self, face: usize, cubelet: [usize; 3]) -> Color {
+        self.faces[face].stickers[self.sticker_index(face, cubelet)]
     }
 
-    fn move_r_prime(&mut self) {
-        self.move_r();
-        self.move_r();
-        self.move_r();
+    fn sticker_index(&self, face: usize, [x, y, z]: [usize; 3]) -> usize {
+        let last = self.size - 1;
+        let (row, col) = match face {
+            U => (z, x),
+            D => (last - z, x),
+            F => (last - y, x),
+            B => (last - y, last - x),
+            L => (last - y, z),
+            _ => (last - y, last - z),
+        };
+        row * self.size + col
     }
 
-    fn move_l(&mut self) {
-        self.faces[L].rotate_cw();
-        let temp = [
-            self.faces[F].stickers[0],
-            self.faces[F].stickers[3],
-            self.faces[F].stickers[6],
-        ];
-        // U left column -> F left column
-        self.faces[F].stickers[0] = self.faces[U].stickers[0];
-        self.faces[F].stickers[3] = self.faces[U].stickers[3];
-        self.faces[F].stickers[6] = self.faces[U].stickers[6];
-        // B right column -> U left column (reversed)
-        self.faces[U].stickers[0] = self.faces[B].stickers[8];
-        self.faces[U].stickers[3] = self.faces[B].stickers[5];
-        self.faces[U].stickers[6] = self.faces[B].stickers[2];
-        // D left column -> B right column (reversed)
-        self.faces[B].stickers[8] = self.faces[D].stickers[0];
-        self.faces[B].stickers[5] = self.faces[D].stickers[3];
-        self.faces[B].stickers[2] = self.faces[D].stickers[6];
-        // F left column -> D left column (from temp)
-        self.faces[D].stickers[0] = temp[0];
-        self.faces[D].stickers[3] = temp[1];
-        self.faces[D].stickers[6] = temp[2];
+    /// The cubelet a sticker is on, the inverse of `sticker_index`
+    fn cubelet(&self, face: usize, index: usize) -> [usize; 3] {
+        let last = self.size - 1;
+        let (row, col) = (index / self.size, index % self.size);
+        match face {
+            U => [col, last, row],
+            D => [col, 0, last - row],
+            F => [col, last - row, last],
+            B => [last - col, last - row, 0],
+            L => [0, last - row, col],
+            _ => [last, last - row, last - col],
+        }
     }
 
-    fn move_l_prime(&mut self) {
-        self.move_l();
-        self.move_l();
-        self.move_l();
+    /// Turns the layers of the span a quarter turn the way a clockwise move turns them: every
+    /// sticker in them moves to where its cubelet and side go
+    fn turn(&mut self, span: Span) {
+        let old = self.faces.clone();
+        let axis = match span.axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        };
+        for (face, stickers) in old.iter().enumerate() {
+            for (index, &color) in stickers.stickers.iter().enumerate() {
+                let cubelet = self.cubelet(face, index);
+                if !span.contains(cubelet[axis]) {
+                    continue;
+                }
+                // Centered coordinates, doubled to stay whole on even cubes
+                let doubled = cubelet.map(|c| 2 * c as i32 - (self.size as i32 - 1));
+                let moved = quarter_turn(doubled, axis, span.positive);
+                let normal = quarter_turn(NORMALS[face], axis, span.positive);
+                let to_face = NORMALS
+                    .iter()
+                    .position(|&n| n == normal)
+                    .expect("a face turns onto a face");
+                let to_cubelet = moved.map(|c| ((c + self.size as i32 - 1) / 2) as usize);
+                let to_index = self.sticker_index(to_face, to_cubelet);
+                self.faces[to_face].stickers[to_index] = color;
+            }
+        }
     }
+}
 
-    fn move_u(&mut self) {
-        self.faces[U].rotate_cw();
-        let temp = [
-            self.faces[F].stickers[0],
-            self.faces[F].stickers[1],
-            self.faces[F].stickers[2],
-        ];
-        // R top row -> F top row
-        self.faces[F].stickers[0] = self.faces[R].stickers[0];
-        self.faces[F].stickers[1] = self.faces[R].stickers[1];
-        self.faces[F].stickers[2] = self.faces[R].stickers[2];
-        // B top row -> R top row
-        self.faces[R].stickers[0] = self.faces[B].stickers[0];
-        self.faces[R].stickers[1] = self.faces[B].stickers[1];
-        self.faces[R].stickers[2] = self.faces[B].stickers[2];
-        // L top row -> B top row
-        self.faces[B].stickers[0] = self.faces[L].stickers[0];
-        self.faces[B].stickers[1] = self.faces[L].stickers[1];
-        self.faces[B].stickers[2] = self.faces[L].stickers[2];
-        // F top row -> L top row (from temp)
-        self.faces[L].stickers[0] = temp[0];
-        self.faces[L].stickers[1] = temp[1];
-        self.faces[L].stickers[2] = temp[2];
+/// A point turned a quarter turn around an axis, clockwise looking from its positive end when
+/// `clockwise`
+fn quarter_turn(point: [i32; 3], axis: usize, clockwise: bool) -> [i32; 3] {
+    let [x, y, z] = point;
+    match (axis, clockwise) {
+        (0, true) => [x, z, -y],
+        (0, false) => [x, -z, y],
+        (1, true) => [-z, y, x],
+        (1, false) => [z, y, -x],
+        (2, true) => [y, -x, z],
+        _ => [-y, x, z],
     }
+}
 
-    fn move_u_prime(&mut self) {
-        self.move_u();
-        self.move_u();
-        self.move_u();
+impl Default for CubeState {
+    fn default() -> Self {
+        Self::solved(3)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::moves::{Side, Slice};
+    use crate::cube::parse_scramble;
+
+    const SIZES: [usize; 3] = [2, 4, 7];
 
-    fn move_d(&mut self) {
-        self.faces[D].rotate_cw();
-        let temp = [
-            self.faces[F].stickers[6],
-            self.faces[F].stickers[7],
-            self.faces[F].stickers[8],
-        ];
-        // L bottom row -> F bottom row
-        self.faces[F].stickers[6] = self.faces[L].stickers[6];
-        self.faces[F].stickers[7] = self.faces[L].stickers[7];
-        self.faces[F].stickers[8] = self.faces[L].stickers[8];
-        // B bottom row -> L bottom row
-        self.faces[L].stickers[6] = self.faces[B].stickers[6];
-        self.faces[L].stickers[7] = self.faces[B].stickers[7];
-        self.faces[L].stickers[8] = self.faces[B].stickers[8];
-        // R bottom row -> B bottom row
-        self.faces[B].stickers[6] = self.faces[R].stickers[6];
-        self.faces[B].stickers[7] = self.faces[R].stickers[7];
-        self.faces[B].stickers[8] = self.faces[R].stickers[8];
-        // F bottom row -> R bottom row (from temp)
-        self.faces[R].stickers[6] = temp[0];
-        self.faces[R].stickers[7] = temp[1];
-        self.faces[R].stickers[8] = temp[2];
+    /// Every move the notation can write for a cube of `size` layers, some of which do not fit
+    fn all_moves(size: usize) -> Vec<Move> {
+        let sides = [Side::U, Side::D, Side::F, Side::B, Side::L, Side::R];
+        let mut layers = Vec::new();
+        for side in sides {
+            layers.push(Layers::Outer(side));
+            for count in 1..=size as u8 + 1 {
+                layers.push(Layers::Wide(side, count));
+                layers.push(Layers::Inner(side, count));
+            }
+        }
+        layers.extend([Slice::M, Slice::E, Slice::S].map(Layers::Slice));
+        layers.extend([Rotation::X, Rotation::Y, Rotation::Z].map(Layers::Rotation));
+        layers
+            .into_iter()
+            .map(|layers| Move::new(layers, 1))
+            .collect()
     }
 
-    fn move_d_prime(&mut self) {
-        self.move_d();
-        self.move_d();
-        self.move_d();
+    fn stickers(state: &CubeState) -> Vec<Vec<Color>> {
+        state
+            .faces
+            .iter()
+            .map(|face| face.stickers.clone())
+            .collect()
     }
 
-    fn move_f(&mut self) {
-        self.faces[F].rotate_cw();
-        let temp = [
-            self.faces[U].stickers[6],
-            self.faces[U].stickers[7],
-            self.faces[U].stickers[8],
-        ];
-        // L right column -> U bottom row
-        self.faces[U].stickers[6] = self.faces[L].stickers[8];
-        self.faces[U].stickers[7] = self.faces[L].stickers[5];
-        self.faces[U].stickers[8] = self.faces[L].stickers[2];
-        // D top row -> L right column
-        self.faces[L].stickers[2] = self.faces[D].stickers[0];
-        self.faces[L].stickers[5] = self.faces[D].stickers[1];
-        self.faces[L].stickers[8] = self.faces[D].stickers[2];
-        // R left column -> D top row (reversed)
-        self.faces[D].stickers[0] = self.faces[R].stickers[6];
-        self.faces[D].stickers[1] = self.faces[R].stickers[3];
-        self.faces[D].stickers[2] = self.faces[R].stickers[0];
-        // U bottom row -> R left column (from temp)
-        self.faces[R].stickers[0] = temp[0];
-        self.faces[R].stickers[3] = temp[1];
-        self.faces[R].stickers[6] = temp[2];
+    /// A scrambled cube, on which every turn moves some stickers to another color
+    fn scrambled(size: usize) -> CubeState {
+        let mut state = CubeState::solved(size);
+        for m in parse_scramble("R U2 F' L D' B2 R' U F2 D").unwrap() {
+            state.apply_move(m);
+        }
+        if size > 3 {
+            for m in parse_scramble("2R 2U' 3Fw 2L2 2D").unwrap() {
+                state.apply_move(m);
+            }
+        }
+        state
     }
 
-    fn move_f_prime(&mut self) {
-        self.move_f();
-        self.move_f();
-        self.move_f();
+    #[test]
+    fn four_quarter_turns_go_back() {
+        for size in SIZES {
+            let start = scrambled(size);
+            for m in all_moves(size).into_iter().filter(|&m| start.fits(m)) {
+                let mut state = start.clone();
+                state.apply_move(m);
+                assert_ne!(stickers(&state), stickers(&start), "{m} on {size}x{size}");
+                for _ in 0..3 {
+                    state.apply_move(m);
+                }
+                assert_eq!(stickers(&state), stickers(&start), "{m} on {size}x{size}");
+            }
+        }
     }
 
-    fn move_b(&mut self) {
-        self.faces[B].rotate_cw();
-        let temp = [
-            self.faces[U].stickers[0],
-            self.faces[U].stickers[1],
-            self.faces[U].stickers[2],
-        ];
-        // R right column -> U top row
-        self.faces[U].stickers[0] = self.faces[R].stickers[2];
-        self.faces[U].stickers[1] = self.faces[R].stickers[5];
-        self.faces[U].stickers[2] = self.faces[R].stickers[8];
-        // D bottom row -> R right column (reversed)
-        self.faces[R].stickers[2] = self.faces[D].stickers[8];
-        self.faces[R].stickers[5] = self.faces[D].stickers[7];
-        self.faces[R].stickers[8] = self.faces[D].stickers[6];
-        // L left column -> D bottom row
-        self.faces[D].stickers[6] = self.faces[L].stickers[0];
-        self.faces[D].stickers[7] = self.faces[L].stickers[3];
-        self.faces[D].stickers[8] = self.faces[L].stickers[6];
-        // U top row -> L left column (reversed, from temp)
-        self.faces[L].stickers[0] = temp[2];
-        self.faces[L].stickers[3] = temp[1];
-        self.faces[L].stickers[6] = temp[0];
+    #[test]
+    fn inverse_moves_go_back() {
+        for size in SIZES {
+            let start = scrambled(size);
+            for m in all_moves(size).into_iter().filter(|&m| start.fits(m)) {
+                for turns in 1..=3 {
+                    let m = Move::new(m.layers, turns);
+                    let mut state = start.clone();
+                    state.apply_move(m);
+                    state.apply_move(m.inverse());
+                    assert_eq!(stickers(&state), stickers(&start), "{m} on {size}x{size}");
+                }
+            }
+        }
     }
 
-    fn move_b_prime(&mut self) {
-        self.move_b();
-        self.move_b();
-        self.move_b();
+    #[test]
+    fn ignores_moves_that_do_not_fit() {
+        for (notation, size, fits) in [
+            ("3Rw", 2, false),
+            ("2R", 2, true),
+            ("M", 2, false),
+            ("3Rw", 4, true),
+            ("2R", 4, true),
+            ("M", 4, true),
+            ("5R", 4, false),
+        ] {
+            let m = parse_scramble(notation).unwrap()[0];
+            let mut state = scrambled(size);
+            let start = stickers(&state);
+            assert_eq!(state.fits(m), fits, "{notation} on {size}x{size}");
+            state.apply_move(m);
+            assert_eq!(
+                stickers(&state) == start,
+                !fits,
+                "{notation} on {size}x{size}"
+            );
+        }
     }
-}
 
-impl Default for CubeState {
-    fn default() -> Self {
-        Self::solved()
+    #[test]
+    fn turns_like_the_outer_layers() {
+        // On any cube, turning every layer one by one is a whole-cube rotation
+        for size in SIZES {
+            let mut layer_by_layer = scrambled(size);
+            for layer in 1..=size as u8 {
+                layer_by_layer.apply_move(Move::new(Layers::Inner(Side::R, layer), 1));
+            }
+            let mut rotated = scrambled(size);
+            rotated.apply_move(Move::new(Layers::Rotation(Rotation::X), 1));
+            assert_eq!(
+                stickers(&layer_by_layer),
+                stickers(&rotated),
+                "{size}x{size}"
+            );
+        }
     }
 }
@@ -1,42 +1,101 @@
-use crate::cube::Move;
-use crate::math::Mat4;
-
-/// Axis of rotation for a layer
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Axis {
-    X,
-    Y,
-    Z,
-}
+use crate::cube::{Axis, B, D, F, Layers, Move, Side, Slice, Span, U};
+use crate::math::{Mat4, Vec3};
+use crate::render::{Camera, FaceHit};
 
-/// Information about which layer is animating
+/// Information about which layers are animating
 #[derive(Clone, Copy, Debug)]
 pub struct AnimatingLayer {
     pub axis: Axis,
-    pub layer: i32, // -1, 0, or 1
+    /// Lowest and highest of the turning layers along the axis, from 0 on the L, D or B side
+    pub first: usize,
+    pub last: usize,
+}
+
+impl AnimatingLayer {
+    fn single(axis: Axis, layer: usize) -> Self {
+        Self {
+            axis,
+            first: layer,
+            last: layer,
+        }
+    }
+
+    fn contains(&self, [x, y, z]: [usize; 3]) -> bool {
+        let coordinate = match self.axis {
+            Axis::X => x,
+            Axis::Y => y,
+            Axis::Z => z,
+        };
+        (self.first..=self.last).contains(&coordinate)
+    }
 }
 
-/// State for dragging a face interactively
+impl From<Span> for AnimatingLayer {
+    fn from(span: Span) -> Self {
+        Self {
+            axis: span.axis,
+            first: span.first,
+            last: span.last,
+        }
+    }
+}
+
+/// Angle in radians the layers turn by while working out where the stickers go on screen
+const PROBE_ANGLE: f32 = 0.01;
+
+/// State for dragging a layer interactively
 pub struct FaceDrag {
-    pub face: usize,
     pub start_x: f32,
     pub start_y: f32,
     pub current_angle: f32,
     pub layer: Option<AnimatingLayer>,
-    pub direction: f32,
-    drag_axis_determined: bool,
+    /// The clicked cubelet, whose layers the drag can turn
+    cubelet: [usize; 3],
+    size: usize,
+    /// The two axes the drag can turn around, each with how far the clicked point moves on
+    /// screen when its layer turns by `PROBE_ANGLE` counterclockwise
+    candidates: [(Axis, (f32, f32)); 2],
+    /// Screen movement of the clicked point by `PROBE_ANGLE`, once the axis is chosen
+    tangent: (f32, f32),
 }
 
 impl FaceDrag {
-    pub fn new(face: usize, x: f32, y: f32) -> Self {
+    pub fn new(
+        hit: &FaceHit,
+        x: f32,
+        y: f32,
+        size: usize,
+        camera: &Camera,
+        canvas_size: (f32, f32),
+    ) -> Self {
+        let (width, height) = canvas_size;
+        let (point_x, point_y) = camera.project(hit.point, width, height);
+        let screen_tangent = |axis: Axis| {
+            let direction = match axis {
+                Axis::X => Vec3::new(1.0, 0.0, 0.0),
+                Axis::Y => Vec3::new(0.0, 1.0, 0.0),
+                Axis::Z => Vec3::new(0.0, 0.0, 1.0),
+            };
+            let moved = hit.point + direction.cross(&hit.point) * PROBE_ANGLE;
+            let (moved_x, moved_y) = camera.project(moved, width, height);
+            (moved_x - point_x, moved_y - point_y)
+        };
+        // The layers of the clicked face turn around the other two axes
+        let axes = match hit.face {
+            U | D => [Axis::X, Axis::Z],
+            F | B => [Axis::X, Axis::Y],
+            _ => [Axis::Y, Axis::Z],
+        };
+
         Self {
-            face,
             start_x: x,
             start_y: y,
             current_angle: 0.0,
             layer: None,
-            direction: 1.0,
-            drag_axis_determined: false,
+            cubelet: hit.cubelet,
+            size,
+            candidates: axes.map(|axis| (axis, screen_tangent(axis))),
+            tangent: (0.0, 0.0),
         }
     }
 
@@ -48,75 +107,44 @@ impl FaceDrag {
 
         // Determine drag direction after threshold
         const THRESHOLD: f32 = 10.0;
-        if !self.drag_axis_determined && (dx.abs() > THRESHOLD || dy.abs() > THRESHOLD) {
+        if self.layer.is_none() && (dx.abs() > THRESHOLD || dy.abs() > THRESHOLD) {
             self.determine_layer(dx, dy);
-            self.drag_axis_determined = true;
-            // Reset start position to current for smoother feel
-            self.start_x = x;
-            self.start_y = y;
         }
 
-        if self.drag_axis_determined {
-            // Calculate rotation angle from drag distance
-            let drag_dist = match self.layer.as_ref().map(|l| l.axis) {
-                Some(Axis::Y) => dx, // Horizontal drag for Y-axis rotation
-                _ => -dy,            // Vertical drag for X/Z-axis rotation
-            };
-            self.current_angle = drag_dist * 0.01 * self.direction;
+        if self.layer.is_some() {
+            // Turn the layer so that the clicked point follows the mouse along its path
+            let (tx, ty) = self.tangent;
+            let length_squared = tx * tx + ty * ty;
+            if length_squared > f32::EPSILON {
+                self.current_angle = (dx * tx + dy * ty) / length_squared * PROBE_ANGLE;
+            }
         }
 
-        self.drag_axis_determined
+        self.layer.is_some()
     }
 
+    /// Picks the axis whose turn moves the clicked point the most along the drag, and the layer
+    /// of the clicked cubelet along it
     fn determine_layer(&mut self, dx: f32, dy: f32) {
-        let horizontal = dx.abs() > dy.abs();
-
-        // Determine which layer rotates based on face and drag direction
-        // Face indices: 0=U, 1=D, 2=F, 3=B, 4=L, 5=R
-        let (axis, layer, dir) = match (self.face, horizontal) {
-            // Front face
-            (2, true) => (Axis::Y, 0, if dx > 0.0 { -1.0 } else { 1.0 }), // horizontal drag on F -> rotate middle Y
-            (2, false) => (Axis::X, 0, if dy > 0.0 { 1.0 } else { -1.0 }), // vertical drag on F -> rotate middle X
-
-            // Back face
-            (3, true) => (Axis::Y, 0, if dx > 0.0 { 1.0 } else { -1.0 }),
-            (3, false) => (Axis::X, 0, if dy > 0.0 { -1.0 } else { 1.0 }),
-
-            // Up face
-            (0, true) => (Axis::Z, 0, if dx > 0.0 { -1.0 } else { 1.0 }),
-            (0, false) => (Axis::X, 0, if dy > 0.0 { 1.0 } else { -1.0 }),
-
-            // Down face
-            (1, true) => (Axis::Z, 0, if dx > 0.0 { 1.0 } else { -1.0 }),
-            (1, false) => (Axis::X, 0, if dy > 0.0 { -1.0 } else { 1.0 }),
-
-            // Left face
-            (4, true) => (Axis::Y, 0, if dx > 0.0 { -1.0 } else { 1.0 }),
-            (4, false) => (Axis::Z, 0, if dy > 0.0 { 1.0 } else { -1.0 }),
-
-            // Right face
-            (5, true) => (Axis::Y, 0, if dx > 0.0 { -1.0 } else { 1.0 }),
-            (5, false) => (Axis::Z, 0, if dy > 0.0 { -1.0 } else { 1.0 }),
-
-            _ => (Axis::Y, 0, 1.0),
+        let along_drag = |(tx, ty): (f32, f32)| {
+            let length = (tx * tx + ty * ty).sqrt().max(f32::EPSILON);
+            (dx * tx + dy * ty).abs() / length
         };
-
-        // For now, always rotate the face layer itself
-        let face_layer = match self.face {
-            0 => (Axis::Y, 1),  // U
-            1 => (Axis::Y, -1), // D
-            2 => (Axis::Z, 1),  // F
-            3 => (Axis::Z, -1), // B
-            4 => (Axis::X, -1), // L
-            5 => (Axis::X, 1),  // R
-            _ => (axis, layer),
+        let [first, second] = self.candidates;
+        let (axis, tangent) = if along_drag(first.1) >= along_drag(second.1) {
+            first
+        } else {
+            second
+        };
+        let [x, y, z] = self.cubelet;
+        let layer = match axis {
+            Axis::X => x,
+            Axis::Y => y,
+            Axis::Z => z,
         };
 
-        self.layer = Some(AnimatingLayer {
-            axis: face_layer.0,
-            layer: face_layer.1,
-        });
-        self.direction = dir;
+        self.layer = Some(AnimatingLayer::single(axis, layer));
+        self.tangent = tangent;
     }
 
     /// Get the rotation matrix for rendering
@@ -133,15 +161,8 @@ impl FaceDrag {
     }
 
     /// Check if a cubelet is in the dragging layer
-    pub fn is_in_layer(&self, x: i32, y: i32, z: i32) -> bool {
-        let Some(ref layer) = self.layer else {
-            return false;
-        };
-        match layer.axis {
-            Axis::X => x == layer.layer,
-            Axis::Y => y == layer.layer,
-            Axis::Z => z == layer.layer,
-        }
+    pub fn is_in_layer(&self, cubelet: [usize; 3]) -> bool {
+        self.layer.is_some_and(|layer| layer.contains(cubelet))
     }
 
     /// Finish drag and return the move to apply (if any)
@@ -152,45 +173,46 @@ impl FaceDrag {
         let half_pi = std::f32::consts::FRAC_PI_2;
         let snapped_turns = (self.current_angle / half_pi).round() as i32;
 
-        if snapped_turns == 0 {
-            return None;
-        }
-
-        // Determine move based on layer and rotation count
         // Positive angle = counterclockwise rotation (looking from positive axis)
         // This matches the LayerAnimation convention where:
         //   R has direction -1.0 (negative angle)
         //   R' has direction 1.0 (positive angle)
-        let counterclockwise = snapped_turns > 0;
-        let double = snapped_turns.abs() >= 2;
-
-        match (layer.axis, layer.layer, counterclockwise, double) {
-            // X axis: R (layer=1), L (layer=-1)
-            (Axis::X, 1, false, false) => Some(Move::R), // clockwise R
-            (Axis::X, 1, true, false) => Some(Move::RPrime), // counterclockwise R'
-            (Axis::X, 1, _, true) => Some(Move::R2),
-            (Axis::X, -1, false, false) => Some(Move::LPrime), // clockwise L' (from +X view)
-            (Axis::X, -1, true, false) => Some(Move::L),       // counterclockwise L
-            (Axis::X, -1, _, true) => Some(Move::L2),
-
-            // Y axis: U (layer=1), D (layer=-1)
-            (Axis::Y, 1, false, false) => Some(Move::U), // clockwise U
-            (Axis::Y, 1, true, false) => Some(Move::UPrime), // counterclockwise U'
-            (Axis::Y, 1, _, true) => Some(Move::U2),
-            (Axis::Y, -1, false, false) => Some(Move::DPrime), // clockwise D'
-            (Axis::Y, -1, true, false) => Some(Move::D),       // counterclockwise D
-            (Axis::Y, -1, _, true) => Some(Move::D2),
-
-            // Z axis: F (layer=1), B (layer=-1)
-            (Axis::Z, 1, false, false) => Some(Move::F), // clockwise F
-            (Axis::Z, 1, true, false) => Some(Move::FPrime), // counterclockwise F'
-            (Axis::Z, 1, _, true) => Some(Move::F2),
-            (Axis::Z, -1, false, false) => Some(Move::BPrime), // clockwise B'
-            (Axis::Z, -1, true, false) => Some(Move::B),       // counterclockwise B
-            (Axis::Z, -1, _, true) => Some(Move::B2),
-
-            _ => None,
+        let counterclockwise_turns = snapped_turns.rem_euclid(4) as u8;
+        if counterclockwise_turns == 0 {
+            return None;
         }
+
+        // Outer layers are named after their side, the middle layer of a 3x3 is a slice, and
+        // other inner layers are counted from the nearer side
+        let last = self.size - 1;
+        let (positive_side, negative_side) = match layer.axis {
+            Axis::X => (Side::R, Side::L),
+            Axis::Y => (Side::U, Side::D),
+            Axis::Z => (Side::F, Side::B),
+        };
+        let (layers, negative) = match layer.first {
+            first if first == last => (Layers::Outer(positive_side), false),
+            0 => (Layers::Outer(negative_side), true),
+            1 if self.size == 3 => match layer.axis {
+                // Slices turn like L, D and F
+                Axis::X => (Layers::Slice(Slice::M), true),
+                Axis::Y => (Layers::Slice(Slice::E), true),
+                Axis::Z => (Layers::Slice(Slice::S), false),
+            },
+            first if last - first < first => (
+                Layers::Inner(positive_side, (last - first + 1) as u8),
+                false,
+            ),
+            first => (Layers::Inner(negative_side, (first + 1) as u8), true),
+        };
+
+        // Clockwise looking from the positive axis is counterclockwise for the negative side
+        let turns = if negative {
+            counterclockwise_turns
+        } else {
+            4 - counterclockwise_turns
+        };
+        Some(Move::new(layers, turns))
     }
 }
 
@@ -205,112 +227,33 @@ pub struct LayerAnimation {
 }
 
 impl LayerAnimation {
-    pub fn new(cube_move: Move, start_time: f64) -> Self {
-        let target_angle = match cube_move {
-            Move::R2 | Move::L2 | Move::U2 | Move::D2 | Move::F2 | Move::B2 => std::f32::consts::PI,
-            _ => std::f32::consts::FRAC_PI_2,
+    /// Animation of a move on a cube of `size` layers, `None` when the cube does not have the
+    /// layers it turns
+    pub fn new(cube_move: Move, start_time: f64, size: usize) -> Option<Self> {
+        let target_angle = if cube_move.turns == 2 {
+            std::f32::consts::PI
+        } else {
+            std::f32::consts::FRAC_PI_2
         };
 
-        let (layer, direction) = Self::get_layer_info(cube_move);
+        let span = cube_move.layers.span(size)?;
+        // Clockwise looking from the positive axis is a negative angle
+        let clockwise_direction = if span.positive { -1.0 } else { 1.0 };
+        let direction = if cube_move.turns == 3 {
+            -clockwise_direction
+        } else {
+            clockwise_direction
+        };
 
-        Self {
+        Some(Self {
             cube_move,
             target_angle,
             current_angle: 0.0,
             start_time,
             duration: 200.0, // 200ms
-            layer,
+            layer: span.into(),
             direction,
-        }
-    }
-
-    fn get_layer_info(cube_move: Move) -> (AnimatingLayer, f32) {
-        match cube_move {
-            Move::R | Move::R2 => (
-                AnimatingLayer {
-                    axis: Axis::X,
-                    layer: 1,
-                },
-                -1.0,
-            ),
-            Move::RPrime => (
-                AnimatingLayer {
-                    axis: Axis::X,
-                    layer: 1,
-                },
-                1.0,
-            ),
-            Move::L | Move::L2 => (
-                AnimatingLayer {
-                    axis: Axis::X,
-                    layer: -1,
-                },
-                1.0,
-            ),
-            Move::LPrime => (
-                AnimatingLayer {
-                    axis: Axis::X,
-                    layer: -1,
-                },
-                -1.0,
-            ),
-            Move::U | Move::U2 => (
-                AnimatingLayer {
-                    axis: Axis::Y,
-                    layer: 1,
-                },
-                -1.0,
-            ),
-            Move::UPrime => (
-                AnimatingLayer {
-                    axis: Axis::Y,
-                    layer: 1,
-                },
-                1.0,
-            ),
-            Move::D | Move::D2 => (
-                AnimatingLayer {
-                    axis: Axis::Y,
-                    layer: -1,
-                },
-                1.0,
-            ),
-            Move::DPrime => (
-                AnimatingLayer {
-                    axis: Axis::Y,
-                    layer: -1,
-                },
-                -1.0,
-            ),
-            Move::F | Move::F2 => (
-                AnimatingLayer {
-                    axis: Axis::Z,
-                    layer: 1,
-                },
-                -1.0,
-            ),
-            Move::FPrime => (
-                AnimatingLayer {
-                    axis: Axis::Z,
-                    layer: 1,
-                },
-                1.0,
-            ),
-            Move::B | Move::B2 => (
-                AnimatingLayer {
-                    axis: Axis::Z,
-                    layer: -1,
-                },
-                1.0,
-            ),
-            Move::BPrime => (
-                AnimatingLayer {
-                    axis: Axis::Z,
-                    layer: -1,
-                },
-                -1.0,
-            ),
-        }
+        })
     }
 
     /// Update animation and return true if complete
@@ -332,13 +275,9 @@ impl LayerAnimation {
         }
     }
 
-    /// Check if a cubelet at position (x, y, z) is in the animating layer
-    pub fn is_in_layer(&self, x: i32, y: i32, z: i32) -> bool {
-        match self.layer.axis {
-            Axis::X => x == self.layer.layer,
-            Axis::Y => y == self.layer.layer,
-            Axis::Z => z == self.layer.layer,
-        }
+    /// Check if a cubelet, by layer along x, y and z, is in the animating layers
+    pub fn is_in_layer(&self, cubelet: [usize; 3]) -> bool {
+        self.layer.contains(cubelet)
     }
 }
 
@@ -384,3 +323,72 @@ fn rotation_z(angle: f32) -> Mat4 {
     ];
     Mat4 { data }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, TAU};
+
+    /// The move of a drag turning a layer by quarter turns, counterclockwise looking from the
+    /// positive end of the axis
+    fn drag(size: usize, axis: Axis, layer: usize, quarter_turns: i32) -> Option<Move> {
+        FaceDrag {
+            start_x: 0.0,
+            start_y: 0.0,
+            current_angle: quarter_turns as f32 * FRAC_PI_2,
+            layer: Some(AnimatingLayer::single(axis, layer)),
+            cubelet: [layer; 3],
+            size,
+            candidates: [(Axis::X, (0.0, 0.0)), (Axis::Y, (0.0, 0.0))],
+            tangent: (0.0, 0.0),
+        }
+        .finish()
+    }
+
+    fn notation(m: Option<Move>) -> String {
+        m.map(|m| m.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn names_inner_layers_of_big_cubes() {
+        assert_eq!(notation(drag(4, Axis::X, 2, -1)), "2R");
+        assert_eq!(notation(drag(4, Axis::X, 1, 1)), "2L");
+        assert_eq!(notation(drag(4, Axis::X, 1, -1)), "2L'");
+        assert_eq!(notation(drag(4, Axis::Y, 2, 2)), "2U2");
+        assert_eq!(notation(drag(4, Axis::Z, 1, -1)), "2B'");
+        assert_eq!(notation(drag(4, Axis::Z, 3, -1)), "F");
+        assert_eq!(notation(drag(3, Axis::X, 1, 1)), "M");
+        assert_eq!(notation(drag(5, Axis::X, 2, 1)), "3L");
+        assert_eq!(notation(drag(4, Axis::X, 2, 0)), "");
+    }
+
+    #[test]
+    fn turns_the_dragged_layer_where_it_was_dropped() {
+        for size in 2..=7 {
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                for layer in 0..size {
+                    for quarter_turns in [-1, 1, 2] {
+                        let m = drag(size, axis, layer, quarter_turns).unwrap();
+                        let animation = LayerAnimation::new(m, 0.0, size).unwrap();
+                        assert_eq!(
+                            (
+                                animation.layer.axis,
+                                animation.layer.first,
+                                animation.layer.last
+                            ),
+                            (axis, layer, layer),
+                            "{m} on {size}x{size}"
+                        );
+                        let dropped = quarter_turns as f32 * FRAC_PI_2;
+                        let difference = animation.target_angle * animation.direction - dropped;
+                        let full_turns = difference / TAU;
+                        assert!(
+                            (full_turns - full_turns.round()).abs() < 1e-4,
+                            "{m} on {size}x{size} turns by {difference} more than dropped"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
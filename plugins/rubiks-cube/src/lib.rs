@@ -6,7 +6,7 @@ mod render;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use cube::{CubeState, Move, parse_scramble};
+use cube::{AlgCard, CubeState, CubieCube, Drill, Move, parse_scramble, solve};
 use input::{FaceDrag, LayerAnimation, MouseHandler};
 use math::Mat4;
 use render::{Camera, RayPicker, Renderer};
@@ -15,6 +15,12 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 use web_sys::{HtmlCanvasElement, MouseEvent};
 
+/// Sizes of cubes a card can ask for, 2x2 to 7x7
+const SIZES: std::ops::RangeInclusive<u32> = 2..=7;
+
+/// Connection to the host page, see the `flashqc-plugin-protocol` crate
+pub use flashqc_plugin_protocol::PluginHost;
+
 #[wasm_bindgen]
 pub struct RubiksCube {
     canvas: HtmlCanvasElement,
@@ -26,6 +32,8 @@ pub struct RubiksCube {
     face_drag: Rc<RefCell<Option<FaceDrag>>>,
     move_count: Rc<RefCell<u32>>,
     needs_mesh_update: Rc<RefCell<bool>>,
+    /// The algorithm drill of the card, `None` for a scramble to solve
+    drill: Option<Drill>,
     closures: Vec<Closure<dyn FnMut(MouseEvent)>>,
 }
 
@@ -40,7 +48,7 @@ impl RubiksCube {
             .ok_or("Canvas not found")?
             .dyn_into::<HtmlCanvasElement>()?;
 
-        let state = CubeState::solved();
+        let state = CubeState::default();
         let aspect = canvas.width() as f32 / canvas.height() as f32;
         let camera = Camera::new(aspect);
 
@@ -56,6 +64,7 @@ impl RubiksCube {
             face_drag: Rc::new(RefCell::new(None)),
             move_count: Rc::new(RefCell::new(0)),
             needs_mesh_update: Rc::new(RefCell::new(false)),
+            drill: None,
             closures: Vec::new(),
         })
     }
@@ -70,6 +79,7 @@ impl RubiksCube {
             let camera = self.camera.clone();
             let animation = self.animation.clone();
             let face_drag = self.face_drag.clone();
+            let state = self.state.clone();
             let canvas_clone = canvas_ref.clone();
 
             let closure = Closure::wrap(Box::new(move |event: MouseEvent| {
@@ -85,9 +95,12 @@ impl RubiksCube {
 
                 // Try to pick a face first
                 let cam = camera.borrow();
-                if let Some(hit) = RayPicker::pick(&cam, x, y, canvas_width, canvas_height) {
+                let size = state.borrow().size;
+                if let Some(hit) = RayPicker::pick(&cam, x, y, canvas_width, canvas_height, size) {
                     // Start face drag
-                    *face_drag.borrow_mut() = Some(FaceDrag::new(hit.face, x, y));
+                    let canvas_size = (canvas_width, canvas_height);
+                    let drag = FaceDrag::new(&hit, x, y, size, &cam, canvas_size);
+                    *face_drag.borrow_mut() = Some(drag);
                 } else {
                     // Start drag for camera rotation
                     mouse.borrow_mut().start_drag(x, y);
@@ -176,7 +189,7 @@ impl RubiksCube {
 
         // Check if mesh needs update (after drag finished)
         if *self.needs_mesh_update.borrow() {
-            self.renderer.update_mesh(&self.state.borrow());
+            self.renderer.update_mesh(&self.shown_state());
             *self.needs_mesh_update.borrow_mut() = false;
         }
 
@@ -192,18 +205,21 @@ impl RubiksCube {
                     *anim = None;
                 } else {
                     // Animation in progress - clone for rendering
-                    current_animation = Some(LayerAnimation::new(
-                        animation.cube_move,
-                        animation.start_time,
-                    ));
-                    current_animation.as_mut().unwrap().current_angle = animation.current_angle;
+                    let size = self.state.borrow().size;
+                    current_animation =
+                        LayerAnimation::new(animation.cube_move, animation.start_time, size).map(
+                            |mut clone| {
+                                clone.current_angle = animation.current_angle;
+                                clone
+                            },
+                        );
                 }
             }
         }
 
         if let Some(m) = should_apply_move {
             self.state.borrow_mut().apply_move(m);
-            self.renderer.update_mesh(&self.state.borrow());
+            self.renderer.update_mesh(&self.shown_state());
         }
 
         // Render
@@ -212,32 +228,130 @@ impl RubiksCube {
         // Check if we're doing a face drag
         if let Some(ref drag) = *self.face_drag.borrow() {
             self.renderer
-                .render_with_drag(&camera, &self.state.borrow(), drag);
+                .render_with_drag(&camera, &self.shown_state(), drag);
         } else if let Some(ref anim) = current_animation {
             self.renderer
-                .render_animated(&camera, &self.state.borrow(), anim);
+                .render_animated(&camera, &self.shown_state(), anim);
         } else {
             let model = Mat4::identity();
             self.renderer.render(&camera, &model);
         }
     }
 
-    pub fn scramble(&mut self, notation: &str) {
-        let moves = parse_scramble(notation);
+    /// Changes the cube to a solved one of `size` layers, 2 to 7, leaving any drill
+    pub fn set_size(&mut self, size: u32) -> Result<(), JsValue> {
+        if !SIZES.contains(&size) {
+            return Err(JsValue::from_str(&format!(
+                "Invalid cube size {size}, expected 2 to 7"
+            )));
+        }
+        *self.state.borrow_mut() = CubeState::solved(size as usize);
+        *self.move_count.borrow_mut() = 0;
+        *self.face_drag.borrow_mut() = None;
+        *self.animation.borrow_mut() = None;
+        self.drill = None;
+        self.renderer.update_mesh(&self.shown_state());
+        Ok(())
+    }
+
+    /// Applies moves in WCA notation, or fails without touching the cube if they cannot be read
+    /// or turn layers the cube does not have
+    pub fn scramble(&mut self, notation: &str) -> Result<(), JsValue> {
+        let moves = parse_scramble(notation)
+            .map_err(|e| JsValue::from_str(&format!("Invalid scramble: {e}")))?;
+        let size = self.state.borrow().size;
+        if let Some(m) = moves.iter().find(|m| !self.state.borrow().fits(**m)) {
+            return Err(JsValue::from_str(&format!(
+                "Invalid scramble: {m} needs a bigger cube than {size}x{size}"
+            )));
+        }
         for m in moves {
             self.state.borrow_mut().apply_move(m);
         }
-        self.renderer.update_mesh(&self.state.borrow());
+        self.renderer.update_mesh(&self.shown_state());
+        Ok(())
+    }
+
+    /// Starts an algorithm drill from the JSON of an `{"mode": "alg"}` card: the cube shows the
+    /// case, with the stickers that do not matter for it in gray
+    pub fn start_drill(&mut self, card: &str) -> Result<(), JsValue> {
+        let card: AlgCard = serde_json::from_str(card)
+            .map_err(|e| JsValue::from_str(&format!("Invalid card: {e}")))?;
+        let drill = Drill::new(&card)
+            .map_err(|e| JsValue::from_str(&format!("Invalid card {}: {e}", card.case)))?;
+        *self.state.borrow_mut() = drill.start.clone();
+        *self.move_count.borrow_mut() = 0;
+        self.drill = Some(drill);
+        self.renderer.update_mesh(&self.shown_state());
+        Ok(())
+    }
+
+    /// Moves allowed to reach the target of the drill, `None` outside of drills
+    pub fn move_budget(&self) -> Option<u32> {
+        self.drill.as_ref().map(|drill| drill.budget)
+    }
+
+    /// Whether the drill's move budget is used up without reaching its target
+    pub fn is_out_of_moves(&self) -> bool {
+        self.drill
+            .as_ref()
+            .is_some_and(|drill| *self.move_count.borrow() >= drill.budget && !self.is_solved())
     }
 
+    /// Whether the pieces are where the scramble takes them, however the cube is held
+    pub fn matches_scramble(&self, notation: &str) -> Result<bool, JsValue> {
+        let moves = parse_scramble(notation)
+            .map_err(|e| JsValue::from_str(&format!("Invalid scramble: {e}")))?;
+        let state = self.state.borrow();
+        if state.size != 3 {
+            // Without a cubie model, compare the stickers
+            let mut expected = CubeState::solved(state.size);
+            for m in moves {
+                expected.apply_move(m);
+            }
+            return Ok(state.matches(&expected));
+        }
+        let mut expected = CubieCube::SOLVED;
+        expected.apply_moves(&moves);
+        let current = CubieCube::try_from(&*state)
+            .map_err(|e| JsValue::from_str(&format!("Impossible cube: {e}")))?;
+        Ok(current == expected)
+    }
+
+    /// Moves solving the cube from where it is, in WCA notation. Near the shortest possible, and
+    /// empty when the cube is solved. The first call takes a moment to compute the solver's
+    /// tables. Only for 3x3 cubes.
+    pub fn solve(&self) -> Result<String, JsValue> {
+        let moves = self.solution()?;
+        Ok(moves
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// The next move toward solved, `None` when the cube is solved
+    pub fn hint(&self) -> Result<Option<String>, JsValue> {
+        Ok(self.solution()?.first().map(|m| m.to_string()))
+    }
+
+    /// Back to a solved cube, or to the case of a drill
     pub fn reset(&mut self) {
-        *self.state.borrow_mut() = CubeState::solved();
+        *self.state.borrow_mut() = match &self.drill {
+            Some(drill) => drill.start.clone(),
+            None => CubeState::solved(self.state.borrow().size),
+        };
         *self.move_count.borrow_mut() = 0;
-        self.renderer.update_mesh(&self.state.borrow());
+        self.renderer.update_mesh(&self.shown_state());
     }
 
+    /// Whether the cube is solved, or shows the target of a drill
     pub fn is_solved(&self) -> bool {
-        self.state.borrow().is_solved()
+        let state = self.state.borrow();
+        match &self.drill {
+            Some(drill) => drill.target.reached(&state),
+            None => state.is_solved(),
+        }
     }
 
     pub fn get_move_count(&self) -> u32 {
@@ -253,3 +367,26 @@ impl RubiksCube {
         self.renderer.resize(width, height);
     }
 }
+
+impl RubiksCube {
+    /// The stickers to draw, with those that do not matter for a drill in gray
+    fn shown_state(&self) -> CubeState {
+        let state = self.state.borrow();
+        match &self.drill {
+            Some(drill) => drill.target.masked(&state),
+            None => state.clone(),
+        }
+    }
+
+    fn solution(&self) -> Result<Vec<Move>, JsValue> {
+        let size = self.state.borrow().size;
+        if size != 3 {
+            return Err(JsValue::from_str(&format!(
+                "Only a 3x3 cube can be solved, not a {size}x{size}"
+            )));
+        }
+        let cube = CubieCube::try_from(&*self.state.borrow())
+            .map_err(|e| JsValue::from_str(&format!("Impossible cube: {e}")))?;
+        solve(&cube).map_err(|e| JsValue::from_str(&format!("Impossible cube: {e}")))
+    }
+}
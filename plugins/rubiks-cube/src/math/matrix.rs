@@ -1,3 +1,5 @@
+use super::Vec3;
+
 /// 4x4 matrix stored in column-major order (OpenGL convention)
 #[derive(Clone, Copy, Debug)]
 pub struct Mat4 {
@@ -57,6 +59,16 @@ impl Mat4 {
         Mat4 { data: result }
     }
 
+    /// Transforms a point, dividing by w for projections
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let d = &self.data;
+        let x = d[0] * p.x + d[4] * p.y + d[8] * p.z + d[12];
+        let y = d[1] * p.x + d[5] * p.y + d[9] * p.z + d[13];
+        let z = d[2] * p.x + d[6] * p.y + d[10] * p.z + d[14];
+        let w = d[3] * p.x + d[7] * p.y + d[11] * p.z + d[15];
+        Vec3::new(x / w, y / w, z / w)
+    }
+
     pub fn as_slice(&self) -> &[f32; 16] {
         &self.data
     }
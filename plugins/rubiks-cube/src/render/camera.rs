@@ -31,6 +31,18 @@ impl Camera {
         Mat4::perspective(self.fov, self.aspect, self.near, self.far)
     }
 
+    /// Where a point of the world shows on the canvas, in pixels from its top left corner
+    pub fn project(&self, point: Vec3, canvas_width: f32, canvas_height: f32) -> (f32, f32) {
+        let ndc = self
+            .projection_matrix()
+            .multiply(&self.view_matrix())
+            .transform_point(point);
+        (
+            (ndc.x + 1.0) / 2.0 * canvas_width,
+            (1.0 - ndc.y) / 2.0 * canvas_height,
+        )
+    }
+
     pub fn rotate(&mut self, delta: Quaternion) {
         self.rotation = delta.multiply(&self.rotation).normalize();
     }
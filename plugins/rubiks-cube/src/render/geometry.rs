@@ -2,6 +2,9 @@ use crate::cube::{B, CubeState, D, F, L, R, U};
 use crate::input::{FaceDrag, LayerAnimation};
 use web_sys::{WebGlBuffer, WebGlRenderingContext};
 
+/// Distance from the center of the cube to its faces, whatever its size
+pub const HALF_SIZE: f32 = 1.5;
+/// Sizes for a 3x3, cubelets of bigger cubes shrink so that all cubes take the same space
 const CUBELET_SIZE: f32 = 0.95;
 const GAP: f32 = 0.025;
 const BLACK: [f32; 3] = [0.05, 0.05, 0.05];
@@ -54,8 +57,9 @@ impl CubeMesh {
         })
     }
 
+    /// Uploads the stickers, and the cubelets too since the size of the cube may have changed
     pub fn update(&mut self, gl: &WebGlRenderingContext, state: &CubeState) {
-        let (vertices, _) = generate_cube_geometry(state);
+        let (vertices, indices) = generate_cube_geometry(state);
 
         gl.bind_buffer(
             WebGlRenderingContext::ARRAY_BUFFER,
@@ -69,6 +73,20 @@ impl CubeMesh {
                 WebGlRenderingContext::STATIC_DRAW,
             );
         }
+
+        gl.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&self.index_buffer),
+        );
+        unsafe {
+            let idx_array = js_sys::Uint16Array::view(&indices);
+            gl.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+                &idx_array,
+                WebGlRenderingContext::STATIC_DRAW,
+            );
+        }
+        self.index_count = indices.len() as i32;
     }
 }
 
@@ -158,37 +176,25 @@ fn generate_split_geometry(
     let mut layer_vertices: Vec<f32> = Vec::new();
     let mut layer_indices: Vec<u16> = Vec::new();
 
-    for x in -1..=1i32 {
-        for y in -1..=1i32 {
-            for z in -1..=1i32 {
-                if x == 0 && y == 0 && z == 0 {
-                    continue;
-                }
-
-                if animation.is_in_layer(x, y, z) {
-                    let base_index = (layer_vertices.len() / 9) as u16;
-                    generate_cubelet(
-                        &mut layer_vertices,
-                        &mut layer_indices,
-                        base_index,
-                        x,
-                        y,
-                        z,
-                        state,
-                    );
-                } else {
-                    let base_index = (static_vertices.len() / 9) as u16;
-                    generate_cubelet(
-                        &mut static_vertices,
-                        &mut static_indices,
-                        base_index,
-                        x,
-                        y,
-                        z,
-                        state,
-                    );
-                }
-            }
+    for cubelet in outer_cubelets(state.size) {
+        if animation.is_in_layer(cubelet) {
+            let base_index = (layer_vertices.len() / 9) as u16;
+            generate_cubelet(
+                &mut layer_vertices,
+                &mut layer_indices,
+                base_index,
+                cubelet,
+                state,
+            );
+        } else {
+            let base_index = (static_vertices.len() / 9) as u16;
+            generate_cubelet(
+                &mut static_vertices,
+                &mut static_indices,
+                base_index,
+                cubelet,
+                state,
+            );
         }
     }
 
@@ -209,37 +215,25 @@ fn generate_drag_split_geometry(
     let mut layer_vertices: Vec<f32> = Vec::new();
     let mut layer_indices: Vec<u16> = Vec::new();
 
-    for x in -1..=1i32 {
-        for y in -1..=1i32 {
-            for z in -1..=1i32 {
-                if x == 0 && y == 0 && z == 0 {
-                    continue;
-                }
-
-                if drag.is_in_layer(x, y, z) {
-                    let base_index = (layer_vertices.len() / 9) as u16;
-                    generate_cubelet(
-                        &mut layer_vertices,
-                        &mut layer_indices,
-                        base_index,
-                        x,
-                        y,
-                        z,
-                        state,
-                    );
-                } else {
-                    let base_index = (static_vertices.len() / 9) as u16;
-                    generate_cubelet(
-                        &mut static_vertices,
-                        &mut static_indices,
-                        base_index,
-                        x,
-                        y,
-                        z,
-                        state,
-                    );
-                }
-            }
+    for cubelet in outer_cubelets(state.size) {
+        if drag.is_in_layer(cubelet) {
+            let base_index = (layer_vertices.len() / 9) as u16;
+            generate_cubelet(
+                &mut layer_vertices,
+                &mut layer_indices,
+                base_index,
+                cubelet,
+                state,
+            );
+        } else {
+            let base_index = (static_vertices.len() / 9) as u16;
+            generate_cubelet(
+                &mut static_vertices,
+                &mut static_indices,
+                base_index,
+                cubelet,
+                state,
+            );
         }
     }
 
@@ -255,44 +249,45 @@ fn generate_cube_geometry(state: &CubeState) -> (Vec<f32>, Vec<u16>) {
     let mut vertices: Vec<f32> = Vec::new();
     let mut indices: Vec<u16> = Vec::new();
 
-    // Generate 27 cubelets (3x3x3 grid)
-    for x in -1..=1i32 {
-        for y in -1..=1i32 {
-            for z in -1..=1i32 {
-                // Skip center cubelet (not visible)
-                if x == 0 && y == 0 && z == 0 {
-                    continue;
-                }
-
-                let base_index = (vertices.len() / 9) as u16;
-                generate_cubelet(&mut vertices, &mut indices, base_index, x, y, z, state);
-            }
-        }
+    for cubelet in outer_cubelets(state.size) {
+        let base_index = (vertices.len() / 9) as u16;
+        generate_cubelet(&mut vertices, &mut indices, base_index, cubelet, state);
     }
 
     (vertices, indices)
 }
 
+/// The cubelets that show, by layer along x, y and z: those with a side on the outside
+fn outer_cubelets(size: usize) -> impl Iterator<Item = [usize; 3]> {
+    let last = size - 1;
+    (0..size)
+        .flat_map(move |x| (0..size).flat_map(move |y| (0..size).map(move |z| [x, y, z])))
+        .filter(move |cubelet| cubelet.iter().any(|&c| c == 0 || c == last))
+}
+
 fn generate_cubelet(
     vertices: &mut Vec<f32>,
     indices: &mut Vec<u16>,
     base_index: u16,
-    x: i32,
-    y: i32,
-    z: i32,
+    cubelet: [usize; 3],
     state: &CubeState,
 ) {
-    let cx = x as f32;
-    let cy = y as f32;
-    let cz = z as f32;
-    let half = CUBELET_SIZE / 2.0;
+    let [x, y, z] = cubelet;
+    let last = state.size - 1;
+    let unit = 2.0 * HALF_SIZE / state.size as f32;
+    let center = |layer: usize| (layer as f32 - last as f32 / 2.0) * unit;
+    let cx = center(x);
+    let cy = center(y);
+    let cz = center(z);
+    let half = CUBELET_SIZE * unit / 2.0;
+    let gap = GAP * unit;
 
     // Each cubelet has 6 faces, but we only render visible external faces
     let mut current_index = base_index;
 
-    // +Y face (top) - visible if y == 1
-    if y == 1 {
-        let color = get_sticker_color(state, U, x, z);
+    // +Y face (top) - visible if y is the top layer
+    if y == last {
+        let color = state.sticker(U, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -308,9 +303,9 @@ fn generate_cubelet(
         );
     }
 
-    // -Y face (bottom) - visible if y == -1
-    if y == -1 {
-        let color = get_sticker_color(state, D, x, z);
+    // -Y face (bottom) - visible if y is the bottom layer
+    if y == 0 {
+        let color = state.sticker(D, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -326,9 +321,9 @@ fn generate_cubelet(
         );
     }
 
-    // +Z face (front) - visible if z == 1
-    if z == 1 {
-        let color = get_sticker_color(state, F, x, y);
+    // +Z face (front) - visible if z is the front layer
+    if z == last {
+        let color = state.sticker(F, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -344,9 +339,9 @@ fn generate_cubelet(
         );
     }
 
-    // -Z face (back) - visible if z == -1
-    if z == -1 {
-        let color = get_sticker_color(state, B, x, y);
+    // -Z face (back) - visible if z is the back layer
+    if z == 0 {
+        let color = state.sticker(B, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -362,9 +357,9 @@ fn generate_cubelet(
         );
     }
 
-    // -X face (left) - visible if x == -1
-    if x == -1 {
-        let color = get_sticker_color(state, L, y, z);
+    // -X face (left) - visible if x is the left layer
+    if x == 0 {
+        let color = state.sticker(L, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -380,9 +375,9 @@ fn generate_cubelet(
         );
     }
 
-    // +X face (right) - visible if x == 1
-    if x == 1 {
-        let color = get_sticker_color(state, R, y, z);
+    // +X face (right) - visible if x is the right layer
+    if x == last {
+        let color = state.sticker(R, cubelet).to_rgb();
         add_face(
             vertices,
             indices,
@@ -400,96 +395,96 @@ fn generate_cubelet(
 
     // Add black edges for internal faces
     // Internal +Y
-    if y != 1 {
+    if y != last {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx - half, cy + half + GAP, cz + half],
-                p1: [cx + half, cy + half + GAP, cz + half],
-                p2: [cx + half, cy + half + GAP, cz - half],
-                p3: [cx - half, cy + half + GAP, cz - half],
+                p0: [cx - half, cy + half + gap, cz + half],
+                p1: [cx + half, cy + half + gap, cz + half],
+                p2: [cx + half, cy + half + gap, cz - half],
+                p3: [cx - half, cy + half + gap, cz - half],
                 normal: [0.0, 1.0, 0.0],
                 color: BLACK,
             },
         );
     }
     // Internal -Y
-    if y != -1 {
+    if y != 0 {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx - half, cy - half - GAP, cz - half],
-                p1: [cx + half, cy - half - GAP, cz - half],
-                p2: [cx + half, cy - half - GAP, cz + half],
-                p3: [cx - half, cy - half - GAP, cz + half],
+                p0: [cx - half, cy - half - gap, cz - half],
+                p1: [cx + half, cy - half - gap, cz - half],
+                p2: [cx + half, cy - half - gap, cz + half],
+                p3: [cx - half, cy - half - gap, cz + half],
                 normal: [0.0, -1.0, 0.0],
                 color: BLACK,
             },
         );
     }
     // Internal +Z
-    if z != 1 {
+    if z != last {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx - half, cy - half, cz + half + GAP],
-                p1: [cx + half, cy - half, cz + half + GAP],
-                p2: [cx + half, cy + half, cz + half + GAP],
-                p3: [cx - half, cy + half, cz + half + GAP],
+                p0: [cx - half, cy - half, cz + half + gap],
+                p1: [cx + half, cy - half, cz + half + gap],
+                p2: [cx + half, cy + half, cz + half + gap],
+                p3: [cx - half, cy + half, cz + half + gap],
                 normal: [0.0, 0.0, 1.0],
                 color: BLACK,
             },
         );
     }
     // Internal -Z
-    if z != -1 {
+    if z != 0 {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx + half, cy - half, cz - half - GAP],
-                p1: [cx - half, cy - half, cz - half - GAP],
-                p2: [cx - half, cy + half, cz - half - GAP],
-                p3: [cx + half, cy + half, cz - half - GAP],
+                p0: [cx + half, cy - half, cz - half - gap],
+                p1: [cx - half, cy - half, cz - half - gap],
+                p2: [cx - half, cy + half, cz - half - gap],
+                p3: [cx + half, cy + half, cz - half - gap],
                 normal: [0.0, 0.0, -1.0],
                 color: BLACK,
             },
         );
     }
     // Internal -X
-    if x != -1 {
+    if x != 0 {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx - half - GAP, cy - half, cz - half],
-                p1: [cx - half - GAP, cy - half, cz + half],
-                p2: [cx - half - GAP, cy + half, cz + half],
-                p3: [cx - half - GAP, cy + half, cz - half],
+                p0: [cx - half - gap, cy - half, cz - half],
+                p1: [cx - half - gap, cy - half, cz + half],
+                p2: [cx - half - gap, cy + half, cz + half],
+                p3: [cx - half - gap, cy + half, cz - half],
                 normal: [-1.0, 0.0, 0.0],
                 color: BLACK,
             },
         );
     }
     // Internal +X
-    if x != 1 {
+    if x != last {
         add_face(
             vertices,
             indices,
             &mut current_index,
             FaceData {
-                p0: [cx + half + GAP, cy - half, cz + half],
-                p1: [cx + half + GAP, cy - half, cz - half],
-                p2: [cx + half + GAP, cy + half, cz - half],
-                p3: [cx + half + GAP, cy + half, cz + half],
+                p0: [cx + half + gap, cy - half, cz + half],
+                p1: [cx + half + gap, cy - half, cz - half],
+                p2: [cx + half + gap, cy + half, cz - half],
+                p3: [cx + half + gap, cy + half, cz + half],
                 normal: [1.0, 0.0, 0.0],
                 color: BLACK,
             },
@@ -497,54 +492,6 @@ fn generate_cubelet(
     }
 }
 
-fn get_sticker_color(state: &CubeState, face: usize, a: i32, b: i32) -> [f32; 3] {
-    // Convert cubelet coordinates to sticker index
-    // a and b are in range [-1, 1], need to map to [0, 2] for sticker index
-    let sticker_index = match face {
-        U => {
-            // U face: a=x, b=z, looking down at it
-            // x: -1->0, 0->1, 1->2 (left to right)
-            // z: 1->0, 0->1, -1->2 (front to back becomes top to bottom)
-            let col = (a + 1) as usize;
-            let row = (1 - b) as usize;
-            row * 3 + col
-        }
-        D => {
-            // D face: a=x, b=z, looking up at it
-            let col = (a + 1) as usize;
-            let row = (b + 1) as usize;
-            row * 3 + col
-        }
-        F => {
-            // F face: a=x, b=y
-            let col = (a + 1) as usize;
-            let row = (1 - b) as usize;
-            row * 3 + col
-        }
-        B => {
-            // B face: a=x, b=y (mirrored horizontally)
-            let col = (1 - a) as usize;
-            let row = (1 - b) as usize;
-            row * 3 + col
-        }
-        L => {
-            // L face: a=y, b=z
-            let col = (b + 1) as usize;
-            let row = (1 - a) as usize;
-            row * 3 + col
-        }
-        R => {
-            // R face: a=y, b=z (mirrored)
-            let col = (1 - b) as usize;
-            let row = (1 - a) as usize;
-            row * 3 + col
-        }
-        _ => 0,
-    };
-
-    state.faces[face].stickers[sticker_index].to_rgb()
-}
-
 struct FaceData {
     p0: [f32; 3],
     p1: [f32; 3],
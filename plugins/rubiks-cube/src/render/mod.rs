@@ -6,5 +6,5 @@ mod webgl;
 
 pub use camera::Camera;
 pub use geometry::{CubeMesh, SplitMesh};
-pub use picking::RayPicker;
+pub use picking::{FaceHit, RayPicker};
 pub use webgl::Renderer;
@@ -1,21 +1,27 @@
 use crate::cube::{B, D, F, L, R, U};
 use crate::math::Vec3;
 use crate::render::Camera;
+use crate::render::geometry::HALF_SIZE;
 
 pub struct FaceHit {
     pub face: usize,
+    /// Where the ray meets the face
+    pub point: Vec3,
+    /// Layers along x, y and z of the cubelet the sticker is on
+    pub cubelet: [usize; 3],
 }
 
 pub struct RayPicker;
 
 impl RayPicker {
-    /// Pick which face of the cube was clicked
+    /// Pick which sticker of a cube of `size` layers was clicked
     pub fn pick(
         camera: &Camera,
         mouse_x: f32,
         mouse_y: f32,
         canvas_width: f32,
         canvas_height: f32,
+        size: usize,
     ) -> Option<FaceHit> {
         // Convert mouse coordinates to normalized device coordinates
         let ndc_x = (2.0 * mouse_x) / canvas_width - 1.0;
@@ -27,23 +33,40 @@ impl RayPicker {
 
         // Test intersection with each face plane
         let faces = [
-            (U, Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
-            (D, Vec3::new(0.0, -1.5, 0.0), Vec3::new(0.0, -1.0, 0.0)),
-            (F, Vec3::new(0.0, 0.0, 1.5), Vec3::new(0.0, 0.0, 1.0)),
-            (B, Vec3::new(0.0, 0.0, -1.5), Vec3::new(0.0, 0.0, -1.0)),
-            (L, Vec3::new(-1.5, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
-            (R, Vec3::new(1.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            (U, Vec3::new(0.0, HALF_SIZE, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (
+                D,
+                Vec3::new(0.0, -HALF_SIZE, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+            ),
+            (F, Vec3::new(0.0, 0.0, HALF_SIZE), Vec3::new(0.0, 0.0, 1.0)),
+            (
+                B,
+                Vec3::new(0.0, 0.0, -HALF_SIZE),
+                Vec3::new(0.0, 0.0, -1.0),
+            ),
+            (
+                L,
+                Vec3::new(-HALF_SIZE, 0.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+            ),
+            (R, Vec3::new(HALF_SIZE, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
         ];
 
         let mut closest: Option<(f32, FaceHit)> = None;
 
         for (face_idx, plane_point, plane_normal) in faces {
-            if let Some((t, _)) =
+            if let Some((t, p)) =
                 ray_plane_intersection(&cam_pos, &ray_dir, &plane_point, &plane_normal)
                     .filter(|(_, p)| is_within_face_bounds(face_idx, p))
                     .filter(|(t, _)| closest.is_none() || *t < closest.as_ref().unwrap().0)
             {
-                closest = Some((t, FaceHit { face: face_idx }));
+                let hit = FaceHit {
+                    face: face_idx,
+                    point: p,
+                    cubelet: cubelet_at(&p, size),
+                };
+                closest = Some((t, hit));
             }
         }
 
@@ -96,8 +119,17 @@ fn ray_plane_intersection(
     Some((t, hit_point))
 }
 
+/// The cubelet of a point on the surface of a cube of `size` layers
+fn cubelet_at(point: &Vec3, size: usize) -> [usize; 3] {
+    let layer = |coordinate: f32| {
+        let layer = ((coordinate + HALF_SIZE) / (2.0 * HALF_SIZE) * size as f32).floor();
+        (layer.max(0.0) as usize).min(size - 1)
+    };
+    [layer(point.x), layer(point.y), layer(point.z)]
+}
+
 fn is_within_face_bounds(face: usize, point: &Vec3) -> bool {
-    let bound = 1.5;
+    let bound = HALF_SIZE;
     let (a, b) = match face {
         U | D => (point.x, point.z),
         F | B => (point.x, point.y),
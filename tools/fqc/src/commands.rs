@@ -1,8 +1,11 @@
 mod bootstrap;
+mod import;
 
 use bootstrap::Bootstrap;
+use import::Import;
 
 #[derive(clap::Subcommand, Debug)]
 pub enum Commands {
     Bootstrap(Bootstrap),
+    Import(Import),
 }
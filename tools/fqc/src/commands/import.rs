@@ -0,0 +1,74 @@
+use flashqc_backend::auth::get_user_by_username;
+use flashqc_backend::import::anki::{self, AnkiPackage};
+use flashqc_backend::plugin::PluginRegistry;
+use flashqc_backend::utils::db;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Args)]
+pub struct Import {
+    #[command(subcommand)]
+    source: ImportSource,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum ImportSource {
+    /// Import an Anki .apkg package as a new private deck
+    Anki(AnkiImport),
+}
+
+#[derive(Debug, clap::Args)]
+struct AnkiImport {
+    file: PathBuf,
+    /// Owner of the imported deck
+    #[arg(long)]
+    user: String,
+    /// Name of the deck, defaults to the name of the main Anki deck
+    #[arg(long)]
+    name: Option<String>,
+    #[arg(long, default_value = "plugins")]
+    plugins_dir: PathBuf,
+}
+
+impl Import {
+    pub fn run(self) -> Result<(), String> {
+        match self.source {
+            ImportSource::Anki(cmd) => cmd.run(),
+        }
+    }
+}
+
+impl AnkiImport {
+    fn run(self) -> Result<(), String> {
+        let bytes = fs::read(&self.file)
+            .map_err(|e| format!("Cannot read {}: {e}", self.file.display()))?;
+        let package = AnkiPackage::read(&bytes).map_err(|e| e.to_string())?;
+
+        let mut db_connection = db::establish_connection()?;
+        let user = get_user_by_username(&mut db_connection, &self.user)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("User '{}' does not exist", self.user))?;
+        let registry = PluginRegistry::load(&self.plugins_dir, &mut db_connection)?;
+
+        let report = anki::import_package(
+            &mut db_connection,
+            &registry,
+            user.id,
+            package,
+            self.name.as_deref(),
+        )
+        .map_err(|e| e.to_string())?;
+
+        println!(
+            "Imported {} cards and {} media files into deck '{}' (id {})",
+            report.imported_cards, report.imported_assets, report.deck.name, report.deck.id
+        );
+        for note in &report.skipped {
+            println!(
+                "Skipped note {} ({}): {}",
+                note.note_id, note.note_type, note.reason
+            );
+        }
+        Ok(())
+    }
+}
@@ -27,6 +27,7 @@ impl Cli {
     pub fn run(self) -> Result<(), String> {
         match self.command {
             Commands::Bootstrap(cmd) => cmd.run(),
+            Commands::Import(cmd) => cmd.run(),
         }
     }
 }